@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Build script: records the git commit the daemon was built from, for --version.
+// Release tarballs have no .git, so the hash is optional.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+	// Short hash of HEAD, with -dirty for uncommitted changes (None outside git)
+	let hash = Command::new("git")
+		.args(["describe", "--always", "--dirty", "--abbrev=12", "--match=NOMATCH"])
+		.output()
+		.ok()
+		.filter(|o| o.status.success())
+		.and_then(|o| String::from_utf8(o.stdout).ok())
+		.map(|s| s.trim().to_string())
+		.filter(|s| !s.is_empty());
+	
+	let out = Path::new(&env::var("OUT_DIR").unwrap()).join("build_info.rs");
+	fs::write(&out, format!("const GIT_HASH: Option<&str> = {:?};\n", hash)).unwrap();
+	
+	// Rebuild when HEAD moves (checkout) or the branch it names advances
+	// (commit); only existing files, or cargo would rerun us every build
+	for f in [".git/HEAD", ".git/index"] {
+		if Path::new(f).exists() {
+			println!("cargo:rerun-if-changed={}", f);
+		}
+	}
+	if let Ok(head) = fs::read_to_string(".git/HEAD")
+		&& let Some(r) = head.trim().strip_prefix("ref: ")
+		&& Path::new(".git").join(r).exists() {
+		println!("cargo:rerun-if-changed=.git/{}", r);
+	}
+}
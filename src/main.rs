@@ -9,6 +9,8 @@ use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Write};
 use std::os::unix::io::RawFd;  // Raw file descriptor type for Unix systems
 use std::process;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
 
 // ============================================================================
 // CONSTANTS: Default configuration values
@@ -18,11 +20,50 @@ use std::process;
 // This sysfs file accepts "0" (off) or "1" (on) to control LED state
 const DEFAULT_LED_PATH: &str = "/sys/class/leds/tpacpi::power/brightness";
 
-// Default path to NVMe disk statistics file
-// This file contains space-separated counters for I/O operations
+// LED class directory, searched when the default LED doesn't exist
+// Names containing these words are preferred, best first
+const LEDS_ROOT: &str = "/sys/class/leds";
+const LED_KEYWORDS: [&str; 3] = ["activity", "disk", "power"];
+
+// Where block devices live in sysfs; each has a `stat` file of
+// space-separated counters for I/O operations
 // Format: reads completed, reads merged, sectors read, time reading (ms),
 //		   writes completed, writes merged, sectors written, time writing (ms), ...
-const DEFAULT_NVME_STAT_PATH: &str = "/sys/block/nvme0n1/stat";
+// With no nvme_path configured, an NVMe namespace here is picked automatically
+const SYS_BLOCK: &str = "/sys/block";
+
+// Scratch buffer for one stat file read
+// A full 17-field line is well under this; a longer file (custom source,
+// huge counters) is read to the end instead of being cut mid-number
+const STAT_BUF_LEN: usize = 512;
+
+// Kernel names of the stat fields, in stat file order (Documentation/block/stat.rst)
+// Header-described sources are mapped back to this order for --record captures
+const STAT_FIELD_NAMES: [&str; 17] = [
+	"r_ios", "r_merges", "r_sectors", "r_ticks", "w_ios", "w_merges", "w_sectors", "w_ticks",
+	"in_flight", "io_ticks", "time_in_queue", "d_ios", "d_merges", "d_sectors", "d_ticks",
+	"f_ios", "f_ticks",
+];
+
+// Size of the "sectors" the stat counters count, in bytes
+// Always 512, whatever the device's logical_block_size: the block layer
+// converts before counting (Documentation/block/stat.rst), so a 4Kn
+// namespace reports 8 sectors per block and scaling by its block size
+// would overstate throughput 8x
+const SECTOR_BYTES: u64 = 512;
+
+// Offsets of the fields the daemon reads, into STAT_FIELD_NAMES order
+// A line needs at least the read and write fields to be usable; the
+// later ones (in_flight, discards, flushes) depend on the kernel version
+const STAT_R_IOS: usize = 0;
+const STAT_R_SECTORS: usize = 2;
+const STAT_W_IOS: usize = 4;
+const STAT_W_SECTORS: usize = 6;
+const STAT_IN_FLIGHT: usize = 8;
+const STAT_IO_TICKS: usize = 9;
+const STAT_D_IOS: usize = 11;
+const STAT_D_SECTORS: usize = 13;
+const STAT_MIN_FIELDS: usize = STAT_W_SECTORS + 1;
 
 // How often to poll the NVMe stat file for changes (in milliseconds)
 // Lower values = more responsive but higher CPU usage
@@ -33,10 +74,146 @@ const DEFAULT_POLL_INTERVAL_MS: u64 = 10;
 // This creates a visible "blink" effect even for very brief I/O operations
 const DEFAULT_BLINK_ON_MS: u64 = 10;
 
+// How often to re-discover md array members when aggregating them
+// Membership changes (resync, spare activation) are rare, so once a
+// second keeps directory scans off the hot poll path
+const MEMBER_RESCAN_MS: u64 = 1000;
+
+// Upper bound on how many devices are monitored at once
+// Each device costs an open/read per poll, so a runaway discovery (huge
+// md array, bad pattern) shouldn't be able to exhaust fds or CPU
+const DEFAULT_MAX_DEVICES: usize = 64;
+
+// Default blink pattern for mount/unmount events (ms, alternating on/off)
+// Three quick flashes: distinct from ordinary activity blinks
+const DEFAULT_MOUNT_PATTERN: &str = "60,60,60,60,60";
+
+// Minimum spacing between mount-change patterns (in milliseconds)
+// Mount storms (e.g. container start-up) would otherwise strobe the LED
+const DEFAULT_MOUNT_THROTTLE_MS: u64 = 1000;
+
+// Sleep shorter than this between two polls doesn't count as a resume
+// (in milliseconds); CLOCK_BOOTTIME and CLOCK_MONOTONIC agree otherwise
+const RESUME_GAP_MS: u64 = 1000;
+
+// resume_blink: two blinks, slower than activity ever looks (on, off, on ms)
+const RESUME_PATTERN: [u64; 3] = [150, 150, 150];
+
+// Length of the idle heartbeat pulse (in milliseconds)
+// Long enough to see, short enough not to pass for activity
+const HEARTBEAT_BLINK_MS: u64 = 50;
+
+// Root of the SCSI enclosure (SES) class in sysfs
+// Each enclosure has one directory per component (slot) with LED attributes
+const ENCLOSURE_ROOT: &str = "/sys/class/enclosure";
+
+// Idle gap that re-arms the edge-only blink (in milliseconds)
+// Activity resuming after at least this much quiet counts as "woke up"
+const DEFAULT_IDLE_MS: u64 = 2000;
+
+// How often the metrics file is rewritten (in milliseconds)
+// Textfile collectors typically scrape every 15s or more; no point going faster
+const DEFAULT_METRICS_INTERVAL_MS: u64 = 10000;
+
+// Throughput history: one-second buckets, enough for the 5-minute average
+// Fixed whatever poll_ms is, so a 10ms poll doesn't cost 30000 entries
+const THROUGHPUT_WINDOW_S: usize = 300;
+
+// How often buffered --csv rows are flushed to the file (in milliseconds)
+// Batches many polls into one write; at most this much is lost on a crash
+const CSV_FLUSH_MS: u64 = 5000;
+
+// Strobe half-period for panic_led=strobe (in milliseconds)
+// Fast enough that it can't be mistaken for disk activity
+const PANIC_STROBE_MS: u64 = 50;
+
+// How much of the block device --probe-io reads, and in what chunks
+// Small enough to be harmless, spread out so readahead can't hide it
+const PROBE_READ_BYTES: usize = 4096;
+const PROBE_READS: u64 = 8;
+const PROBE_STRIDE: u64 = 1 << 20;
+
+// Largest kernel uevent message (the kernel's UEVENT_BUFFER_SIZE)
+const UEVENT_BUF_LEN: usize = 2048;
+
+// --monitor: fastest screen refresh, and how many refreshes the sparkline
+// spans. Redrawing at a 10ms poll rate would only flicker
+const MONITOR_MIN_MS: u64 = 250;
+const MONITOR_HISTORY: usize = 60;
+
+// --test-led: on/off blinks, then (dimmable LEDs) a brightness sweep
+// Slow enough to count by eye
+const TEST_BLINK_MS: u64 = 200;
+const TEST_BLINKS: u32 = 3;
+const TEST_SWEEP_STEPS: u32 = 10;
+
+// startup_blink: three quick blinks (on/off LEDs) or a ramp up and down
+// (dimmable ones), short enough not to hold up startup
+const STARTUP_BLINK_MS: u64 = 80;
+const STARTUP_BLINKS: u32 = 3;
+const STARTUP_RAMP_STEPS: u32 = 20;
+const STARTUP_RAMP_STEP_MS: u64 = 15;
+
+// Default RGB colors for activity (#rrggbb): green reads, red writes
+const DEFAULT_READ_COLOR: &str = "#00ff00";
+const DEFAULT_WRITE_COLOR: &str = "#ff0000";
+
+// Software PWM defaults: frequency, and the floor below which it flickers
+const DEFAULT_PWM_FREQ_HZ: u64 = 200;
+const MIN_PWM_FREQ_HZ: u64 = 100;
+const DEFAULT_PWM_DUTY: u64 = 30;
+
+// Step length of a fade-out (in milliseconds)
+// 100 steps per second is smooth to the eye and cheap in wakeups
+const FADE_STEP_MS: u64 = 10;
+
+// Kernel LED trigger used by --kernel-trigger (ledtrig-disk)
+const DEFAULT_KERNEL_TRIGGER: &str = "disk-activity";
+
+// How long a control socket client gets to send its command line(s)
+// Connections are served inline by the event loop, so keep this short
+const CONTROL_TIMEOUT_MS: u64 = 100;
+
+// How long a metrics_addr client gets to send its HTTP request
+// Served inline by the event loop like the control socket
+const METRICS_HTTP_TIMEOUT_MS: u64 = 100;
+
+// Settings the control socket's `set` command may change at runtime
+// (only ones read afresh on every blink; others need a reload)
+const CONTROL_SET_KEYS: [&str; 8] = ["blink_ms", "read_blink_ms", "write_blink_ms", "discard_blink_ms", "min_on_ms", "min_off_ms", "idle_ms", "edge_only"];
+
+// Where syslog listens (log=syslog), and the facility messages are filed under
+const SYSLOG_PATH: &str = "/dev/log";
+const SYSLOG_FACILITY_DAEMON: u8 = 3;
+
+// journald's native protocol socket (log=journald)
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+// All block devices' counters in one file (source=diskstats)
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+
+// Adaptive polling defaults: how much to stretch the interval, and after
+// how many consecutive idle polls
+const DEFAULT_BACKOFF_FACTOR: f64 = 2.0;
+const DEFAULT_BACKOFF_AFTER: u64 = 10;
+
+// How often to retry an LED or stat file that went away: start quickly,
+// then double up to the cap while it stays missing
+const REOPEN_BACKOFF_MIN_MS: u64 = 100;
+const REOPEN_BACKOFF_MAX_MS: u64 = 5000;
+
 // Path to optional configuration file
 // If present, settings are loaded from here before applying CLI overrides
 const DEFAULT_CONFIG_PATH: &str = "/etc/nvme-led-daemon.conf";
 
+// Git commit of this build (GIT_HASH), written by build.rs; None when
+// built outside a git checkout (e.g. from a release tarball)
+include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+
+// Prefix of environment variables that override config file keys
+// NVME_LED_BLINK_MS sets blink_ms, NVME_LED_ON_FIELDS sets on_fields, ...
+const ENV_PREFIX: &str = "NVME_LED_";
+
 // ============================================================================
 // ENUMS: Type definitions for configuration options
 // ============================================================================
@@ -45,7 +222,9 @@ const DEFAULT_CONFIG_PATH: &str = "/etc/nvme-led-daemon.conf";
 /// The stat file contains multiple counters; we can track either:
 /// - I/O operation counts (how many read/write operations)
 /// - Sector counts (how much data transferred in 512-byte sectors)
-#[derive(Copy, Clone, Debug)]
+/// - I/Os currently in flight (how deep the queue is right now)
+/// - Time spent doing I/O (what percentage of the time the disk is busy)
+#[derive(Copy, Clone, Debug, PartialEq)]
 enum NvmeMode {
 	/// Monitor sectors read/written (fields 2 and 6 in stat file)
 	/// Better for detecting large sequential transfers
@@ -53,24 +232,156 @@ enum NvmeMode {
 	
 	/// Monitor I/O operations count (fields 0 and 4 in stat file)
 	/// Better for detecting small random I/O patterns
-	Io
+	Io,
+	
+	/// Monitor I/Os currently in progress (field 8 in stat file)
+	/// The LED is lit while anything is queued, rather than blinking on
+	/// completions; a truer "busy right now" indicator
+	InFlight,
+	
+	/// Monitor time spent doing I/O (field 9, io_ticks) as a utilization
+	/// percentage; dimmable LEDs glow brighter the busier the disk is
+	Busy
+}
+
+/// Where device counters come from
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Source {
+	/// One stat file per device (nvme_path), e.g. /sys/block/nvme0n1/stat
+	Stat,
+	
+	/// The device's line in /proc/diskstats (device=NAME)
+	DiskStats
+}
+
+/// How counters are located in the stat source
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum StatFormat {
+	/// Fixed field positions, as in /sys/block/*/stat (the kernel format)
+	Indexed,
+	
+	/// A header line names the columns (r_ios, w_sectors, ...) and the
+	/// next line holds the values; for custom or wrapped activity files
+	Named
+}
+
+/// Shape of a fade-out
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FadeCurve {
+	/// Brightness drops by the same amount each step
+	Linear,
+	
+	/// Brightness drops fast at first, then tails off (closer to how
+	/// incandescent lamps and the eye behave)
+	Exp
+}
+
+/// What to do when some of several LEDs fail to open
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum LedOpenPolicy {
+	/// Any failure is fatal
+	Strict,
+	
+	/// Carry on with the LEDs that opened; a failed per-direction LED's
+	/// blinks go to the main LED instead
+	BestEffort
+}
+
+/// What to leave the LED showing after a clean shutdown (SIGTERM/SIGINT)
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ExitLedState {
+	/// Off (the usual "no activity" look)
+	Off,
+	
+	/// On
+	On,
+	
+	/// Whatever brightness it had before we started
+	Restore
+}
+
+/// What to leave the LED showing when the daemon dies on a fatal error
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PanicLed {
+	/// Leave the LED as it was
+	None,
+	
+	/// Solid on
+	On,
+	
+	/// Fast blink driven by the kernel's timer trigger (outlives us)
+	Strobe
 }
 
-/// Direction of disk activity (read or write)
+/// Direction of disk activity (read, write or discard)
 /// Used to determine which blink duration to apply and for filtering
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum Dir { 
 	Read,	// Data being read from disk
-	Write	// Data being written to disk
+	Write,	// Data being written to disk
+	Discard	// Blocks being discarded (TRIM)
+}
+
+/// Log severity, ordered from most to least important
+/// A message is printed when its level is at or above the configured
+/// threshold (i.e. `level as u8 <= threshold as u8`)
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+enum Level {
+	Error,	// Fatal or unrecoverable problems (always shown)
+	Warn,	// Recoverable problems worth knowing about
+	Info,	// Startup banner and one-off status lines (default)
+	Debug	// Per-event traces (--verbose)
+}
+
+impl Level {
+	/// Parse a `log_level` value (error|warn|info|debug)
+	fn parse(s: &str) -> Option<Level> {
+		match s {
+			"error" => Some(Level::Error),
+			"warn" => Some(Level::Warn),
+			"info" => Some(Level::Info),
+			"debug" => Some(Level::Debug),
+			_ => None,
+		}
+	}
+	
+	/// The name `parse` accepts
+	fn name(self) -> &'static str {
+		match self {
+			Level::Error => "error",
+			Level::Warn => "warn",
+			Level::Info => "info",
+			Level::Debug => "debug",
+		}
+	}
+}
+
+/// Where log messages go
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum LogTarget {
+	Stdout,	  // stdout/stderr (what systemd's journal picks up)
+	Syslog,	  // RFC 3164 datagrams to /dev/log
+	Journald  // journald native protocol, with structured fields
 }
 
 /// Which types of operations should trigger the LED
-/// Allows filtering to only show reads, only writes, or both
-#[derive(Copy, Clone, Debug)]
+/// Allows filtering to only show reads, only writes, both, discards, or all
+#[derive(Copy, Clone, Debug, PartialEq)]
 enum FieldsSel {
-	Reads,	 // Only read operations trigger LED
-	Writes,  // Only write operations trigger LED
-	Both	 // Both read and write operations trigger LED
+	Reads,	  // Only read operations trigger LED
+	Writes,   // Only write operations trigger LED
+	Both,	  // Both read and write operations trigger LED
+	Discards, // Only discard (TRIM) operations trigger LED
+	All		  // Reads, writes and discards all trigger LED
+}
+
+/// Which direction a poll is reported as when reads and writes both moved
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum MixedPriority {
+	Read,	  // Reads win
+	Write,	  // Writes win (the long-standing behavior)
+	Longer,	  // The direction with the longer blink (writes on a tie)
+	Shorter	  // The direction with the shorter blink (writes on a tie)
 }
 
 // ============================================================================
@@ -85,6 +396,161 @@ fn ns_from_ms(ms: u64) -> i64 {
 	(ms as i64) * 1_000_000  // 1 millisecond = 1,000,000 nanoseconds
 }
 
+/// Next multiple of `interval_ns` strictly after `now_ns`
+/// Used to place aligned poll ticks on round clock boundaries
+#[inline]
+fn next_aligned_ns(now_ns: u64, interval_ns: u64) -> u64 {
+	let interval_ns = interval_ns.max(1);
+	(now_ns / interval_ns + 1) * interval_ns
+}
+
+/// Current time of `clock` in milliseconds
+/// CLOCK_BOOTTIME keeps counting through suspend, CLOCK_MONOTONIC doesn't
+fn clock_ms(clock: libc::clockid_t) -> u64 {
+	let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+	unsafe { libc::clock_gettime(clock, &mut now) };
+	now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000
+}
+
+/// How long the system slept between two (boottime, monotonic) readings
+#[inline]
+fn slept_ms(before: (u64, u64), now: (u64, u64)) -> u64 {
+	now.0.saturating_sub(before.0).saturating_sub(now.1.saturating_sub(before.1))
+}
+
+// ============================================================================
+// LOGGING: Level-filtered output through one helper
+// ============================================================================
+
+// Current log threshold, set once from Config in main()
+// A global keeps log! usable from any struct without threading Config through
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Log a formatted message if `$lvl` passes the configured threshold
+/// The level check happens before formatting, so disabled debug traces
+/// in the poll path cost a single atomic load.
+/// 
+/// `log!(lvl, fields: &[("KEY", value)], ...)` attaches structured fields
+/// for journald (ignored by the other targets); they're only built if the
+/// message is emitted.
+macro_rules! log {
+	($lvl:expr, fields: $fields:expr, $($arg:tt)*) => {
+		if ($lvl as u8) <= LOG_LEVEL.load(Ordering::Relaxed) {
+			emit_log($lvl, $fields, format_args!($($arg)*));
+		}
+	};
+	($lvl:expr, $($arg:tt)*) => {
+		if ($lvl as u8) <= LOG_LEVEL.load(Ordering::Relaxed) {
+			emit_log($lvl, &[], format_args!($($arg)*));
+		}
+	};
+}
+
+// Socket for syslog/journald (unbound: each message is sent to the
+// target's path, so a restarted log daemon is picked up) and the target
+static LOG_SOCKET: std::sync::OnceLock<std::os::unix::net::UnixDatagram> = std::sync::OnceLock::new();
+static LOG_TARGET: AtomicU8 = AtomicU8::new(LogTarget::Stdout as u8);
+
+/// Switch log output between stdout/stderr, syslog and journald
+fn set_log_target(target: LogTarget) -> io::Result<()> {
+	let path = match target {
+		LogTarget::Stdout => None,
+		LogTarget::Syslog => Some(SYSLOG_PATH),
+		LogTarget::Journald => Some(JOURNAL_SOCKET),
+	};
+	if let Some(path) = path {
+		// Fail now rather than on every message
+		use std::os::unix::fs::FileTypeExt;
+		if !std::fs::metadata(path)?.file_type().is_socket() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a socket"));
+		}
+		if LOG_SOCKET.get().is_none() {
+			let _ = LOG_SOCKET.set(std::os::unix::net::UnixDatagram::unbound()?);
+		}
+	}
+	LOG_TARGET.store(target as u8, Ordering::Relaxed);
+	Ok(())
+}
+
+/// Syslog severity of a level (also journald's PRIORITY)
+fn syslog_severity(level: Level) -> u8 {
+	match level {
+		Level::Error => 3,
+		Level::Warn => 4,
+		Level::Info => 6,
+		Level::Debug => 7,
+	}
+}
+
+/// Send one message to syslog as `<PRI>Mmm dd hh:mm:ss tag[pid]: msg`
+/// (RFC 3164, daemon facility; the local daemon adds the hostname)
+fn syslog_send(sock: &std::os::unix::net::UnixDatagram, level: Level, args: std::fmt::Arguments) -> io::Result<()> {
+	// Local time, in the fixed-width format the RFC asks for
+	let mut stamp = [0u8; 32];
+	let len = unsafe {
+		let now = libc::time(std::ptr::null_mut());
+		let mut tm: libc::tm = std::mem::zeroed();
+		libc::localtime_r(&now, &mut tm);
+		libc::strftime(stamp.as_mut_ptr().cast(), stamp.len(), c"%b %e %H:%M:%S".as_ptr(), &tm)
+	};
+	let msg = format!("<{}>{} nvme-led-daemon[{}]: {}", SYSLOG_FACILITY_DAEMON * 8 + syslog_severity(level),
+		String::from_utf8_lossy(&stamp[..len]), process::id(), args);
+	sock.send_to(msg.as_bytes(), SYSLOG_PATH).map(|_| ())
+}
+
+/// Append one field to a journald native protocol datagram
+/// 
+/// Plain values are `KEY=value\n`; a value containing a newline is sent
+/// as `KEY\n`, its length as a little-endian u64, the bytes, then `\n`.
+fn journal_field(out: &mut Vec<u8>, key: &str, value: &str) {
+	out.extend_from_slice(key.as_bytes());
+	if value.contains('\n') {
+		out.push(b'\n');
+		out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+	} else {
+		out.push(b'=');
+	}
+	out.extend_from_slice(value.as_bytes());
+	out.push(b'\n');
+}
+
+/// Send one message to journald in its native protocol, with the
+/// caller's structured fields (KEY, value) alongside MESSAGE
+fn journal_send(sock: &std::os::unix::net::UnixDatagram, level: Level, fields: &[(&str, String)], args: std::fmt::Arguments) -> io::Result<()> {
+	let mut out = Vec::with_capacity(256);
+	journal_field(&mut out, "MESSAGE", &args.to_string());
+	journal_field(&mut out, "PRIORITY", &syslog_severity(level).to_string());
+	journal_field(&mut out, "SYSLOG_IDENTIFIER", "nvme-led-daemon");
+	for (k, v) in fields {
+		journal_field(&mut out, k, v);
+	}
+	sock.send_to(&out, JOURNAL_SOCKET).map(|_| ())
+}
+
+/// Write one log line: errors and warnings go to stderr, the rest to stdout
+/// (or all of them to syslog/journald, falling back to stderr if it's
+/// unreachable). Structured `fields` only reach journald.
+fn emit_log(level: Level, fields: &[(&str, String)], args: std::fmt::Arguments) {
+	let target = LOG_TARGET.load(Ordering::Relaxed);
+	if target != LogTarget::Stdout as u8
+		&& let Some(sock) = LOG_SOCKET.get() {
+		let sent = if target == LogTarget::Journald as u8 {
+			journal_send(sock, level, fields, args)
+		} else {
+			syslog_send(sock, level, args)
+		};
+		if sent.is_err() {
+			eprintln!("nvme-led-daemon: (log socket unreachable) {}", args);
+		}
+		return;
+	}
+	match level {
+		Level::Error => eprintln!("nvme-led-daemon: error: {}", args),
+		Level::Warn => eprintln!("nvme-led-daemon: warning: {}", args),
+		Level::Info | Level::Debug => println!("nvme-led-daemon: {}", args),
+	}
+}
+
 // ============================================================================
 // EPOLL WRAPPER: Efficient event monitoring
 // ============================================================================
@@ -118,11 +584,11 @@ impl Epoll {
 	/// 
 	/// # Arguments
 	/// * `fd` - The file descriptor to monitor (in our case, timerfd)
-	/// * `data_u64` - User data to identify which fd triggered (our "tag")
-	///				   This value is returned in events, letting us distinguish
-	///				   between the poll timer and off timer
-	/// * `events` - Bitmask of events to monitor (e.g., EPOLLIN for readable)
-	///				 Timerfds become readable when they expire
+	/// * `data_u64` - User data to identify which fd triggered (our "tag").
+	///   This value is returned in events, letting us distinguish
+	///   between the poll timer and off timer
+	/// * `events` - Bitmask of events to monitor (e.g., EPOLLIN for readable).
+	///   Timerfds become readable when they expire
 	fn add_fd(&self, fd: RawFd, data_u64: u64, events: u32) -> io::Result<()> {
 		// Create epoll_event structure with our tag in the u64 field
 		let mut ev = libc::epoll_event { events, u64: data_u64 };
@@ -143,20 +609,32 @@ impl Epoll {
 	/// 
 	/// # Returns
 	/// Number of events that occurred (how many entries in events[] are valid)
+	/// A signal interrupting the wait (EINTR) is zero events, not an error:
+	/// the caller just loops and waits again.
 	fn wait(&self, events: &mut [libc::epoll_event]) -> io::Result<usize> {
-		// Call epoll_wait with timeout=-1 (block indefinitely until event)
+		// Block indefinitely until an event
 		// This is efficient: the process sleeps and kernel wakes it when timer fires
+		self.wait_timeout(events, -1)
+	}
+	
+	/// Like wait(), giving up after `timeout_ms` (-1 = never, 0 = don't block)
+	/// Zero events then means nothing was ready in time
+	fn wait_timeout(&self, events: &mut [libc::epoll_event], timeout_ms: i32) -> io::Result<usize> {
 		let n = unsafe { 
 			libc::epoll_wait(
 				self.fd,					// epoll instance
 				events.as_mut_ptr(),		// output buffer
 				events.len() as i32,		// buffer size
-				-1							// timeout (-1 = infinite)
+				timeout_ms					// timeout (-1 = infinite)
 			) 
 		};
 		
 		if n < 0 { 
-			return Err(io::Error::last_os_error()); 
+			let e = io::Error::last_os_error();
+			if e.kind() == io::ErrorKind::Interrupted {
+				return Ok(0);
+			}
+			return Err(e); 
 		}
 		Ok(n as usize)
 	}
@@ -190,13 +668,25 @@ impl Tfd {
 	/// 
 	/// The timer starts immediately (after 1ns) and then repeats at the
 	/// specified interval. This ensures we get the first poll quickly.
-	fn periodic(interval_ms: u64) -> io::Result<Self> {
-		// Create timerfd with CLOCK_MONOTONIC (not affected by system time changes)
+	/// 
+	/// With `align` set, the first expiration is instead an absolute time on
+	/// the next multiple of the interval (TFD_TIMER_ABSTIME), so every tick
+	/// lands on a round boundary of CLOCK_MONOTONIC. Other timers aligned the
+	/// same way then expire together and the kernel can batch the wakeups.
+	fn periodic(interval_ms: u64, align: bool) -> io::Result<Self> {
+		Self::periodic_on(libc::CLOCK_MONOTONIC, interval_ms, align)
+	}
+	
+	/// Like periodic(), on the given clock
+	/// CLOCK_MONOTONIC stands still during suspend; CLOCK_BOOTTIME doesn't, so
+	/// a timer on it that came due while asleep fires right after resume
+	fn periodic_on(clock: libc::clockid_t, interval_ms: u64, align: bool) -> io::Result<Self> {
+		// Create timerfd on `clock` (neither is affected by system time changes)
 		// TFD_NONBLOCK: reads won't block (we use epoll anyway)
 		// TFD_CLOEXEC: close on exec (good practice)
 		let fd = unsafe { 
 			libc::timerfd_create(
-				libc::CLOCK_MONOTONIC,						// clock type
+				clock,										// clock type
 				libc::TFD_NONBLOCK | libc::TFD_CLOEXEC		// flags
 			) 
 		};
@@ -221,9 +711,25 @@ impl Tfd {
 			},
 		};
 		
+		// Aligned start: replace the 1ns relative start with the next
+		// interval boundary as an absolute time on the timer's clock
+		let mut spec = spec;
+		let mut flags = 0;
+		if align {
+			let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+			unsafe { libc::clock_gettime(clock, &mut now) };
+			let now_ns = now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64;
+			let at = next_aligned_ns(now_ns, interval_ms * 1_000_000);
+			spec.it_value = libc::timespec {
+				tv_sec: (at / 1_000_000_000) as i64,
+				tv_nsec: (at % 1_000_000_000) as i64,
+			};
+			flags = libc::TFD_TIMER_ABSTIME;
+		}
+		
 		// Arm the timer with our specification
-		// flags=0 means it_value is relative time (not absolute)
-		if unsafe { libc::timerfd_settime(fd, 0, &spec, std::ptr::null_mut()) } < 0 {
+		// flags=0 means it_value is relative time; TFD_TIMER_ABSTIME absolute
+		if unsafe { libc::timerfd_settime(fd, flags, &spec, std::ptr::null_mut()) } < 0 {
 			let e = io::Error::last_os_error(); 
 			unsafe { libc::close(fd) };  // Clean up on error
 			return Err(e);
@@ -238,10 +744,15 @@ impl Tfd {
 	/// when we detect activity. This is more efficient than creating/destroying
 	/// the timer on each activity event.
 	fn oneshot() -> io::Result<Self> {
+		Self::oneshot_on(libc::CLOCK_MONOTONIC)
+	}
+	
+	/// Like oneshot(), on the given clock (see periodic_on)
+	fn oneshot_on(clock: libc::clockid_t) -> io::Result<Self> {
 		// Create timerfd with same flags as periodic timer
 		let fd = unsafe { 
 			libc::timerfd_create(
-				libc::CLOCK_MONOTONIC, 
+				clock, 
 				libc::TFD_NONBLOCK | libc::TFD_CLOEXEC
 			) 
 		};
@@ -285,23 +796,72 @@ impl Tfd {
 		Ok(())
 	}
 	
+	/// Arm the one-shot timer to fire after delay_us microseconds
+	/// Used where millisecond steps are too coarse (software PWM edges)
+	fn arm_after_us(&self, delay_us: u64) -> io::Result<()> {
+		let spec = libc::itimerspec {
+			it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+			it_value: libc::timespec { 
+				tv_sec: (delay_us / 1_000_000) as i64,
+				tv_nsec: ((delay_us % 1_000_000) * 1000) as i64
+			},
+		};
+		if unsafe { libc::timerfd_settime(self.0, 0, &spec, std::ptr::null_mut()) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(())
+	}
+	
+	/// (Re-)arm as a periodic timer: first expiry after `first_ms`, then
+	/// every `interval_ms`. Replaces any previous setting.
+	/// Used for timers that shouldn't fire immediately (e.g. summaries).
+	fn set_periodic(&self, interval_ms: u64, first_ms: u64) -> io::Result<()> {
+		let spec = libc::itimerspec {
+			it_interval: libc::timespec { 
+				tv_sec: (interval_ms / 1000) as i64,
+				tv_nsec: ns_from_ms(interval_ms % 1000)
+			},
+			it_value: libc::timespec { 
+				tv_sec: (first_ms / 1000) as i64,
+				tv_nsec: ns_from_ms(first_ms % 1000)
+			},
+		};
+		if unsafe { libc::timerfd_settime(self.0, 0, &spec, std::ptr::null_mut()) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(())
+	}
+	
 	/// Acknowledge timer expiration by reading from the fd
 	/// When a timerfd expires, it becomes readable. Reading from it:
 	/// 1. Clears the readable state (so epoll won't immediately trigger again)
 	/// 2. Returns a u64 with the number of expirations since last read
 	/// 
-	/// We don't care about the count (we just want to clear the state),
-	/// so we ignore the return value and any errors.
-	fn ack(&self, buf8: &mut [u8; 8]) { 
+	/// Returns the expiration count, or 0 if there was nothing to read
+	/// (the timer was re-armed or disarmed after epoll reported it). Most
+	/// callers only want to clear the state and ignore the count.
+	fn ack(&self, buf8: &mut [u8; 8]) -> u64 { 
 		// Read 8 bytes (u64) from timerfd - this clears the readable state
-		// We ignore errors because there's nothing useful to do if this fails
-		unsafe { 
+		// Errors (EAGAIN) mean no expiration is pending; report 0
+		let n = unsafe { 
 			libc::read(
 				self.0,							// timerfd file descriptor
 				buf8.as_mut_ptr() as *mut _,	// buffer to receive count
 				8								// always read 8 bytes (u64)
-			); 
+			)
 		}; 
+		if n == 8 { u64::from_ne_bytes(*buf8) } else { 0 }
+	}
+	
+	/// Time until the next expiry (timerfd_gettime), or None if disarmed
+	/// Used by the status output to tell a stuck LED from a pending off
+	fn time_remaining(&self) -> io::Result<Option<Duration>> {
+		let mut cur: libc::itimerspec = unsafe { std::mem::zeroed() };
+		if unsafe { libc::timerfd_gettime(self.0, &mut cur) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let left = Duration::new(cur.it_value.tv_sec as u64, cur.it_value.tv_nsec as u32);
+		Ok((!left.is_zero()).then_some(left))
 	}
 }
 
@@ -314,772 +874,7722 @@ impl Drop for Tfd {
 }
 
 // ============================================================================
-// LED CONTROLLER: Manages LED state via sysfs
+// SIGNALFD WRAPPER: Signals delivered as epoll events
 // ============================================================================
 
-/// LED controller that writes to sysfs brightness file
+/// Wrapper around Linux signalfd
 /// 
-/// Most Linux LED drivers expose a "brightness" file in sysfs that accepts
-/// ASCII "0" or "1" to control the LED. We keep the file open and cache the
-/// current state to avoid redundant writes (which cause unnecessary syscalls
-/// and potential flickering).
-struct Led {
-	f: File,				  // Open file handle to LED brightness sysfs file
-	current_logical: u8,	  // Cache of current state (0=off, 1=on, 255=unknown)
-	active_high: bool,		  // LED polarity: true=1 is on, false=0 is on
-}
+/// The listed signals are blocked for normal delivery and queued on the fd
+/// instead, so they're handled in the event loop like a timer expiry rather
+/// than in an async handler that can't safely touch our state.
+struct SigFd(RawFd);
 
-impl Led {
-	/// Open LED sysfs file and initialize state tracking
-	/// 
-	/// # Arguments
-	/// * `path` - Path to LED brightness file (e.g., /sys/class/leds/tpacpi::power/brightness)
-	/// * `active_high` - LED polarity (true if writing "1" turns LED on)
-	/// 
-	/// Some LEDs are "active low" meaning writing "0" turns them on.
-	/// The active_high parameter handles this difference.
-	fn new(path: &str, active_high: bool) -> io::Result<Self> {
-		// Open the LED file for writing only
-		// We keep it open for the lifetime of the program to avoid
-		// repeated open/close syscalls
-		let f = OpenOptions::new().write(true).open(path)?;
-		
-		Ok(Self { 
-			f, 
-			current_logical: 255,  // 255 = unknown state (forces first write)
-			active_high 
-		})
-	}
-	
-	/// Set LED state, avoiding redundant writes
-	/// 
-	/// This is the core LED control function. It:
-	/// 1. Checks if we're already in the desired state (avoids redundant writes)
-	/// 2. Converts logical state (on/off) to physical value based on polarity
-	/// 3. Writes the value to the sysfs file
-	/// 4. Updates the cached state
-	#[inline(always)]
-	fn set(&mut self, on: bool) -> io::Result<()> {
-		// Convert boolean to numeric state for comparison
-		let want = if on { 1 } else { 0 };
-		
-		// Skip write if already in desired state
-		// This is important for performance: avoiding unnecessary syscalls
-		// and preventing potential LED flickering from redundant writes
-		if self.current_logical == want { 
-			return Ok(()); 
+impl SigFd {
+	/// Block `signals` and create a non-blocking signalfd that receives them
+	fn new(signals: &[libc::c_int]) -> io::Result<Self> {
+		let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+		unsafe { libc::sigemptyset(&mut set) };
+		for &sig in signals {
+			unsafe { libc::sigaddset(&mut set, sig) };
 		}
 		
-		// Convert logical state to physical value based on polarity
-		// For active-high LEDs: on=1, off=0
-		// For active-low LEDs: on=0, off=1 (inverted)
-		let phys = if self.active_high { 
-			if on { b'1' } else { b'0' } 
-		} else { 
-			if on { b'0' } else { b'1' }  // Inverted for active-low LEDs
-		};
-		
-		// Write ASCII digit followed by newline
-		// Most sysfs files expect a newline-terminated value
-		let buf = [phys, b'\n'];
-		self.f.write_all(&buf)?;
+		// Must be blocked, or the default action (terminate) still runs
+		if unsafe { libc::sigprocmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
 		
-		// Update cached state so next call can skip write if unchanged
-		self.current_logical = want;
-		Ok(())
+		let fd = unsafe { libc::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC) };
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self(fd))
 	}
 	
-	/// Convenience method to turn LED on
-	#[inline(always)] 
-	fn on(&mut self) -> io::Result<()> { 
-		self.set(true) 
+	/// Take the next pending signal, if any
+	fn read(&self) -> Option<libc::c_int> {
+		let mut info: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+		let size = std::mem::size_of::<libc::signalfd_siginfo>();
+		let n = unsafe { libc::read(self.0, &mut info as *mut _ as *mut libc::c_void, size) };
+		if n as usize != size {
+			return None;
+		}
+		Some(info.ssi_signo as libc::c_int)
 	}
-	
-	/// Convenience method to turn LED off
-	#[inline(always)] 
-	fn off(&mut self) -> io::Result<()> { 
-		self.set(false) 
+}
+
+/// Close the signalfd when dropped (the signals stay blocked)
+impl Drop for SigFd {
+	fn drop(&mut self) {
+		unsafe { libc::close(self.0) };
 	}
 }
 
 // ============================================================================
-// NVME ACTIVITY MONITOR: Detects disk I/O by polling stat file
+// UEVENTS: Disks coming and going (hotplug)
 // ============================================================================
 
-/// NVMe activity monitor that reads /sys/block/nvme0n1/stat
-/// 
-/// The Linux kernel exposes disk statistics in /sys/block/*/stat with the format:
-/// Field  0: reads completed successfully
-/// Field  1: reads merged
-/// Field  2: sectors read
-/// Field  3: time spent reading (ms)
-/// Field  4: writes completed
-/// Field  5: writes merged
-/// Field  6: sectors written
-/// Field  7: time spent writing (ms)
-/// Field  8: I/Os currently in progress
-/// Field  9: time spent doing I/Os (ms)
-/// Field 10: weighted time spent doing I/Os (ms)
+/// Netlink socket receiving the kernel's device uevents
 /// 
-/// We monitor either fields 0&4 (I/O counts) or 2&6 (sector counts) and
-/// detect activity by comparing to previous values.
-struct Nvme {
-	path: String,		  // Path to stat file (e.g., /sys/block/nvme0n1/stat)
-	last_reads: u128,	  // Previous read counter value (u128 to avoid overflow)
-	last_writes: u128,	  // Previous write counter value
-	mode: NvmeMode,		  // Which fields to monitor (sectors vs I/O count)
-}
+/// The kernel multicasts one message per device added or removed:
+/// `ACTION@DEVPATH`, then NUL-separated `KEY=VALUE` pairs. Listening to the
+/// kernel group directly (rather than udev's) needs no udev daemon, and
+/// the device's sysfs files already exist by the time `add` arrives.
+struct UeventWatch(RawFd);
 
-impl Nvme {
-	/// Create a new NVMe monitor
-	/// 
-	/// # Arguments
-	/// * `path` - Path to the stat file
-	/// * `mode` - Which counters to monitor (Sectors or Io)
-	fn new(path: &str, mode: NvmeMode) -> Self {
-		Self { 
-			path: path.to_string(), 
-			last_reads: 0,		// Start with zero (first poll will show activity)
-			last_writes: 0, 
-			mode 
+impl UeventWatch {
+	/// Open a non-blocking NETLINK_KOBJECT_UEVENT socket on the kernel group
+	fn open() -> io::Result<Self> {
+		let fd = unsafe { libc::socket(libc::AF_NETLINK,
+			libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT) };
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+		addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+		addr.nl_groups = 1;  // Kernel uevents
+		let len = std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+		if unsafe { libc::bind(fd, &addr as *const _ as *const libc::sockaddr, len) } < 0 {
+			let e = io::Error::last_os_error();
+			unsafe { libc::close(fd) };
+			return Err(e);
 		}
+		Ok(Self(fd))
 	}
 	
-	/// Check for disk activity by reading stat file and comparing to previous values
-	/// 
-	/// This is called on every poll timer tick. It:
-	/// 1. Opens and reads the stat file
-	/// 2. Parses the relevant counter fields
-	/// 3. Compares to previous values to detect changes
-	/// 4. Returns the direction of activity (read/write) or None if no activity
-	/// 
-	/// # Returns
-	/// * `Some(Dir::Read)` - Only read counter increased
-	/// * `Some(Dir::Write)` - Only write counter increased, or both increased
-	/// * `None` - No activity detected
-	/// 
-	/// Note: If both counters increased, we report Write. This is arbitrary but
-	/// ensures we always report something when there's activity.
-	fn activity_dir(&mut self, scratch: &mut [u8; 256]) -> io::Result<Option<Dir>> {
-		// Open and read entire stat file into buffer
-		// We open/close on each poll rather than keeping it open because
-		// the kernel updates the file contents on each read
-		let mut f = File::open(&self.path)?;
-		let n = f.read(scratch)?;
-		
-		// Convert bytes to string for parsing
-		let s = std::str::from_utf8(&scratch[..n]).unwrap_or("");
-		
-		// Parse whitespace-separated fields
-		let mut idx = 0usize;	   // Current field index
-		let mut r = None;		   // Read counter value
-		let mut w = None;		   // Write counter value
-		
-		// Iterate through whitespace-separated tokens
-		for token in s.split_whitespace() {
-			// Try to parse as u64 (all stat fields are numeric)
-			if let Ok(v) = token.parse::<u64>() {
-				// Extract the fields we care about based on mode
-				match self.mode {
-					NvmeMode::Sectors => {
-						// Field 2: sectors read (512-byte sectors)
-						if idx == 2 { r = Some(v as u128); }
-						// Field 6: sectors written
-						if idx == 6 { 
-							w = Some(v as u128); 
-							// Early exit once we have both values
-							if r.is_some() { break; } 
-						}
-					}
-					NvmeMode::Io => {
-						// Field 0: read I/Os completed successfully
-						if idx == 0 { r = Some(v as u128); }
-						// Field 4: write I/Os completed
-						if idx == 4 { 
-							w = Some(v as u128); 
-							// Early exit once we have both values
-							if r.is_some() { break; } 
-						}
-					}
-				}
-				idx += 1;
-			} else {
-				// Non-numeric token (shouldn't happen, but handle gracefully)
-				idx += 1;
+	/// Take the next pending disk event: Some((added, device name)), None
+	/// once nothing is left. Anything else (partitions, other subsystems,
+	/// messages not from the kernel) is skipped.
+	fn read(&self, buf: &mut [u8]) -> Option<(bool, String)> {
+		loop {
+			let mut from: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+			let mut len = std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+			let n = unsafe { libc::recvfrom(self.0, buf.as_mut_ptr().cast(), buf.len(), 0,
+				&mut from as *mut _ as *mut libc::sockaddr, &mut len) };
+			if n < 0 {
+				return None;
+			}
+			if from.nl_pid == 0 && let Some(ev) = parse_uevent(&buf[..n as usize]) {
+				return Some(ev);
 			}
 		}
-		
-		// Check if we successfully parsed both values
-		// If not, return None (file format unexpected)
-		let (Some(rn), Some(wn)) = (r, w) else { 
-			return Ok(None); 
-		};
-		
-		// Compare to previous values to detect changes
-		// Any increase in counter indicates activity
-		let rchg = rn != self.last_reads;
-		let wchg = wn != self.last_writes;
-		
-		// Update cached values for next comparison
-		// Important: do this before returning so next poll sees new baseline
-		self.last_reads = rn;
-		self.last_writes = wn;
-		
-		// Determine activity direction based on which counter(s) changed
-		// Priority: if both changed, report as Write (arbitrary choice)
-		if rchg && !wchg { 
-			Ok(Some(Dir::Read))		 // Only reads increased
-		} else if wchg && !rchg { 
-			Ok(Some(Dir::Write))	 // Only writes increased
-		} else if rchg && wchg { 
-			Ok(Some(Dir::Write))	 // Both increased, report as write
-		} else { 
-			Ok(None)				 // No change detected
+	}
+}
+
+/// Close the socket when dropped
+impl Drop for UeventWatch {
+	fn drop(&mut self) {
+		unsafe { libc::close(self.0) };
+	}
+}
+
+/// Pick a whole-disk add/remove out of a uevent message
+/// 
+/// # Returns
+/// * `Some((true, name))` - Block disk `name` (e.g. `nvme1n1`) was added
+/// * `Some((false, name))` - It was removed
+/// * `None` - Some other device or action
+fn parse_uevent(msg: &[u8]) -> Option<(bool, String)> {
+	let mut action = None;
+	let (mut block, mut disk, mut name) = (false, false, None);
+	for field in msg.split(|&b| b == 0).skip(1) {
+		let field = String::from_utf8_lossy(field);
+		match field.split_once('=') {
+			Some(("ACTION", v)) => action = match v { "add" => Some(true), "remove" => Some(false), _ => None },
+			Some(("SUBSYSTEM", v)) => block = v == "block",
+			Some(("DEVTYPE", v)) => disk = v == "disk",
+			Some(("DEVNAME", v)) => name = Some(v.trim_start_matches("/dev/").to_string()),
+			_ => {}
 		}
 	}
+	if !(block && disk) {
+		return None;
+	}
+	Some((action?, name?))
 }
 
 // ============================================================================
-// CONFIGURATION: Settings loaded from file and/or CLI
+// SERVICE MANAGER: systemd notification protocol
 // ============================================================================
 
-/// Configuration loaded from file and/or command-line arguments
+/// Send a state string (e.g. "READY=1") to systemd's notification socket
 /// 
-/// Settings are loaded in this order (later overrides earlier):
-/// 1. Hard-coded defaults (DEFAULT_* constants)
-/// 2. Default config file (/etc/nvme-led-daemon.conf) if present
-/// 3. Custom config file (--config PATH) if specified
-/// 4. Command-line arguments
-#[derive(Clone)]
-struct Config {
-	led_path: String,				   // Path to LED sysfs file
-	nvme_path: String,				   // Path to NVMe stat file
-	poll_ms: u64,					   // Polling interval in milliseconds
-	blink_ms: u64,					   // Default LED on duration in milliseconds
-	read_blink_ms: Option<u64>,		   // Override blink duration for reads (if Some)
-	write_blink_ms: Option<u64>,	   // Override blink duration for writes (if Some)
-	active_high: bool,				   // LED polarity (true = writing "1" turns on)
-	quiet: bool,					   // Suppress startup message
-	nvme_mode: NvmeMode,			   // Which stat fields to monitor
-	on_fields: FieldsSel,			   // Which operations trigger LED
+/// The protocol is one datagram to the unix socket named by NOTIFY_SOCKET
+/// (a leading '@' means the abstract namespace). Without NOTIFY_SOCKET we
+/// weren't started as Type=notify, so this does nothing.
+fn sd_notify(state: &str) -> io::Result<()> {
+	use std::os::unix::net::{SocketAddr, UnixDatagram};
+	
+	let Some(path) = env::var_os("NOTIFY_SOCKET") else { return Ok(()) };
+	let path = path.to_string_lossy();
+	let addr = match path.strip_prefix('@') {
+		Some(name) => {
+			use std::os::linux::net::SocketAddrExt;
+			SocketAddr::from_abstract_name(name.as_bytes())?
+		}
+		None => SocketAddr::from_pathname(path.as_ref())?,
+	};
+	
+	let sock = UnixDatagram::unbound()?;
+	sock.send_to_addr(state.as_bytes(), &addr)?;
+	Ok(())
 }
 
-/// Load configuration from a key=value file
+/// Keep-alive interval requested by systemd's WatchdogSec=, in milliseconds
 /// 
-/// File format:
-/// - Lines starting with # are comments
-/// - Empty lines are ignored
-/// - Settings are key=value pairs
-/// - Whitespace around = is trimmed
+/// Taken from WATCHDOG_USEC (ignored if WATCHDOG_PID names another process).
+/// Returns half the timeout, the ping rate sd_watchdog_enabled(3) suggests.
+fn watchdog_ping_ms() -> Option<u64> {
+	if let Ok(pid) = env::var("WATCHDOG_PID")
+		&& pid.trim().parse::<u32>().ok() != Some(process::id()) {
+		return None;
+	}
+	let usec = env::var("WATCHDOG_USEC").ok()?.trim().parse::<u64>().ok()?;
+	(usec > 0).then(|| (usec / 2000).max(1))
+}
+
+/// Create the PID file exclusively and write our PID into it
 /// 
-/// Example:
-/// ```
-/// # NVMe LED daemon configuration
-/// led_path=/sys/class/leds/tpacpi::power/brightness
-/// interval_ms=10
-/// blink_ms=20
-/// active_high=false
-/// ```
-fn load_config(path: &str) -> io::Result<HashMap<String, String>> {
-	// Read entire file as string
-	let contents = std::fs::read_to_string(path)?;
-	let mut map = HashMap::new();
-	
-	// Parse line by line
-	for line in contents.lines() {
-		let line = line.trim();
-		
-		// Skip comments and empty lines
-		if line.is_empty() || line.starts_with('#') { 
-			continue; 
+/// O_EXCL makes a second instance notice the first. A leftover file whose
+/// PID is no longer running (crash, power loss) is stale and replaced; one
+/// naming a live process is an error, so two daemons never share an LED.
+fn create_pidfile(path: &str) -> io::Result<File> {
+	for _ in 0..2 {
+		match OpenOptions::new().write(true).create_new(true).open(path) {
+			Ok(mut f) => return writeln!(f, "{}", process::id()).map(|_| f),
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+				let old = std::fs::read_to_string(path).ok()
+					.and_then(|s| s.trim().parse::<libc::pid_t>().ok());
+				// kill(pid, 0) probes for existence; EPERM still means alive
+				if let Some(pid) = old
+					&& pid > 0
+					&& (unsafe { libc::kill(pid, 0) } == 0
+						|| io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)) {
+					return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+						format!("pidfile {}: already running as pid {}", path, pid)));
+				}
+				log!(Level::Warn, "removing stale pidfile {}", path);
+				std::fs::remove_file(path)?;
+			}
+			Err(e) => return Err(io::Error::new(e.kind(), format!("pidfile {}: {}", path, e))),
 		}
-		
-		// Split on first '=' to get key and value
-		if let Some((k, v)) = line.split_once('=') {
-			// Trim whitespace and store in map
-			map.insert(k.trim().to_string(), v.trim().to_string());
+	}
+	Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("pidfile {}: recreated while starting", path)))
+}
+
+/// Remove our pidfile on the way out
+/// 
+/// After --user the directory (e.g. /run) usually isn't ours to unlink
+/// from any more. The file is then emptied through the fd kept since it
+/// was created: a pidfile without a PID is stale to the next start, which
+/// replaces it.
+fn remove_pidfile(path: &str, f: &File) {
+	if let Err(e) = std::fs::remove_file(path) {
+		log!(Level::Warn, "removing pidfile {}: {}; emptying it instead", path, e);
+		if let Err(e) = f.set_len(0) {
+			log!(Level::Warn, "emptying pidfile {}: {}", path, e);
 		}
 	}
-	Ok(map)
 }
 
-/// Parse boolean from config map with default fallback
-/// Accepts: true/yes/1 for true, false/no/0 for false
-/// Returns default if key not found or value not recognized
-fn get_bool(map: &HashMap<String, String>, key: &str, default: bool) -> bool {
-	map.get(key).and_then(|v| match v.as_str() {
-		"true" | "yes" | "1" => Some(true),
-		"false" | "no" | "0" => Some(false),
-		_ => None  // Unrecognized value, use default
-	}).unwrap_or(default)
+/// Switch to an unprivileged user once every privileged fd is open
+/// 
+/// Supplementary groups are cleared first (root's would otherwise survive),
+/// then the group, then the user - in that order, since after setuid we'd
+/// no longer be allowed to change groups.
+fn drop_privileges(user: &str) -> io::Result<()> {
+	let name = std::ffi::CString::new(user)
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "user name contains NUL"))?;
+	let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+	if pw.is_null() {
+		return Err(io::Error::new(io::ErrorKind::NotFound, format!("unknown user {:?}", user)));
+	}
+	let (uid, gid) = unsafe { ((*pw).pw_uid, (*pw).pw_gid) };
+	
+	if unsafe { libc::setgroups(0, std::ptr::null()) } < 0
+		|| unsafe { libc::setgid(gid) } < 0
+		|| unsafe { libc::setuid(uid) } < 0 {
+		let e = io::Error::last_os_error();
+		return Err(io::Error::new(e.kind(), format!("dropping privileges to {}: {}", user, e)));
+	}
+	Ok(())
 }
 
-/// Parse u64 from config map with default fallback
-/// Returns default if key not found or value not a valid u64
-fn get_u64(map: &HashMap<String, String>, key: &str, default: u64) -> u64 {
-	map.get(key)
-		.and_then(|v| v.parse().ok())  // Try to parse as u64
-		.unwrap_or(default)				// Use default if parse fails
+/// Highest SCHED_FIFO priority (sched_get_priority_max on Linux)
+/// --rt-priority above it is rejected rather than silently clamped
+const RT_PRIORITY_MAX: u64 = 99;
+
+/// sched_setscheduler flag: children fall back to SCHED_OTHER
+/// Not in the libc crate for Linux; value from <linux/sched.h>
+const SCHED_RESET_ON_FORK: libc::c_int = 0x4000_0000;
+
+/// Run the daemon as SCHED_FIFO at `priority` so a busy system can't
+/// delay the poll and off timers
+/// 
+/// Must happen before drop_privileges: raising a priority needs
+/// CAP_SYS_NICE (or an RLIMIT_RTPRIO allowance), which the new user lacks.
+fn set_rt_priority(priority: u64) -> io::Result<()> {
+	let param = libc::sched_param { sched_priority: priority as libc::c_int };
+	if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO | SCHED_RESET_ON_FORK, &param) } < 0 {
+		let e = io::Error::last_os_error();
+		if e.raw_os_error() == Some(libc::EPERM) {
+			return Err(io::Error::new(e.kind(), format!(
+				"--rt-priority {}: not permitted (needs CAP_SYS_NICE or LimitRTPRIO= of at least {})", priority, priority)));
+		}
+		return Err(io::Error::new(e.kind(), format!("--rt-priority {}: {}", priority, e)));
+	}
+	Ok(())
 }
 
-/// Get string from config map with default fallback
-/// Returns reference to value in map, or default if key not found
-fn get_str<'a>(map: &'a HashMap<String, String>, key: &str, default: &'a str) -> &'a str {
-	map.get(key)
-		.map(|s| s.as_str())  // Convert String to &str
-		.unwrap_or(default)    // Use default if key not found
+/// Restrict the daemon to one CPU, e.g. a housekeeping core kept free of
+/// workload threads
+/// 
+/// The index is checked against the CPUs we may run on now (online, and
+/// allowed by taskset or the cgroup), not a count: with CPUs offlined the
+/// online set has holes. A typo gets a clear message listing that set.
+fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+	let size = std::mem::size_of::<libc::cpu_set_t>();
+	let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+	if unsafe { libc::sched_getaffinity(0, size, &mut allowed) } < 0 {
+		let e = io::Error::last_os_error();
+		return Err(io::Error::new(e.kind(), format!("--cpu {}: {}", cpu, e)));
+	}
+	if cpu >= libc::CPU_SETSIZE as usize || !unsafe { libc::CPU_ISSET(cpu, &allowed) } {
+		let cpus: Vec<String> = (0..libc::CPU_SETSIZE as usize)
+			.filter(|&c| unsafe { libc::CPU_ISSET(c, &allowed) })
+			.map(|c| c.to_string())
+			.collect();
+		return Err(io::Error::new(io::ErrorKind::InvalidInput,
+			format!("--cpu {}: not one of the CPUs available ({})", cpu, cpus.join(","))));
+	}
+	let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+	unsafe { libc::CPU_SET(cpu, &mut set) };
+	if unsafe { libc::sched_setaffinity(0, size, &set) } < 0 {
+		let e = io::Error::last_os_error();
+		return Err(io::Error::new(e.kind(), format!("--cpu {}: {}", cpu, e)));
+	}
+	Ok(())
 }
 
-/// Print help message and exit
-/// Called when user passes --help or invalid arguments
-fn help() -> ! {
-	eprintln!(
-"nvme-led-daemon (GPL-3.0-or-later)
-Usage:
-  nvme-led-daemon [--config PATH] [OPTIONS]
+/// Lock every current and future page of the daemon into RAM, so a
+/// page-in after memory pressure can't stall a poll or an LED write
+/// 
+/// Like set_rt_priority, this runs before drop_privileges: an
+/// unprivileged process is limited to RLIMIT_MEMLOCK, usually far less
+/// than the binary and its heap.
+fn lock_memory() -> io::Result<()> {
+	if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } < 0 {
+		let e = io::Error::last_os_error();
+		let why = match e.raw_os_error() {
+			Some(libc::ENOMEM) => "RLIMIT_MEMLOCK too low (raise LimitMEMLOCK= or run as root)".to_string(),
+			Some(libc::EPERM) => "needs CAP_IPC_LOCK".to_string(),
+			_ => e.to_string(),
+		};
+		return Err(io::Error::new(e.kind(), why));
+	}
+	Ok(())
+}
 
-Config file (optional): {default_cfg}
-CLI options override config file settings.
+// ============================================================================
+// LED CONTROLLER: Manages LED state via sysfs
+// ============================================================================
 
-Options:
-  --config PATH    Load config from PATH
-  --led PATH
-  --nvme PATH
-  --interval-ms N
-  --blink-ms N
-  --read-blink-ms N
-  --write-blink-ms N
-  --on-fields reads|writes|both
-  --nvme-mode io|sectors
-  --active-high
-  --quiet
-  --help
+/// Something that shows LED states: the sysfs `Led`, or a `RecordingLed`
+/// that only logs/records them (--dry-run, tests)
+/// 
+/// Blinks, patterns, PWM and fades all drive the LED through this, so
+/// they behave the same whatever is underneath.
+trait LedBackend {
+	/// Set the LED on or off, skipping redundant writes
+	fn set(&mut self, on: bool) -> io::Result<()>;
+	
+	/// Set a brightness level (clamped to max_brightness), skipping
+	/// redundant writes
+	fn set_level(&mut self, level: u32) -> io::Result<()>;
+	
+	/// Write a raw brightness value (e.g. one saved at startup) verbatim
+	fn write_raw(&mut self, raw: &str) -> io::Result<()>;
+	
+	/// Brightness the LED had when it was opened (None if unknown)
+	fn original(&self) -> Option<u32>;
+	
+	/// Check for out-of-band changes; Some(observed) if the LED no longer
+	/// matches what we last wrote
+	fn reconcile(&mut self) -> io::Result<Option<bool>>;
+	
+	/// Highest brightness level (1 for on/off LEDs)
+	fn max_brightness(&self) -> u32;
+	
+	/// Last level written by set_level (None after set or a dropped write)
+	fn level(&self) -> Option<u32>;
+	
+	/// Convenience method to turn LED on
+	#[inline(always)] 
+	fn on(&mut self) -> io::Result<()> { 
+		self.set(true) 
+	}
+	
+	/// Convenience method to turn LED off
+	#[inline(always)] 
+	fn off(&mut self) -> io::Result<()> { 
+		self.set(false) 
+	}
+	
+	/// Put back the brightness from before we opened it (exit_led_state=
+	/// restore), or switch off if it couldn't be read
+	fn restore(&mut self) -> io::Result<()> {
+		match self.original() {
+			Some(v) => self.write_raw(&v.to_string()),
+			None => self.off(),
+		}
+	}
+}
 
-Defaults:
-  led_path	  {lp}
-  nvme_path    {np}
-  interval_ms	 {pi}
-  blink_ms	  {bm}
-  nvme_mode    sectors
-  on_fields    both
-",
-		default_cfg = DEFAULT_CONFIG_PATH,
-		lp = DEFAULT_LED_PATH, 
-		np = DEFAULT_NVME_STAT_PATH,
-		pi = DEFAULT_POLL_INTERVAL_MS, 
-		bm = DEFAULT_BLINK_ON_MS
-	);
-	process::exit(0)
+/// Read the max_brightness file next to a brightness file (1 if absent)
+fn read_max_brightness(path: &str) -> u32 {
+	std::path::Path::new(path).parent()
+		.and_then(|d| std::fs::read_to_string(d.join("max_brightness")).ok())
+		.and_then(|v| v.trim().parse::<u32>().ok())
+		.unwrap_or(1)
+		.max(1)
 }
 
-/// Parse configuration from default config file and command-line arguments
-/// 
-/// Loading order:
-/// 1. Try to load /etc/nvme-led-daemon.conf (silently ignore if missing)
-/// 2. Apply defaults from config file or use hard-coded defaults
-/// 3. Process CLI arguments, which override config file settings
-/// 4. If --config specified, load that file and re-apply its settings
-///    (but CLI args still take precedence)
+/// Open the backend for the LED at `path`: the real sysfs file, or with
+/// --dry-run a stand-in that logs what would be written
+fn open_led(cfg: &Config, path: &str, active_high: bool) -> io::Result<Box<dyn LedBackend>> {
+	if cfg.dry_run {
+		return Ok(Box::new(RecordingLed::dry_run(path)));
+	}
+	Ok(Box::new(Led::new(path, active_high)?))
+}
+
+/// LED controller that writes to sysfs brightness file
 /// 
-/// This allows flexible configuration: you can use just CLI args, just a
-/// config file, or a mix of both with CLI args overriding file settings.
-fn parse_args() -> Config {
-	// Try loading default config file first (silently ignore if missing)
-	// unwrap_or_else returns empty HashMap if file doesn't exist
-	let config_map = load_config(DEFAULT_CONFIG_PATH)
-		.unwrap_or_else(|_| HashMap::new());
+/// Most Linux LED drivers expose a "brightness" file in sysfs that accepts
+/// ASCII "0" or "1" to control the LED. We keep the file open and cache the
+/// current state to avoid redundant writes (which cause unnecessary syscalls
+/// and potential flickering).
+struct Led {
+	f: File,				  // Open file handle to LED brightness sysfs file
+	path: String,			  // Path to brightness file (re-read for reconciliation)
+	current_logical: u8,	  // Cache of current state (0=off, 1=on, 255=unknown)
+	active_high: bool,		  // LED polarity: true=1 is on, false=0 is on
+	max_brightness: u32,	  // From the sibling max_brightness file (1 if absent)
+	original: Option<u32>,	  // Raw brightness found at open (for restore)
+	level: Option<u32>,		  // Last level written by set_level (None after set)
+	retry_at: Option<Instant>,  // Degraded: next reopen attempt (None = healthy)
+	retry_ms: u64,			  // Current reopen backoff
+}
 
-	// Initialize config with defaults from file or constants
-	// get_* functions handle missing keys by returning defaults
-	let mut cfg = Config {
-		led_path: get_str(&config_map, "led_path", DEFAULT_LED_PATH).to_string(),
-		nvme_path: get_str(&config_map, "nvme_path", DEFAULT_NVME_STAT_PATH).to_string(),
-		poll_ms: get_u64(&config_map, "interval_ms", DEFAULT_POLL_INTERVAL_MS),
-		blink_ms: get_u64(&config_map, "blink_ms", DEFAULT_BLINK_ON_MS),
-		
-		// Optional per-direction blink durations
-		read_blink_ms: config_map.get("read_blink_ms")
-			.and_then(|v| v.parse().ok()),
-		write_blink_ms: config_map.get("write_blink_ms")
-			.and_then(|v| v.parse().ok()),
-		
-		active_high: get_bool(&config_map, "active_high", false),
-		quiet: get_bool(&config_map, "quiet", false),
-		
-		// Parse nvme_mode from string
-		nvme_mode: match get_str(&config_map, "nvme_mode", "sectors") {
-			"io" => NvmeMode::Io,
-			_ => NvmeMode::Sectors,  // Default to sectors for any other value
-		},
+impl Led {
+	/// Open LED sysfs file and initialize state tracking
+	/// 
+	/// # Arguments
+	/// * `path` - Path to LED brightness file (e.g., /sys/class/leds/tpacpi::power/brightness)
+	/// * `active_high` - LED polarity (true if writing "1" turns LED on)
+	/// 
+	/// Some LEDs are "active low" meaning writing "0" turns them on.
+	/// The active_high parameter handles this difference.
+	fn new(path: &str, active_high: bool) -> io::Result<Self> {
+		// Open the LED file for writing only
+		// We keep it open for the lifetime of the program to avoid
+		// repeated open/close syscalls
+		let f = OpenOptions::new().write(true).open(path)?;
 		
-		// Parse on_fields from string
-		on_fields: match get_str(&config_map, "on_fields", "both") {
-			"reads" => FieldsSel::Reads,
-			"writes" => FieldsSel::Writes,
-			_ => FieldsSel::Both,  // Default to both for any other value
-		},
-	};
-
-	// Process command-line arguments, overriding config file values
-	// skip(1) skips the program name (argv[0])
-	let mut it = env::args().skip(1).peekable();
-	
-	while let Some(a) = it.next() {
-		match a.as_str() {
-			"--help" | "-h" => help(),	// Print help and exit
-			
-			// Boolean flags (no argument)
-			"--quiet" => cfg.quiet = true,
-			"--active-high" => cfg.active_high = true,
-			
-			// Path arguments (require next argument)
-			"--led" => { 
-				cfg.led_path = it.next().unwrap_or_else(|| { 
-					eprintln!("--led requires PATH"); 
-					process::exit(2) 
-				}); 
-			}
-			
-			"--nvme" => { 
-				cfg.nvme_path = it.next().unwrap_or_else(|| { 
-					eprintln!("--nvme requires PATH"); 
-					process::exit(2) 
-				}); 
-			}
-			
-			// Numeric arguments with validation
-			"--interval-ms" => {
-				cfg.poll_ms = it.next()
-					.and_then(|v| v.parse().ok())
-					.unwrap_or_else(|| { 
-						eprintln!("invalid --interval-ms"); 
-						process::exit(2) 
-					});
-				// Enforce minimum of 1ms (0 would cause busy loop)
-				if cfg.poll_ms == 0 { cfg.poll_ms = 1; }
-			}
-			
-			"--blink-ms" => {
-				cfg.blink_ms = it.next()
-					.and_then(|v| v.parse().ok())
-					.unwrap_or_else(|| { 
-						eprintln!("invalid --blink-ms"); 
-						process::exit(2) 
-					});
-				// Enforce minimum of 1ms
-				if cfg.blink_ms == 0 { cfg.blink_ms = 1; }
-			}
-			
-			"--read-blink-ms" => {
-				let v: u64 = it.next()
-					.and_then(|v| v.parse().ok())
-					.unwrap_or_else(|| { 
-						eprintln!("invalid --read-blink-ms"); 
-						process::exit(2) 
-					});
-				// Store as Some with minimum of 1ms
-				cfg.read_blink_ms = Some(v.max(1));
-			}
-			
-			"--write-blink-ms" => {
-				let v: u64 = it.next()
-					.and_then(|v| v.parse().ok())
-					.unwrap_or_else(|| { 
-						eprintln!("invalid --write-blink-ms"); 
-						process::exit(2) 
-					});
-				// Store as Some with minimum of 1ms
-				cfg.write_blink_ms = Some(v.max(1));
-			}
-			
-			// Enum arguments with validation
-			"--nvme-mode" => {
-				let v = it.next().unwrap_or_else(|| { 
-					eprintln!("--nvme-mode requires io|sectors"); 
-					process::exit(2) 
-				});
-				cfg.nvme_mode = match v.as_str() {
-					"io" => NvmeMode::Io,
-					"sectors" => NvmeMode::Sectors,
-					_ => { 
-						eprintln!("--nvme-mode must be io or sectors"); 
-						process::exit(2) 
-					}
-				}
-			}
-			
-			"--on-fields" => {
-				let v = it.next().unwrap_or_else(|| { 
-					eprintln!("--on-fields requires reads|writes|both"); 
-					process::exit(2) 
-				});
-				cfg.on_fields = match v.as_str() {
-					"reads" => FieldsSel::Reads,
-					"writes" => FieldsSel::Writes,
-					"both" => FieldsSel::Both,
-					_ => { 
-						eprintln!("--on-fields must be reads|writes|both"); 
-						process::exit(2) 
-					}
+		// Whatever the firmware (or a trigger) left it at, before we touch
+		// it; kept raw, so polarity and levels above 1 come back as they were
+		let original = std::fs::read_to_string(path).ok()
+			.and_then(|s| s.trim().parse::<u32>().ok());
+		
+		Ok(Self { 
+			f, 
+			path: path.to_string(),
+			current_logical: 255,  // 255 = unknown state (forces first write)
+			active_high,
+			// Dimmable LEDs advertise more than one step; on/off ones say 1
+			max_brightness: read_max_brightness(path),
+			original,
+			level: None,
+			retry_at: None,
+			retry_ms: REOPEN_BACKOFF_MIN_MS,
+		})
+	}
+	
+	/// Write a value to the brightness file, surviving the node going away
+	/// 
+	/// If the LED disappears (dock undock, module reload) the write fails.
+	/// The file is then reopened and the write retried once; if that fails
+	/// too the LED is degraded. Writes are dropped while degraded and a
+	/// reopen is only attempted every `retry_ms`, doubling up to
+	/// REOPEN_BACKOFF_MAX_MS, so a missing LED doesn't cost an open per poll.
+	/// 
+	/// # Returns
+	/// `true` if the value reached the LED, `false` if it was dropped
+	fn write_value(&mut self, buf: &[u8]) -> bool {
+		match self.retry_at {
+			Some(at) if Instant::now() < at => return false,
+			Some(_) => {}
+			None => if self.f.write_all(buf).is_ok() { return true; },
+		}
+		
+		let reopened = OpenOptions::new().write(true).open(&self.path)
+			.and_then(|mut f| f.write_all(buf).map(|_| f));
+		match reopened {
+			Ok(f) => {
+				self.f = f;
+				if self.retry_at.take().is_some() {
+					log!(Level::Info, "LED {} is back", self.path);
 				}
+				self.retry_ms = REOPEN_BACKOFF_MIN_MS;
+				true
 			}
-			
-			// Load custom config file
-			// This re-applies config file settings, but CLI args already
-			// processed still take precedence (we don't re-process them)
-			"--config" => {
-				let path = it.next().unwrap_or_else(|| { 
-					eprintln!("--config requires PATH"); 
-					process::exit(2) 
-				});
-				
-				// Load the custom config file (error if it doesn't exist)
-				let new_map = load_config(&path).unwrap_or_else(|e| {
-					eprintln!("Failed to load config {}: {}", path, e);
-					process::exit(2)
-				});
-				
-				// Re-apply config from custom path
-				// Use current values as defaults so CLI args aren't overridden
-				cfg.led_path = get_str(&new_map, "led_path", &cfg.led_path).to_string();
-				cfg.nvme_path = get_str(&new_map, "nvme_path", &cfg.nvme_path).to_string();
-				cfg.poll_ms = get_u64(&new_map, "interval_ms", cfg.poll_ms);
-				cfg.blink_ms = get_u64(&new_map, "blink_ms", cfg.blink_ms);
-				
-				// Optional values: only override if present in new config
-				if let Some(v) = new_map.get("read_blink_ms").and_then(|v| v.parse().ok()) { 
-					cfg.read_blink_ms = Some(v); 
+			Err(e) => {
+				if self.retry_at.is_none() {
+					log!(Level::Warn, "LED {} unavailable ({}); retrying in the background", self.path, e);
+				} else {
+					self.retry_ms = (self.retry_ms * 2).min(REOPEN_BACKOFF_MAX_MS);
 				}
-				if let Some(v) = new_map.get("write_blink_ms").and_then(|v| v.parse().ok()) { 
-					cfg.write_blink_ms = Some(v); 
-				}
-				
-				cfg.active_high = get_bool(&new_map, "active_high", cfg.active_high);
-				cfg.quiet = get_bool(&new_map, "quiet", cfg.quiet);
-				
-				// Parse enum values with current value as default
-				cfg.nvme_mode = match get_str(&new_map, "nvme_mode", 
-					match cfg.nvme_mode { 
-						NvmeMode::Io => "io", 
-						NvmeMode::Sectors => "sectors" 
-					}) {
-					"io" => NvmeMode::Io,
-					_ => NvmeMode::Sectors,
-				};
-				
-				cfg.on_fields = match get_str(&new_map, "on_fields", 
-					match cfg.on_fields { 
-						FieldsSel::Reads => "reads", 
-						FieldsSel::Writes => "writes", 
-						FieldsSel::Both => "both" 
-					}) {
-					"reads" => FieldsSel::Reads,
-					"writes" => FieldsSel::Writes,
-					_ => FieldsSel::Both,
-				};
-			}
-			
-			// Unknown argument
-			other => { 
-				eprintln!("Unknown arg: {}", other); 
-				help();  // Print help and exit
+				self.retry_at = Some(Instant::now() + Duration::from_millis(self.retry_ms));
+				false
 			}
 		}
 	}
-	
-	cfg
 }
 
-// ============================================================================
-// MAIN: Event loop that ties everything together
-// ============================================================================
+impl LedBackend for Led {
+	/// Set LED state, avoiding redundant writes
+	/// 
+	/// This is the core LED control function. It:
+	/// 1. Checks if we're already in the desired state (avoids redundant writes)
+	/// 2. Converts logical state (on/off) to physical value based on polarity
+	///    ("on" is full brightness: max_brightness, not 1, which on a
+	///    255-step LED would barely glow)
+	/// 3. Writes the value to the sysfs file
+	/// 4. Updates the cached state
+	#[inline(always)]
+	fn set(&mut self, on: bool) -> io::Result<()> {
+		// Convert boolean to numeric state for comparison
+		let want = if on { 1 } else { 0 };
+		
+		// Skip write if already in desired state
+		// This is important for performance: avoiding unnecessary syscalls
+		// and preventing potential LED flickering from redundant writes
+		if self.current_logical == want { 
+			return Ok(()); 
+		}
+		
+		// Convert logical state to physical value based on polarity
+		// For active-high LEDs: on=max, off=0
+		// For active-low LEDs: on=0, off=max (inverted)
+		let lit = on == self.active_high;
+		let phys = if lit { self.max_brightness } else { 0 };
+		
+		// Write the decimal value followed by newline
+		// Most sysfs files expect a newline-terminated value
+		let buf = format!("{}\n", phys);
+		
+		// Update cached state so next call can skip write if unchanged
+		// (a dropped write leaves it unknown, so recovery rewrites it)
+		self.current_logical = if self.write_value(buf.as_bytes()) { want } else { 255 };
+		self.level = None;
+		Ok(())
+	}
+	
+	/// Set an actual brightness level, clamped to max_brightness
+	/// 
+	/// Like `set`, repeated writes of the same level are skipped. Active-low
+	/// LEDs count down from max_brightness. Any level above 0 counts as "on"
+	/// for the on/off cache, so a later `off()` always writes.
+	fn set_level(&mut self, level: u32) -> io::Result<()> {
+		let level = level.min(self.max_brightness);
+		if self.level == Some(level) {
+			return Ok(());
+		}
+		
+		let phys = if self.active_high { level } else { self.max_brightness - level };
+		if self.write_value(format!("{}\n", phys).as_bytes()) {
+			self.level = Some(level);
+			self.current_logical = if level > 0 { 1 } else { 0 };
+		} else {
+			self.level = None;
+			self.current_logical = 255;
+		}
+		Ok(())
+	}
+	
+	/// Write a raw brightness value (e.g. one saved at startup) verbatim
+	/// The cache becomes unknown since the value may not be 0/1.
+	fn write_raw(&mut self, raw: &str) -> io::Result<()> {
+		self.current_logical = 255;
+		self.level = None;
+		self.write_value(format!("{}\n", raw.trim()).as_bytes());
+		Ok(())
+	}
+	
+	/// Compare the actual brightness with our cached state
+	/// 
+	/// Another process (or firmware) may write the LED behind our back, in
+	/// which case `set` would skip a write it actually needs. This re-reads
+	/// the brightness file and, if it disagrees with `current_logical`,
+	/// resets the cache to unknown so the next `set` always writes.
+	/// 
+	/// # Returns
+	/// * `Some(on)` - Out-of-band change detected; `on` is the observed state
+	/// * `None` - State matches the cache (or the cache is already unknown)
+	fn reconcile(&mut self) -> io::Result<Option<bool>> {
+		// Nothing to compare against until we've written once
+		if self.current_logical == 255 {
+			return Ok(None);
+		}
+		
+		// Any non-zero brightness counts as physically lit
+		let raw = std::fs::read_to_string(&self.path)?;
+		let lit = raw.trim().parse::<u32>().map(|v| v != 0).unwrap_or(false);
+		
+		// Undo polarity to get the logical state
+		let on = lit == self.active_high;
+		if on as u8 == self.current_logical {
+			return Ok(None);
+		}
+		
+		self.current_logical = 255;
+		self.level = None;
+		Ok(Some(on))
+	}
+	
+	fn original(&self) -> Option<u32> {
+		self.original
+	}
+	
+	fn max_brightness(&self) -> u32 {
+		self.max_brightness
+	}
+	
+	fn level(&self) -> Option<u32> {
+		self.level
+	}
+}
 
-/// Main event loop: monitor NVMe activity and blink LED accordingly
+/// LED backend that writes nowhere
 /// 
-/// Architecture:
-/// 1. Set up epoll with two timerfds (poll timer and off timer)
-/// 2. Enter infinite loop waiting for timer events
-/// 3. On poll timer: check NVMe stats, turn LED on if activity detected
-/// 4. On off timer: turn LED off
+/// With --dry-run each change is logged instead of written, so a config
+/// can be tried without touching the real LED; tests record the writes
+/// instead. Redundant writes are skipped exactly like `Led` does, so what
+/// shows up is what the sysfs file would have received. Logged lines carry
+/// the time since start, so blink lengths and gaps can be read off them.
+struct RecordingLed {
+	label: Option<String>,		  // Log writes under this name (None = silent)
+	started: Instant,			  // Zero point for logged timestamps
+	writes: Option<Vec<String>>,  // Every write in order, when recording
+	max_brightness: u32,		  // Level range to pretend to have
+	on: Option<bool>,			  // Cached on/off state (None = unknown)
+	level: Option<u32>,			  // Last level written by set_level
+}
+
+impl RecordingLed {
+	/// Stand-in for the LED at `path`, logging each write; max_brightness
+	/// is still read from sysfs so dimming decisions match the real LED
+	fn dry_run(path: &str) -> Self {
+		Self {
+			label: Some(path.to_string()),
+			started: Instant::now(),
+			writes: None,
+			max_brightness: read_max_brightness(path),
+			on: None,
+			level: None,
+		}
+	}
+	
+	/// Log and/or record one write
+	fn record(&mut self, what: String) {
+		if let Some(label) = &self.label {
+			log!(Level::Info, "dry run: {:9.3}s LED {} {}", self.started.elapsed().as_secs_f64(), label, what);
+		}
+		if let Some(w) = &mut self.writes {
+			w.push(what);
+		}
+	}
+}
+
+impl LedBackend for RecordingLed {
+	fn set(&mut self, on: bool) -> io::Result<()> {
+		if self.on == Some(on) {
+			return Ok(());
+		}
+		self.record(if on { "on" } else { "off" }.to_string());
+		self.on = Some(on);
+		self.level = None;
+		Ok(())
+	}
+	
+	fn set_level(&mut self, level: u32) -> io::Result<()> {
+		let level = level.min(self.max_brightness);
+		if self.level == Some(level) {
+			return Ok(());
+		}
+		self.record(format!("level {}", level));
+		self.on = Some(level > 0);
+		self.level = Some(level);
+		Ok(())
+	}
+	
+	fn write_raw(&mut self, raw: &str) -> io::Result<()> {
+		self.record(format!("raw {}", raw.trim()));
+		self.on = None;
+		self.level = None;
+		Ok(())
+	}
+	
+	fn reconcile(&mut self) -> io::Result<Option<bool>> {
+		Ok(None)  // Nothing else can write to it
+	}
+	
+	fn original(&self) -> Option<u32> {
+		None  // Never wrote anything, so nothing to put back
+	}
+	
+	fn max_brightness(&self) -> u32 {
+		self.max_brightness
+	}
+	
+	fn level(&self) -> Option<u32> {
+		self.level
+	}
+}
+
+/// Leave an LED we're done with in the configured exit state
+fn release_led(led: &mut dyn LedBackend, state: ExitLedState) -> io::Result<()> {
+	match state {
+		ExitLedState::On => led.on(),
+		ExitLedState::Off => led.off(),
+		ExitLedState::Restore => led.restore(),
+	}
+}
+
+/// Blink the configured LED so the user can check it's the right one
 /// 
-/// The key insight is that we use two independent timers:
-/// - Poll timer fires regularly (e.g., every 10ms) to check for activity
-/// - Off timer is armed when activity detected and fires once to turn LED off
+/// TEST_BLINKS on/off blinks of TEST_BLINK_MS through `Led::set` (so
+/// active_high applies), then for dimmable LEDs a sweep up to
+/// max_brightness and back down. Plain sleeps: no event loop needed.
+/// A permission or polarity mistake shows up right here.
+fn test_led(cfg: &Config) -> Result<(), DaemonError> {
+	let mut led = open_led(cfg, &cfg.led_path, cfg.active_high).map_err(DaemonError::led_open(&cfg.led_path))?;
+	let pause = |ms: u64| std::thread::sleep(Duration::from_millis(ms));
+	log!(Level::Info, "testing {}: {} blinks (active_high={}, max_brightness={})",
+		cfg.led_path, TEST_BLINKS, cfg.active_high, led.max_brightness());
+	
+	for _ in 0..TEST_BLINKS {
+		led.on()?;
+		pause(TEST_BLINK_MS);
+		led.off()?;
+		pause(TEST_BLINK_MS);
+	}
+	
+	if led.max_brightness() > 1 {
+		log!(Level::Info, "sweeping brightness 0-{}", led.max_brightness());
+		sweep_level(led.as_mut(), TEST_SWEEP_STEPS, TEST_BLINK_MS / 4)?;
+	}
+	led.off()?;
+	log!(Level::Info, "done; if nothing blinked, check led_path, or try toggling active_high if it was lit between blinks");
+	Ok(())
+}
+
+/// Ramp a dimmable LED from 0 up to max_brightness and back down to 0 in
+/// `steps` steps each way, sleeping `step_ms` between writes
+fn sweep_level(led: &mut dyn LedBackend, steps: u32, step_ms: u64) -> io::Result<()> {
+	for i in (0..=steps).chain((0..steps).rev()) {
+		led.set_level(led.max_brightness() * i / steps)?;
+		std::thread::sleep(Duration::from_millis(step_ms));
+	}
+	Ok(())
+}
+
+/// Show that the daemon is up (startup_blink), before the event loop starts
 /// 
-/// This allows precise control of LED on-duration while maintaining efficient
-/// polling. The LED stays on as long as activity continues (each activity
-/// event resets the off timer).
-fn main() -> io::Result<()> {
-	// Load configuration from file and CLI arguments
-	let cfg = parse_args();
+/// Dimmable LEDs ramp up and down; on/off ones blink STARTUP_BLINKS times,
+/// which reads differently from activity. Blocking, but well under a second.
+/// Leaves the LED off.
+fn startup_blink(led: &mut dyn LedBackend) -> io::Result<()> {
+	if led.max_brightness() > 1 {
+		sweep_level(led, STARTUP_RAMP_STEPS, STARTUP_RAMP_STEP_MS)?;
+	} else {
+		for _ in 0..STARTUP_BLINKS {
+			led.on()?;
+			std::thread::sleep(Duration::from_millis(STARTUP_BLINK_MS));
+			led.off()?;
+			std::thread::sleep(Duration::from_millis(STARTUP_BLINK_MS));
+		}
+	}
+	led.off()
+}
 
-	// Set up epoll for event-driven I/O
-	// This allows us to wait on multiple timers efficiently
-	let ep = Epoll::new()?;
+/// Leave the LED in the configured panic state before a fatal exit
+/// 
+/// The daemon can't blink anything once it's gone, so the strobe is handed
+/// to the kernel: the LED class `timer` trigger next to `brightness` keeps
+/// blinking after we exit. LEDs without a trigger file (enclosure slots,
+/// plain files) get solid on instead.
+fn apply_panic_led(cfg: &Config) -> io::Result<()> {
+	if cfg.panic_led == PanicLed::None || cfg.dry_run {
+		return Ok(());
+	}
 	
-	// Create two timers:
-	// 1. Periodic timer for polling NVMe stats at regular intervals
-	let poll_tfd = Tfd::periodic(cfg.poll_ms)?;
+	if cfg.panic_led == PanicLed::Strobe
+		&& let Some(dir) = std::path::Path::new(&cfg.led_path).parent()
+		&& dir.join("trigger").exists() {
+		std::fs::write(dir.join("trigger"), "timer")?;
+		std::fs::write(dir.join("delay_on"), PANIC_STROBE_MS.to_string())?;
+		std::fs::write(dir.join("delay_off"), PANIC_STROBE_MS.to_string())?;
+		return Ok(());
+	}
 	
-	// 2. One-shot timer for turning LED off after blink duration
-	//	  Created disarmed; we arm it when activity is detected
-	let off_tfd = Tfd::oneshot()?;
+	Led::new(&cfg.led_path, cfg.active_high)?.on()
+}
 
-	// Tags to identify which timer fired in epoll events
-	// These are arbitrary u64 values we use to distinguish the timers
-	const POLL_TAG: u64 = 1;  // Poll timer identifier
-	const OFF_TAG: u64 = 2;   // Off timer identifier
+/// A per-direction LED (read_led_path / write_led_path) with its own off-timer
+/// 
+/// The main LED keeps the original off_tfd; these blink independently, so a
+/// read LED can go dark while the write LED is still lit.
+struct BlinkLed {
+	led: Box<dyn LedBackend>, // The LED itself
+	off: Tfd,				  // One-shot off-timer for this LED only
+	lit: bool,				  // Whether we currently have it on
+	lit_at: Option<Instant>,  // When it last went on (for min_on_ms)
+	off_at: Option<Instant>,  // When it last went off (for min_off_ms)
+}
 
-	// Register both timers with epoll
-	// EPOLLIN means we want to be notified when the fd is readable
-	// (timerfds become readable when they expire)
-	ep.add_fd(poll_tfd.0, POLL_TAG, libc::EPOLLIN as u32)?;
-	ep.add_fd(off_tfd.0, OFF_TAG, libc::EPOLLIN as u32)?;
+impl BlinkLed {
+	/// Take an opened LED (turning it off) and create its disarmed off-timer
+	fn new(mut led: Box<dyn LedBackend>) -> io::Result<Self> {
+		led.off()?;
+		Ok(Self { led, off: Tfd::oneshot()?, lit: false, lit_at: None, off_at: None })
+	}
+}
 
-	// Initialize LED controller and NVMe monitor
-	let mut led = Led::new(&cfg.led_path, cfg.active_high)?;
-	let mut nvme = Nvme::new(&cfg.nvme_path, cfg.nvme_mode);
+/// An LED exposed as separate red/green/blue brightness files
+/// 
+/// Colors are given as 0-255 per channel and scaled to each channel's
+/// max_brightness (255 if the driver doesn't say). Like `Led::set`, each
+/// channel caches what it last wrote and skips redundant writes.
+struct RgbLed {
+	channels: [File; 3],	  // Open red, green, blue brightness files
+	max: [u32; 3],			  // Each channel's max_brightness
+	current: [Option<u32>; 3],  // Last value written per channel (None = unknown)
+}
 
-	// Buffers for epoll events and file reads
-	// We only have 2 timers, so we only need space for 2 events
-	let mut events = [libc::epoll_event { events: 0, u64: 0 }; 2];
+impl RgbLed {
+	/// Open the three channel files (red, green, blue order)
+	fn new(paths: [&str; 3]) -> io::Result<Self> {
+		let open = |p: &str| OpenOptions::new().write(true).open(p)
+			.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", p, e)));
+		let max = |p: &str| std::path::Path::new(p).parent()
+			.and_then(|d| std::fs::read_to_string(d.join("max_brightness")).ok())
+			.and_then(|v| v.trim().parse::<u32>().ok())
+			.unwrap_or(255);
+		Ok(Self {
+			channels: [open(paths[0])?, open(paths[1])?, open(paths[2])?],
+			max: [max(paths[0]), max(paths[1]), max(paths[2])],
+			current: [None; 3],
+		})
+	}
 	
-	// Buffer for timer acknowledgment reads (timerfds return u64)
-	let mut tbuf = [0u8; 8];
+	/// Show a color, writing only the channels whose value changes
+	fn set_color(&mut self, r: u8, g: u8, b: u8) -> io::Result<()> {
+		for (i, c) in [r, g, b].into_iter().enumerate() {
+			let want = c as u32 * self.max[i] / 255;
+			if self.current[i] == Some(want) {
+				continue;
+			}
+			self.channels[i].write_all(format!("{}\n", want).as_bytes())?;
+			self.current[i] = Some(want);
+		}
+		Ok(())
+	}
+}
+
+/// Both colors at once, for a poll that saw reads and writes: the
+/// channel-wise sum, saturating (green + red = yellow)
+fn blend_colors(a: (u8, u8, u8), b: (u8, u8, u8)) -> (u8, u8, u8) {
+	(a.0.saturating_add(b.0), a.1.saturating_add(b.1), a.2.saturating_add(b.2))
+}
+
+/// Parse a `#rrggbb` color into its channels
+fn parse_color(s: &str) -> Option<(u8, u8, u8)> {
+	let hex = s.trim().strip_prefix('#')?;
+	if hex.len() != 6 {
+		return None;
+	}
+	let ch = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+	Some((ch(0)?, ch(2)?, ch(4)?))
+}
+
+/// Pick an LED when the default (a ThinkPad's) doesn't exist
+/// 
+/// Every LED in /sys/class/leds whose `brightness` we can open for writing
+/// is a candidate; names containing LED_KEYWORDS win, in keyword order, then
+/// alphabetical. The choice and the alternatives are logged. With nothing
+/// writable the error lists what was found, so the user can pick one.
+fn detect_led_path() -> io::Result<String> {
+	// No LED class at all is reported like an empty one
+	let entries = std::fs::read_dir(LEDS_ROOT).into_iter().flatten().flatten();
+	let mut found: Vec<(usize, String, bool)> = entries
+		.map(|e| {
+			let name = e.file_name().to_string_lossy().into_owned();
+			let rank = LED_KEYWORDS.iter().position(|k| name.contains(k)).unwrap_or(LED_KEYWORDS.len());
+			let writable = OpenOptions::new().write(true).open(e.path().join("brightness")).is_ok();
+			(rank, name, writable)
+		})
+		.collect();
+	found.sort();
 	
-	// Buffer for reading NVMe stat file (256 bytes is plenty)
-	let mut sbuf = [0u8; 256];
-
-	// Track LED state to avoid redundant operations
-	// This is redundant with Led::current_logical but makes the logic clearer
-	let mut led_on = false;
-
-	// Print startup message unless quiet mode
-	// This helps with debugging and confirms the daemon started successfully
-	if !cfg.quiet {
-		println!(
-			"nvme-led-daemon: led={} nvme={} interval={}ms blink={}ms read_blink={:?} write_blink={:?} active_high={} mode={:?} on_fields={:?} (pid={})",
-			cfg.led_path,			// LED sysfs path
-			cfg.nvme_path,			// NVMe stat file path
-			cfg.poll_ms,			// Polling interval
-			cfg.blink_ms,			// Default blink duration
-			cfg.read_blink_ms,		// Read-specific blink duration (if set)
-			cfg.write_blink_ms,		// Write-specific blink duration (if set)
-			cfg.active_high,		// LED polarity
-			match cfg.nvme_mode {	// Which stat fields we're monitoring
-				NvmeMode::Sectors => "sectors", 
-				NvmeMode::Io => "io" 
-			},
-			match cfg.on_fields {	// Which operations trigger LED
-				FieldsSel::Reads => "reads", 
-				FieldsSel::Writes => "writes", 
-				FieldsSel::Both => "both" 
-			},
-			std::process::id()		// Our PID (useful for systemd, etc.)
-		);
+	let Some((_, best, _)) = found.iter().find(|(_, _, w)| *w) else {
+		let list = found.iter().map(|(_, n, _)| n.as_str()).collect::<Vec<_>>().join(", ");
+		return Err(io::Error::new(io::ErrorKind::NotFound, format!(
+			"{} not found and no writable LED under {} (found: {}); set led_path (--led PATH), \
+			 and run as root or grant write access to the LED's brightness file",
+			DEFAULT_LED_PATH, LEDS_ROOT, if list.is_empty() { "none" } else { &list })));
+	};
+	let others: Vec<&str> = found.iter().filter(|(_, n, w)| *w && n != best).map(|(_, n, _)| n.as_str()).collect();
+	log!(Level::Info, "{} not found; using LED {} (also writable: {})",
+		DEFAULT_LED_PATH, best, if others.is_empty() { "none".to_string() } else { others.join(", ") });
+	Ok(format!("{}/{}/brightness", LEDS_ROOT, best))
+}
+
+/// Print a table of the LEDs in /sys/class/leds (--list-leds)
+/// 
+/// Read-only: the writable column comes from access(2), not from opening
+/// the brightness file.
+fn list_leds() -> io::Result<()> {
+	let mut names: Vec<String> = std::fs::read_dir(LEDS_ROOT).into_iter().flatten().flatten()
+		.map(|e| e.file_name().to_string_lossy().into_owned())
+		.collect();
+	names.sort();
+	let width = names.iter().map(String::len).max().unwrap_or(0).max(4);
+	println!("{:width$}  {:>4}  {:>10}  WRITABLE  PATH", "NAME", "MAX", "BRIGHTNESS", width = width);
+	for name in &names {
+		let dir = std::path::Path::new(LEDS_ROOT).join(name);
+		let read = |f: &str| std::fs::read_to_string(dir.join(f)).map(|v| v.trim().to_string())
+			.unwrap_or_else(|_| "?".to_string());
+		let path = dir.join("brightness");
+		let writable = std::ffi::CString::new(path.to_string_lossy().as_bytes())
+			.is_ok_and(|c| unsafe { libc::access(c.as_ptr(), libc::W_OK) } == 0);
+		println!("{:width$}  {:>4}  {:>10}  {:8}  {}", name, read("max_brightness"), read("brightness"),
+			if writable { "yes" } else { "no" }, path.display(), width = width);
+	}
+	if names.is_empty() {
+		println!("(no LEDs under {})", LEDS_ROOT);
 	}
+	Ok(())
+}
 
-	// Ensure LED starts in off state
-	// Ignore errors here (LED might already be off)
-	let _ = led.off();
+/// Hand the LED to a kernel trigger, if the kernel offers it
+/// 
+/// The `trigger` file beside `brightness` lists every available trigger,
+/// with the active one in [brackets]. Returns false (and writes nothing)
+/// when `name` isn't in the list, so the caller can fall back to polling.
+fn set_kernel_trigger(led_path: &str, name: &str) -> io::Result<bool> {
+	let file = std::path::Path::new(led_path).parent()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: no LED directory", led_path)))?
+		.join("trigger");
+	let list = std::fs::read_to_string(&file)?;
+	let available = list.split_whitespace()
+		.any(|t| t.trim_start_matches('[').trim_end_matches(']') == name);
+	if available {
+		std::fs::write(&file, name)?;
+	}
+	Ok(available)
+}
 
-	// Main event loop - runs forever until killed
-	loop {
-		// Wait for timer events (blocks until at least one timer expires)
-		// This is efficient: the process sleeps and the kernel wakes it
-		// when a timer fires. No busy-waiting or polling.
-		let n = ep.wait(&mut events)?;
-		
-		// Process all events that occurred
-		// Usually n=1 (one timer fired), but could be 2 if both fired
-		// between epoll_wait calls (unlikely but possible)
-		for i in 0..n {
-			// Extract the tag we set when registering the fd
-			// This tells us which timer fired
-			let tag = events[i].u64;
+/// Resolve an enclosure slot to the LED attribute file that controls it
+/// 
+/// Server backplanes expose per-slot LEDs under `/sys/class/enclosure`
+/// rather than stable `/sys/class/leds` names. `spec` is either `SLOT` (any
+/// enclosure) or `ENCLOSURE/SLOT`, where SLOT matches a component's `slot`
+/// number or its directory name. `attr` picks the LED (`active`, `locate`
+/// or `fault`); the resolved file accepts 0/1 just like `brightness`.
+fn resolve_enclosure_slot(root: &std::path::Path, spec: &str, attr: &str) -> io::Result<String> {
+	let not_found = |why: String| io::Error::new(io::ErrorKind::NotFound, 
+		format!("enclosure slot {:?}: {}", spec, why));
+	
+	let (enc, slot) = match spec.rsplit_once('/') {
+		Some((e, sl)) => (Some(e), sl),
+		None => (None, spec),
+	};
+	
+	// Candidate enclosures: the named one, or every one present
+	let enclosures: Vec<std::path::PathBuf> = match enc {
+		Some(e) => vec![root.join(e)],
+		None => std::fs::read_dir(root)
+			.map_err(|e| not_found(format!("cannot list {}: {}", root.display(), e)))?
+			.flatten().map(|e| e.path()).collect(),
+	};
+	
+	let want_num = slot.trim().parse::<u32>().ok();
+	for enc_dir in enclosures {
+		let Ok(components) = std::fs::read_dir(&enc_dir) else { continue };
+		for comp in components.flatten() {
+			let dir = comp.path();
+			let num = std::fs::read_to_string(dir.join("slot")).ok()
+				.and_then(|v| v.trim().parse::<u32>().ok());
 			
-			match tag {
-				POLL_TAG => {
-					// Polling timer fired - time to check for NVMe activity
-					
-					// First, acknowledge the timer to clear its readable state
-					// This prevents epoll from immediately triggering again
-					poll_tfd.ack(&mut tbuf);
-					
-					// Check if there's been any disk activity since last poll
-					// Returns Some(Dir) if activity detected, None otherwise
-					if let Some(dir) = nvme.activity_dir(&mut sbuf)? {
-						// Activity detected! Determine if we should blink for it
-						// based on the on_fields filter
-						let relevant = match (cfg.on_fields, dir) {
-							(FieldsSel::Both, _) => true,			   // Both: always relevant
-							(FieldsSel::Reads, Dir::Read) => true,	   // Reads only: relevant if read
-							(FieldsSel::Writes, Dir::Write) => true,   // Writes only: relevant if write
-							_ => false,									// Filtered out
-						};
-						
-						if relevant {
-							// Determine blink duration
-							// Start with default, then check for direction-specific override
-							let mut dur = cfg.blink_ms;
-							
-							// Override with read-specific duration if set
-							if dir == Dir::Read { 
-								if let Some(r) = cfg.read_blink_ms { 
-									dur = r; 
-								} 
-							}
-							
-							// Override with write-specific duration if set
-							if dir == Dir::Write { 
-								if let Some(w) = cfg.write_blink_ms { 
-									dur = w; 
-								} 
-							}
-
-							// Turn LED on if not already on
-							// The LED::on() method will skip the write if already on
-							if !led_on { 
-								led.on()?; 
-								led_on = true; 
-							}
-							
-							// Schedule LED turn-off after blink duration
-							// If the timer is already armed (from previous activity),
-							// this resets it to the new duration. This is how we
-							// extend the LED blink on continuous activity: each new
-							// activity event pushes the off-time further into the future.
-							off_tfd.arm_after_ms(dur)?;
-						}
-					}
-				}
-				
-				OFF_TAG => {
-					// Off-timer fired - time to turn LED off
-					
-					// Acknowledge the timer to clear its readable state
-					off_tfd.ack(&mut tbuf);
-					
-					// Turn LED off if it's currently on
-					// The LED::off() method will skip the write if already off
-					if led_on {
-						led.off()?;
-						led_on = false;
-					}
-				}
-				
-				_ => {
-					// Unknown tag (shouldn't happen with our setup)
-					// We only registered two fds with specific tags
-					// If we get here, something is very wrong
+			// Only component dirs have a `slot` file
+			let Some(num) = num else { continue };
+			if want_num == Some(num) || comp.file_name().to_string_lossy() == slot {
+				let led = dir.join(attr);
+				if !led.exists() {
+					return Err(not_found(format!("{} has no {} attribute", dir.display(), attr)));
 				}
+				return Ok(led.to_string_lossy().into_owned());
+			}
+		}
+	}
+	Err(not_found(format!("no matching slot under {}", root.display())))
+}
+
+/// Notification source for firmware-initiated LED brightness changes
+/// 
+/// LED drivers that can change brightness on their own (e.g. a hotkey
+/// handled in firmware) expose `brightness_hw_changed` next to `brightness`.
+/// It signals POLLPRI when that happens, which is a precise, wakeup-free
+/// alternative to periodic reconciliation.
+struct HwChangedWatch {
+	f: File,				  // Open handle to brightness_hw_changed
+}
+
+impl HwChangedWatch {
+	/// Open `brightness_hw_changed` beside the given brightness file
+	/// Returns None when the driver doesn't provide it
+	fn open(brightness_path: &str) -> Option<Self> {
+		let p = std::path::Path::new(brightness_path).parent()?.join("brightness_hw_changed");
+		let mut w = Self { f: File::open(p).ok()? };
+		w.drain();
+		Some(w)
+	}
+	
+	/// File descriptor to register with epoll (EPOLLPRI)
+	fn fd(&self) -> RawFd {
+		use std::os::unix::io::AsRawFd;
+		self.f.as_raw_fd()
+	}
+	
+	/// Re-read from offset 0 to clear the pending notification
+	/// The read itself fails with ENODATA until the first hardware change,
+	/// which is fine: sysfs re-arms the notification either way.
+	fn drain(&mut self) {
+		let mut buf = [0u8; 16];
+		unsafe { libc::pread(self.fd(), buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+	}
+}
+
+/// Change notification for the stat files, where sysfs provides it
+/// 
+/// A sysfs attribute signals POLLPRI when its driver calls sysfs_notify().
+/// Block device `stat` files don't on current kernels, so this is
+/// opportunistic: timer polling carries on until a notification actually
+/// arrives, and only then do changes drive the polls instead of the clock.
+struct StatWatch {
+	files: Vec<File>,		  // One open handle per stat file
+}
+
+impl StatWatch {
+	/// Open every monitor's stat file and register it with epoll (EPOLLPRI)
+	/// Returns None if any of them can't be watched
+	fn open(nvmes: &[Nvme], ep: &Epoll, tag: u64) -> Option<Self> {
+		use std::os::unix::io::AsRawFd;
+		let mut w = Self { files: Vec::new() };
+		for n in nvmes {
+			let f = File::open(&n.path).ok()?;
+			ep.add_fd(f.as_raw_fd(), tag, libc::EPOLLPRI as u32).ok()?;
+			w.files.push(f);
+		}
+		w.drain();
+		Some(w)
+	}
+	
+	/// Re-read every file from offset 0 to clear pending notifications
+	fn drain(&mut self) {
+		use std::os::unix::io::AsRawFd;
+		let mut buf = [0u8; STAT_BUF_LEN];
+		for f in &self.files {
+			unsafe { libc::pread(f.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+		}
+	}
+}
+
+// ============================================================================
+// BLINK PATTERNS: Timed on/off sequences independent of disk activity
+// ============================================================================
+
+/// Plays a fixed on/off sequence on the LED, one step per timer expiry
+/// 
+/// Steps are durations in milliseconds, alternating on, off, on, ... starting
+/// with on. While a pattern plays it owns the LED: activity blinks are held
+/// back so the pattern stays recognizable. The LED is left off at the end.
+struct Pattern {
+	tfd: Tfd,				  // One-shot timer advancing to the next step
+	steps: Vec<u64>,		  // Step durations (even index = on, odd = off)
+	pos: Option<usize>,		  // Current step, or None when idle
+}
+
+impl Pattern {
+	/// Create an idle pattern player with its (disarmed) step timer
+	fn new() -> io::Result<Self> {
+		Ok(Self { tfd: Tfd::oneshot()?, steps: Vec::new(), pos: None })
+	}
+	
+	/// True while a pattern is in progress
+	#[inline(always)]
+	fn playing(&self) -> bool {
+		self.pos.is_some()
+	}
+	
+	/// Stop mid-pattern, leaving the LED as it is for the caller
+	fn cancel(&mut self) -> io::Result<()> {
+		self.pos = None;
+		self.tfd.arm_after_ms(0)  // Zero disarms
+	}
+	
+	/// Start playing `steps` from the beginning (restarts if already playing)
+	fn start(&mut self, steps: &[u64], led: &mut dyn LedBackend) -> io::Result<()> {
+		self.steps = steps.to_vec();
+		self.pos = Some(0);
+		self.apply(led)
+	}
+	
+	/// Step timer fired: move on to the next step
+	fn advance(&mut self, led: &mut dyn LedBackend, buf8: &mut [u8; 8]) -> io::Result<()> {
+		self.tfd.ack(buf8);
+		if let Some(i) = self.pos {
+			self.pos = Some(i + 1);
+			self.apply(led)?;
+		}
+		Ok(())
+	}
+	
+	/// Write the LED state for the current step and arm its timer
+	fn apply(&mut self, led: &mut dyn LedBackend) -> io::Result<()> {
+		let Some(i) = self.pos else { return Ok(()) };
+		if i >= self.steps.len() {
+			self.pos = None;
+			return led.off();
+		}
+		led.set(i % 2 == 0)?;
+		self.tfd.arm_after_ms(self.steps[i].max(1))
+	}
+}
+
+/// Software PWM for LEDs that only take 0/1
+/// 
+/// While a blink is in progress the LED is toggled on a one-shot timer,
+/// on for `duty`% of each period and off for the rest, which reads as a
+/// dimmer light. At 100% it's simply left on.
+struct Pwm {
+	tfd: Tfd,				  // One-shot timer for the next on/off edge
+	period_us: u64,			  // 1 / pwm_freq_hz
+	duty: u64,				  // Percentage of each period spent on (1-100)
+	running: bool,			  // Currently driving the LED
+	high: bool,				  // LED is in the on part of the period
+}
+
+impl Pwm {
+	/// Create an idle PWM driver at `freq_hz` (disarmed)
+	fn new(freq_hz: u64) -> io::Result<Self> {
+		Ok(Self { tfd: Tfd::oneshot()?, period_us: 1_000_000 / freq_hz.max(1), duty: 100, running: false, high: false })
+	}
+	
+	/// Start (or re-duty) the PWM; a new duty applies from the next edge
+	fn start(&mut self, duty: u64, led: &mut dyn LedBackend) -> io::Result<()> {
+		self.duty = duty.clamp(1, 100);
+		if self.running {
+			return Ok(());
+		}
+		self.running = true;
+		self.high = true;
+		led.on()?;
+		self.arm()
+	}
+	
+	/// Stop driving the LED (the caller decides its final state)
+	fn stop(&mut self) -> io::Result<()> {
+		self.running = false;
+		self.tfd.arm_after_us(0)  // Zero disarms
+	}
+	
+	/// Edge timer fired: flip the LED and arm the next edge
+	fn tick(&mut self, led: &mut dyn LedBackend, buf8: &mut [u8; 8]) -> io::Result<()> {
+		if self.tfd.ack(buf8) == 0 || !self.running {
+			return Ok(());
+		}
+		self.high = !self.high || self.duty >= 100;
+		led.set(self.high)?;
+		self.arm()
+	}
+	
+	/// Arm the timer for the length of the current half of the period
+	fn arm(&self) -> io::Result<()> {
+		if self.duty >= 100 {
+			return Ok(());  // Solid: no edges until the duty drops
+		}
+		let on_us = self.period_us * self.duty / 100;
+		self.tfd.arm_after_us(if self.high { on_us } else { self.period_us - on_us }.max(1))
+	}
+}
+
+/// Fade-out for dimmable LEDs: steps the level down to 0 on a periodic timer
+/// instead of a single write, and can be cancelled if activity resumes
+struct Fade {
+	tfd: Tfd,				  // Periodic step timer while fading, else disarmed
+	curve: FadeCurve,		  // How the level falls over the steps
+	from: u32,				  // Level at the start of the fade
+	step: u32,				  // Steps taken so far
+	steps: u32,				  // Steps in the whole fade (0 = not fading)
+}
+
+impl Fade {
+	/// Create an idle fader
+	fn new(curve: FadeCurve) -> io::Result<Self> {
+		Ok(Self { tfd: Tfd::oneshot()?, curve, from: 0, step: 0, steps: 0 })
+	}
+	
+	/// True while a fade is in progress
+	#[inline(always)]
+	fn active(&self) -> bool {
+		self.steps > 0
+	}
+	
+	/// Begin fading from `from` to 0 over `fade_ms`
+	fn start(&mut self, from: u32, fade_ms: u64) -> io::Result<()> {
+		self.from = from;
+		self.step = 0;
+		self.steps = (fade_ms / FADE_STEP_MS).max(1) as u32;
+		self.tfd.set_periodic(FADE_STEP_MS, FADE_STEP_MS)
+	}
+	
+	/// Stop fading, leaving the LED at whatever level it reached
+	fn cancel(&mut self) -> io::Result<()> {
+		self.steps = 0;
+		self.tfd.arm_after_ms(0)  // Zero disarms (and clears the interval)
+	}
+	
+	/// Step timer fired: write the next level, finishing at 0
+	fn tick(&mut self, led: &mut dyn LedBackend, buf8: &mut [u8; 8]) -> io::Result<()> {
+		self.tfd.ack(buf8);
+		if !self.active() {
+			return Ok(());
+		}
+		self.step += 1;
+		if self.step >= self.steps {
+			self.cancel()?;
+			return led.set_level(0);
+		}
+		let left = 1.0 - self.step as f64 / self.steps as f64;
+		let scale = match self.curve {
+			FadeCurve::Linear => left,
+			FadeCurve::Exp => (left * 5.0).exp_m1() / 5f64.exp_m1(),
+		};
+		led.set_level((self.from as f64 * scale).round() as u32)
+	}
+}
+
+/// PWM duty for one poll: `base` at a trickle, rising to 100% at
+/// `full_scale_mb_s` (fixed at `base` if no full scale is configured)
+fn pwm_duty(base: u64, delta_sectors: u128, poll_ms: u64, full_scale_mb_s: Option<u64>) -> u64 {
+	let Some(full) = full_scale_mb_s else { return base };
+	let bytes_per_s = delta_sectors * SECTOR_BYTES as u128 * 1000 / poll_ms.max(1) as u128;
+	let frac = (bytes_per_s * 100 / (full.max(1) as u128 * 1_000_000)).min(100) as u64;
+	base + (100 - base.min(100)) * frac / 100
+}
+
+/// Parse a comma-separated list of step durations ("60,60,60")
+/// Returns None if empty or any entry isn't a number
+fn parse_pattern(s: &str) -> Option<Vec<u64>> {
+	let steps: Option<Vec<u64>> = s.split(',').map(parse_ms).collect();
+	steps.filter(|v| !v.is_empty())
+}
+
+// ============================================================================
+// MOUNT WATCHER: Notices filesystems being mounted or unmounted
+// ============================================================================
+
+/// Watches /proc/self/mounts for changes
+/// 
+/// The kernel flags the open mounts file with POLLPRI|POLLERR whenever the
+/// mount table changes, so it can sit in our epoll set with no polling. The
+/// flag is cleared by re-reading the file from the start.
+struct MountWatch {
+	f: File,				  // Open handle to /proc/self/mounts
+	last_fired: Option<Instant>,  // Last time we reacted (for throttling)
+}
+
+impl MountWatch {
+	/// Open the mounts file and consume its current contents
+	fn new() -> io::Result<Self> {
+		let mut w = Self { f: File::open("/proc/self/mounts")?, last_fired: None };
+		w.drain()?;
+		Ok(w)
+	}
+	
+	/// File descriptor to register with epoll (EPOLLPRI)
+	fn fd(&self) -> RawFd {
+		use std::os::unix::io::AsRawFd;
+		self.f.as_raw_fd()
+	}
+	
+	/// Re-read the whole file to clear the pending change notification
+	fn drain(&mut self) -> io::Result<()> {
+		use std::io::{Seek, SeekFrom};
+		let mut buf = [0u8; 4096];
+		self.f.seek(SeekFrom::Start(0))?;
+		while self.f.read(&mut buf)? > 0 {}
+		Ok(())
+	}
+	
+	/// Handle a change notification
+	/// 
+	/// # Returns
+	/// true if a pattern should be played, false if throttled
+	fn changed(&mut self, throttle_ms: u64) -> io::Result<bool> {
+		self.drain()?;
+		let now = Instant::now();
+		if self.last_fired.is_some_and(|t| now.duration_since(t) < Duration::from_millis(throttle_ms)) {
+			return Ok(false);
+		}
+		self.last_fired = Some(now);
+		Ok(true)
+	}
+}
+
+// ============================================================================
+// CONTROL SOCKET: Runtime status and commands over a Unix socket
+// ============================================================================
+
+/// Listening Unix socket for line commands (control_socket)
+/// 
+/// Each connection sends one or more newline-terminated commands, gets a
+/// one-line reply per command and is closed. Connections are served one at
+/// a time from the event loop; a client that doesn't finish its lines
+/// within CONTROL_TIMEOUT_MS is cut off so it can't stall the LED.
+struct ControlSocket {
+	listener: std::os::unix::net::UnixListener,  // Non-blocking listener
+	path: String,								 // Socket path (removed on drop)
+}
+
+impl ControlSocket {
+	/// Bind `path`, replacing a stale socket file left by a crashed run
+	/// (one that still accepts connections belongs to a live daemon)
+	fn bind(path: &str) -> io::Result<Self> {
+		use std::os::unix::net::{UnixListener, UnixStream};
+		if std::path::Path::new(path).exists() {
+			if UnixStream::connect(path).is_ok() {
+				return Err(io::Error::new(io::ErrorKind::AddrInUse,
+					format!("control socket {} is in use by another daemon", path)));
+			}
+			std::fs::remove_file(path)?;
+		}
+		let listener = UnixListener::bind(path)
+			.map_err(|e| io::Error::new(e.kind(), format!("control socket {}: {}", path, e)))?;
+		listener.set_nonblocking(true)?;
+		Ok(Self { listener, path: path.to_string() })
+	}
+	
+	/// File descriptor for epoll registration (readable = client waiting)
+	fn fd(&self) -> RawFd {
+		std::os::unix::io::AsRawFd::as_raw_fd(&self.listener)
+	}
+	
+	/// Accept a waiting client and read its command lines
+	/// 
+	/// # Returns
+	/// The connection (for the replies) and its non-empty lines, or None
+	/// if nobody was actually waiting
+	fn accept(&self) -> Option<(std::os::unix::net::UnixStream, Vec<String>)> {
+		let (mut stream, _) = self.listener.accept().ok()?;
+		let timeout = Some(Duration::from_millis(CONTROL_TIMEOUT_MS));
+		stream.set_nonblocking(false).ok()?;
+		stream.set_read_timeout(timeout).ok()?;
+		stream.set_write_timeout(timeout).ok()?;
+		
+		// Until the client's last line is complete (or it hangs up)
+		let mut buf = Vec::new();
+		let mut chunk = [0u8; 512];
+		while buf.len() < 4096 && !buf.ends_with(b"\n") {
+			match stream.read(&mut chunk) {
+				Ok(0) | Err(_) => break,
+				Ok(n) => buf.extend_from_slice(&chunk[..n]),
 			}
 		}
+		let lines = String::from_utf8_lossy(&buf).lines()
+			.map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect();
+		Some((stream, lines))
+	}
+}
+
+impl Drop for ControlSocket {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.path);
+	}
+}
+
+// ============================================================================
+// NVME ACTIVITY MONITOR: Detects disk I/O by polling stat file
+// ============================================================================
+
+/// NVMe activity monitor that reads /sys/block/nvme0n1/stat
+/// 
+/// The Linux kernel exposes disk statistics in /sys/block/*/stat with the format:
+/// Field  0: reads completed successfully
+/// Field  1: reads merged
+/// Field  2: sectors read
+/// Field  3: time spent reading (ms)
+/// Field  4: writes completed
+/// Field  5: writes merged
+/// Field  6: sectors written
+/// Field  7: time spent writing (ms)
+/// Field  8: I/Os currently in progress
+/// Field  9: time spent doing I/Os (ms)
+/// Field 10: weighted time spent doing I/Os (ms)
+/// 
+/// We monitor either fields 0&4 (I/O counts) or 2&6 (sector counts) and
+/// detect activity by comparing to previous values. In-flight mode also
+/// reads field 8 and reports activity for as long as it is non-zero; busy
+/// mode reads field 9 and reports activity while its delta is.
+/// 
+/// The counters themselves come from an `ActivitySource` (the stat files
+/// via `SysfsSource`); this struct only turns them into activity.
+struct Nvme {
+	path: String,		  // Path to stat file (e.g., /sys/block/nvme0n1/stat)
+	name: String,		  // Friendly label used in output (e.g., nvme0n1 or "boot")
+	last_reads: u128,	  // Previous read counter value (u128 to avoid overflow)
+	last_writes: u128,	  // Previous write counter value
+	last_discards: u128,  // Previous discard counter value (0 unless tracked)
+	last_delta: u128,	  // Reads+writes increase seen by the latest poll
+	last_read_delta: u128,   // Read counter increase seen by the latest poll
+	last_write_delta: u128,  // Write counter increase seen by the latest poll
+	last_depth: u128,	  // I/Os in flight at the latest poll (in-flight mode only)
+	last_dir: Dir,		  // Latest completed direction (labels in-flight/busy activity)
+	last_ticks: u128,	  // Previous io_ticks value (busy mode only)
+	ticks_at: Option<Instant>,  // When last_ticks was read (None = not yet)
+	last_busy: u64,		  // Busy percentage over the latest poll (busy mode only)
+	last_mixed: bool,	  // Reads and writes both moved in the latest poll
+	primed: bool,		  // A baseline has been read (deltas are meaningful)
+	mode: NvmeMode,		  // Which fields to monitor (sectors vs I/O count)
+	source: Box<dyn ActivitySource>,	// Where the counters are read from
+	min_read: u128,						// Smallest read delta that counts (mode units)
+	min_write: u128,					// Smallest write delta that counts (mode units)
+	alpha: f64,							// EMA weight of the newest delta (1.0 = no smoothing)
+	ema_read: f64,						// Smoothed per-poll read delta
+	ema_write: f64,						// Smoothed per-poll write delta
+	ema: f64,							// Smoothed reads+writes delta (for brightness/PWM)
+	retry_at: Option<Instant>,			// Unavailable: next read attempt (None = readable)
+	retry_ms: u64,						// Current retry backoff while unavailable
+	rebase: bool,						// Adopt the next read as baseline (after a resume)
+}
+
+impl Nvme {
+	/// Create a new NVMe monitor
+	/// 
+	/// # Arguments
+	/// * `path` - Path to the stat file
+	/// * `name` - Label for output; None derives it from the path
+	/// * `mode` - Which counters to monitor (Sectors, Io, InFlight or Busy)
+	/// * `source` - Where the counters are read from
+	fn new(path: &str, name: Option<&str>, mode: NvmeMode, source: Box<dyn ActivitySource>) -> Self {
+		Self { 
+			path: path.to_string(), 
+			name: name.map(str::to_string).unwrap_or_else(|| device_name_from_path(path)),
+			last_reads: 0,		// Start with zero (first poll will show activity)
+			last_writes: 0, 
+			last_discards: 0,
+			last_delta: 0,
+			last_read_delta: 0,
+			last_write_delta: 0,
+			last_depth: 0,
+			last_dir: Dir::Write,
+			last_ticks: 0,
+			ticks_at: None,
+			last_busy: 0,
+			last_mixed: false,
+			primed: false,
+			mode,
+			source,
+			min_read: 0,
+			min_write: 0,
+			alpha: 1.0,
+			ema_read: 0.0,
+			ema_write: 0.0,
+			ema: 0.0,
+			retry_at: None,
+			retry_ms: REOPEN_BACKOFF_MIN_MS,
+			rebase: false,
+		}
+	}
+	
+	/// Take the next read as the new baseline without reporting activity
+	/// (counters may have moved across a suspend that we couldn't see)
+	fn rebaseline(&mut self) {
+		self.rebase = true;
 	}
 	
-	// Note: we never reach here (infinite loop above)
-	// If we did, Rust's Drop implementations would clean up:
-	// - Epoll::drop() closes epoll fd
-	// - Tfd::drop() closes both timerfd fds
-	// - File in Led is automatically closed
+	/// Check for disk activity by reading stat file and comparing to previous values
+	/// 
+	/// This is called on every poll timer tick. It:
+	/// 1. Opens and reads the stat file
+	/// 2. Parses the relevant counter fields
+	/// 3. Compares to previous values to detect changes
+	/// 4. Returns the direction of activity (read/write) or None if no activity
+	/// 
+	/// # Returns
+	/// * `Some(Dir::Read)` - Only read counter increased
+	/// * `Some(Dir::Write)` - Only write counter increased, or both increased
+	/// * `Some(Dir::Discard)` - Discard counter increased (tracked only when
+	///   on_fields asks for discards; wins over reads and writes)
+	/// * `None` - No activity detected
+	/// 
+	/// Note: If both counters increased, we report Write. This is arbitrary but
+	/// ensures we always report something when there's activity.
+	/// 
+	/// In in-flight mode the answer is instead "is anything queued": Some
+	/// while the depth (left in `last_depth`) is above zero, labelled with
+	/// the direction that last completed, since the queue doesn't say.
+	/// Busy mode likewise reports activity while the busy percentage (left
+	/// in `last_busy`) is above zero.
+	fn activity_dir(&mut self, scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<Dir>> {
+		self.last_delta = 0;
+		(self.last_read_delta, self.last_write_delta) = (0, 0);
+		self.last_depth = 0;
+		self.last_busy = 0;
+		self.last_mixed = false;
+		// An unreadable stat file (device suspended, enclosure spun down)
+		// counts as idle; back off instead of exiting. The very first read
+		// still fails hard, as that's likely a wrong path.
+		if self.retry_at.is_some_and(|at| Instant::now() < at) {
+			return Ok(None);
+		}
+		let counters = match self.source.read(scratch) {
+			Ok(c) => c,
+			Err(e) if !self.primed => return Err(e),
+			Err(e) => {
+				if self.retry_at.is_none() {
+					log!(Level::Warn, "{}: {} unavailable ({}); treating as idle", self.name, self.path, e);
+				} else {
+					self.retry_ms = (self.retry_ms * 2).min(REOPEN_BACKOFF_MAX_MS);
+				}
+				self.retry_at = Some(Instant::now() + Duration::from_millis(self.retry_ms));
+				return Ok(None);
+			}
+		};
+		
+		// Check if we successfully parsed both values
+		// If not, return None (file format unexpected, md member vanished)
+		let Some(((rn, wn, dn, qn), changed)) = counters else { 
+			return Ok(None); 
+		};
+		
+		// md membership changed: the sums jump without real activity, so
+		// silently adopt them as the new baseline
+		if changed {
+			(self.last_reads, self.last_writes, self.last_discards) = (rn, wn, dn);
+			(self.last_ticks, self.ticks_at) = (qn, Some(Instant::now()));
+			return Ok(None);
+		}
+		
+		// Back again: the counters may have moved (or reset) while we
+		// couldn't see them, so resume from here without a phantom delta
+		// (likewise after a system suspend)
+		let back = self.retry_at.take().is_some();
+		if back || std::mem::take(&mut self.rebase) {
+			if back {
+				log!(Level::Info, "{}: {} readable again", self.name, self.path);
+			}
+			self.retry_ms = REOPEN_BACKOFF_MIN_MS;
+			(self.last_reads, self.last_writes, self.last_discards) = (rn, wn, dn);
+			(self.last_ticks, self.ticks_at) = (qn, Some(Instant::now()));
+			(self.ema_read, self.ema_write, self.ema) = (0.0, 0.0, 0.0);
+			self.primed = true;
+			return Ok(None);
+		}
+		
+		// A counter going backwards means the device was removed and
+		// re-added (counters restart near zero): re-baseline silently
+		// rather than blink, or treat the drop as a huge delta
+		// (in-flight depth is a gauge and may fall; io_ticks may not)
+		let ticks_back = self.mode == NvmeMode::Busy && qn < self.last_ticks;
+		if self.primed && (rn < self.last_reads || wn < self.last_writes || dn < self.last_discards || ticks_back) {
+			log!(Level::Debug, "{}: counters reset, re-baselining", self.name);
+			(self.last_reads, self.last_writes, self.last_discards) = (rn, wn, dn);
+			(self.last_ticks, self.ticks_at) = (qn, Some(Instant::now()));
+			return Ok(None);
+		}
+		
+		let dr = rn.saturating_sub(self.last_reads);
+		let dw = wn.saturating_sub(self.last_writes);
+		
+		// The first read compares against zero, so it has no real delta
+		// (and mustn't seed the averages with the lifetime totals)
+		if self.primed {
+			self.last_delta = dr + dw;
+			(self.last_read_delta, self.last_write_delta) = (dr, dw);
+			self.ema_read = self.alpha * dr as f64 + (1.0 - self.alpha) * self.ema_read;
+			self.ema_write = self.alpha * dw as f64 + (1.0 - self.alpha) * self.ema_write;
+			self.ema = self.ema_read + self.ema_write;
+		}
+		
+		// Compare to previous values to detect changes
+		// Any change indicates activity, unless the (smoothed) increase
+		// doesn't exceed the direction's threshold (background trickle)
+		let (sr, sw) = if self.primed { (self.ema_read, self.ema_write) } else { (dr as f64, dw as f64) };
+		let rchg = rn != self.last_reads && sr > self.min_read as f64;
+		let wchg = wn != self.last_writes && sw > self.min_write as f64;
+		// Discards move no data, so they don't count toward the deltas or
+		// thresholds; any new discard counts
+		let dchg = dn != self.last_discards;
+		self.primed = true;
+		
+		// Update cached values for next comparison
+		// Important: do this before returning so next poll sees new baseline
+		self.last_reads = rn;
+		self.last_writes = wn;
+		self.last_discards = dn;
+		
+		// Determine activity direction based on which counter(s) changed
+		// Priority: if both changed, report as Write and flag the poll as
+		// mixed (mixed_priority may pick reads instead).
+		// Discards are rare and only tracked when asked for, so they win.
+		self.last_mixed = rchg && wchg && !dchg;
+		let dir = if dchg {
+			Some(Dir::Discard)
+		} else if rchg && !wchg { 
+			Some(Dir::Read)			 // Only reads increased
+		} else if wchg { 
+			Some(Dir::Write)		 // Only writes, or both (reported as write)
+		} else { 
+			None					 // No change detected
+		};
+		
+		// In-flight mode: busy exactly while something is queued; completions
+		// only decide which direction that is reported as
+		if self.mode == NvmeMode::InFlight {
+			if let Some(d) = dir {
+				self.last_dir = d;
+			}
+			self.last_depth = qn;
+			return Ok((qn > 0).then_some(self.last_dir));
+		}
+		
+		// Busy mode: share of the wall-clock time since the previous read
+		// that the device spent doing I/O. Overlapping I/O on a multi-queue
+		// device (or summed md members) can exceed 100%, so clamp.
+		if self.mode == NvmeMode::Busy {
+			if let Some(d) = dir {
+				self.last_dir = d;
+			}
+			let now = Instant::now();
+			if let Some(at) = self.ticks_at {
+				let elapsed_ms = now.duration_since(at).as_millis().max(1);
+				self.last_busy = (qn.saturating_sub(self.last_ticks) * 100 / elapsed_ms).min(100) as u64;
+			}
+			(self.last_ticks, self.ticks_at) = (qn, Some(now));
+			return Ok((self.last_busy > 0).then_some(self.last_dir));
+		}
+		Ok(dir)
+	}
+}
+
+/// Where a monitor's counters come from
+/// 
+/// The daemon reads the kernel's stat files (`SysfsSource`); keeping that
+/// behind a trait lets the delta, threshold and reset handling in
+/// `Nvme::activity_dir` run against scripted counters too.
+trait ActivitySource {
+	/// Read the current counters
+	/// 
+	/// # Returns
+	/// * `Ok(Some((counts, changed)))` - Counters for the monitor's mode;
+	///   `changed` is true when they aren't comparable with the previous
+	///   read (an md array's member set changed) and must be re-baselined
+	/// * `Ok(None)` - Nothing usable this time (unexpected format)
+	/// * `Err(_)` - The source couldn't be read at all
+	fn read(&mut self, scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<(Counts, bool)>>;
+	
+	/// All stat fields behind the latest read, in kernel order, if this
+	/// source captures them (for --record)
+	fn fields(&self) -> Option<Vec<u64>> {
+		None
+	}
+	
+	/// True once a finite source (a --replay capture) has nothing left
+	fn finished(&self) -> bool {
+		false
+	}
+}
+
+/// Counters read from a sysfs/procfs stat file, /proc/diskstats, or the
+/// summed stat files of an md array's members
+struct SysfsSource {
+	path: String,						// Stat file (or /proc/diskstats)
+	name: String,						// Monitor label, for warnings
+	mode: NvmeMode,						// Which fields to read
+	format: StatFormat,					// Fixed positions or header-named columns
+	discards: bool,						// Also read the discard counters
+	group: Option<MemberGroup>,			// Where members come from, when summing them
+	members: Vec<String>,				// Member stat paths (empty = read `path`)
+	members_scanned: Option<Instant>,	// Last member discovery (None = rescan now)
+	max_members: usize,					// Cap on members summed (max_devices)
+	capped: bool,						// Last scan hit the cap (warn once per episode)
+	diskstats_dev: Option<String>,		// Device name to find in /proc/diskstats
+	files: std::cell::RefCell<HashMap<String, File>>,	// Kept-open kernel stat files
+	captured: std::cell::RefCell<Option<Vec<u64>>>,	// Fields of the latest read (None = not recording)
+}
+
+impl SysfsSource {
+	/// Create a source reading `path` for `mode`'s counters
+	fn new(path: &str, name: &str, mode: NvmeMode) -> Self {
+		Self {
+			path: path.to_string(),
+			name: name.to_string(),
+			mode,
+			format: StatFormat::Indexed,
+			discards: false,
+			group: None,
+			members: Vec::new(),
+			members_scanned: None,
+			max_members: DEFAULT_MAX_DEVICES,
+			capped: false,
+			diskstats_dev: None,
+			files: std::cell::RefCell::new(HashMap::new()),
+			captured: std::cell::RefCell::new(None),
+		}
+	}
+	
+	/// Add one stat file's fields (kernel order) to the capture, if
+	/// recording; md members' fields are summed
+	fn capture(&self, fields: impl Iterator<Item = u64>) {
+		if let Some(sum) = self.captured.borrow_mut().as_mut() {
+			for (i, v) in fields.enumerate() {
+				match sum.get_mut(i) {
+					Some(f) => *f += v,
+					None => sum.push(v),
+				}
+			}
+		}
+	}
+	
+	/// Switch to summing the member disks of an md array
+	/// 
+	/// Instead of the md layer's own counters, activity is taken from the
+	/// sum of its members' stat files, so the LED reflects the underlying
+	/// disks (including resync/scrub traffic that never passes through md).
+	/// Members are re-discovered periodically to follow spares and rebuilds.
+	/// 
+	/// At most `max` members are summed; extras are ignored with a warning.
+	/// 
+	/// # Returns
+	/// Number of members found, or an error if `path` isn't an md array
+	fn aggregate_members(&mut self, max: usize) -> io::Result<usize> {
+		let dev_dir = std::path::Path::new(&self.path).parent()
+			.filter(|d| d.file_name().is_some_and(|n| n.to_string_lossy().starts_with("md")))
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+				format!("{} is not an md device stat file", self.path)))?
+			.to_path_buf();
+		
+		self.max_members = max;
+		self.members = self.capped_members(discover_md_members(&dev_dir));
+		if self.members.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::NotFound,
+				format!("no members found under {}", dev_dir.display())));
+		}
+		self.group = Some(MemberGroup::Md(dev_dir));
+		self.members_scanned = Some(Instant::now());
+		Ok(self.members.len())
+	}
+	
+	/// Switch to summing every namespace of an NVMe controller
+	/// 
+	/// `ctrl` is the controller name (`nvme0`); its namespaces (`nvme0n1`,
+	/// `nvme0n2`, ...) are found in /sys/block and re-discovered periodically,
+	/// like md members, so namespaces created or deleted later are followed.
+	/// Having none right now isn't an error: activity starts once one appears.
+	/// 
+	/// # Returns
+	/// Number of namespaces found
+	fn aggregate_controller(&mut self, ctrl: &str, max: usize) -> usize {
+		let group = MemberGroup::Controller(ctrl.to_string());
+		self.max_members = max;
+		self.members = self.capped_members(group.discover());
+		self.group = Some(group);
+		self.members_scanned = Some(Instant::now());
+		self.members.len()
+	}
+	
+	/// Truncate a discovered member list to `max_members`, warning once each
+	/// time the cap starts being hit
+	fn capped_members(&mut self, mut found: Vec<String>) -> Vec<String> {
+		let over = found.len() > self.max_members;
+		if over && !self.capped {
+			log!(Level::Warn, "{}: {} members found, monitoring only the first {} (max_devices)",
+				self.name, found.len(), self.max_members);
+		}
+		self.capped = over;
+		found.truncate(self.max_members);
+		found
+	}
+	
+	/// Read and parse the (read, write, discard, extra) counters for our
+	/// mode from one stat file
+	/// 
+	/// # Returns
+	/// * `Some((reads, writes, discards, extra))` - Read and write fields
+	///   were present (discards are 0 when not tracked or not reported by the
+	///   kernel; extra is in_flight or io_ticks, 0 in other modes)
+	/// * `None` - File format unexpected (too few fields)
+	fn read_counters(&self, path: &str, scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<Counts>> {
+		// /proc/diskstats: find our device's line; the same stat fields
+		// follow its major, minor and name columns. The file has a line
+		// per block device, so it doesn't fit the fixed scratch buffer.
+		if let Some(dev) = &self.diskstats_dev {
+			let mut all = String::new();
+			self.with_file(path, |mut f| {
+				use std::io::Seek;
+				f.seek(io::SeekFrom::Start(0))?;
+				f.read_to_string(&mut all)
+			})?;
+			let Some(line) = all.lines().find(|l| l.split_whitespace().nth(2) == Some(dev.as_str())) else {
+				return Ok(None);
+			};
+			self.capture(line.split_whitespace().skip(3).map(|t| t.parse().unwrap_or(0)));
+			return Ok(parse_indexed_counters(line.split_whitespace().skip(3), self.mode, self.discards));
+		}
+		
+		// Read the entire stat file into the buffer from offset 0
+		// (pread on the kept-open file, so one syscall per poll). A full
+		// buffer may have cut the last number in half: read on to the end.
+		let mut long = Vec::new();
+		let n = self.with_file(path, |f| {
+			use std::os::unix::fs::FileExt;
+			let n = f.read_at(scratch, 0)?;
+			if n == scratch.len() {
+				long.extend_from_slice(&scratch[..]);
+				let mut chunk = [0u8; STAT_BUF_LEN];
+				loop {
+					let m = f.read_at(&mut chunk, long.len() as u64)?;
+					if m == 0 {
+						break;
+					}
+					long.extend_from_slice(&chunk[..m]);
+				}
+			}
+			Ok(n)
+		})?;
+		
+		// Convert bytes to string for parsing
+		let bytes = if long.is_empty() { &scratch[..n] } else { &long[..] };
+		let s = std::str::from_utf8(bytes).unwrap_or("");
+		
+		// Header-described sources are looked up by column name instead
+		if self.format == StatFormat::Named {
+			if self.captured.borrow().is_some() {
+				self.capture(named_fields(s));
+			}
+			return Ok(parse_named_counters(s, self.mode, self.discards));
+		}
+		
+		// Parse whitespace-separated fields
+		self.capture(s.split_whitespace().map(|t| t.parse().unwrap_or(0)));
+		Ok(parse_indexed_counters(s.split_whitespace(), self.mode, self.discards))
+	}
+	
+	/// Run `read` on an open handle to `path`
+	/// 
+	/// sysfs and procfs regenerate a file's contents whenever it is read
+	/// from offset 0, so those are opened once and kept; a failed read
+	/// drops the handle so the next poll reopens (device gone and back).
+	/// Anything else (a custom activity file) is opened per poll, since a
+	/// writer may replace it with rename and a kept fd would go stale.
+	fn with_file<T>(&self, path: &str, read: impl FnOnce(&File) -> io::Result<T>) -> io::Result<T> {
+		if !(path.starts_with("/sys/") || path.starts_with("/proc/")) {
+			return read(&File::open(path)?);
+		}
+		
+		let mut files = self.files.borrow_mut();
+		if !files.contains_key(path) {
+			files.insert(path.to_string(), File::open(path)?);
+		}
+		let result = read(&files[path]);
+		if result.is_err() {
+			files.remove(path);
+		}
+		result
+	}
+	
+	/// Sum the counters of all members, following membership changes
+	/// 
+	/// # Returns
+	/// * `Some((counts, changed))` - Summed counters; `changed` is true
+	///   when the member set differs from last time (sums aren't comparable)
+	/// * `None` - A member couldn't be read; it is re-scanned next poll
+	fn member_counters(&mut self, scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<(Counts, bool)>> {
+		let mut changed = false;
+		
+		// Periodic (or forced) re-discovery of the member set
+		let due = self.members_scanned
+			.is_none_or(|t| t.elapsed() >= Duration::from_millis(MEMBER_RESCAN_MS));
+		if due && let Some(group) = &self.group {
+			let fresh = group.discover();
+			let fresh = self.capped_members(fresh);
+			if fresh != self.members {
+				self.members = fresh;
+				changed = true;
+				// Close handles of members that left the array
+				let members = &self.members;
+				self.files.borrow_mut().retain(|p, _| members.contains(p));
+			}
+			self.members_scanned = Some(Instant::now());
+		}
+		
+		let mut rsum = 0u128;
+		let mut wsum = 0u128;
+		let mut dsum = 0u128;
+		let mut qsum = 0u128;
+		for i in 0..self.members.len() {
+			match self.read_counters(&self.members[i], scratch) {
+				Ok(Some((r, w, d, q))) => { rsum += r; wsum += w; dsum += d; qsum += q; }
+				// A member vanished mid-rebuild: re-scan on the next poll
+				_ => {
+					self.members_scanned = None;
+					return Ok(None);
+				}
+			}
+		}
+		Ok(Some(((rsum, wsum, dsum, qsum), changed)))
+	}
+}
+
+impl ActivitySource for SysfsSource {
+	fn read(&mut self, scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<(Counts, bool)>> {
+		if let Some(c) = self.captured.get_mut() {
+			c.clear();
+		}
+		if self.group.is_some() {
+			return self.member_counters(scratch);
+		}
+		Ok(self.read_counters(&self.path, scratch)?.map(|c| (c, false)))
+	}
+	
+	fn fields(&self) -> Option<Vec<u64>> {
+		self.captured.borrow().clone()
+	}
+}
+
+/// Appends what each monitor read to a capture file (--record)
+/// 
+/// One line per device per poll in which its fields changed:
+/// `<unix time ms> <device> <stat fields in kernel order>`. Unchanged polls
+/// are left out; a replay holds the previous values until the next line.
+struct Recorder {
+	f: File,						  // Capture file, opened for appending
+	last: HashMap<String, Vec<u64>>,  // Fields last written per device
+}
+
+impl Recorder {
+	/// Open (or create) `path` for appending, with a header if it's new
+	fn open(path: &str) -> io::Result<Self> {
+		let mut f = OpenOptions::new().append(true).create(true).open(path)?;
+		if f.metadata()?.len() == 0 {
+			writeln!(f, "# nvme-led-daemon capture: <unix time ms> <device> <stat fields>")?;
+		}
+		Ok(Self { f, last: HashMap::new() })
+	}
+	
+	/// Write a line for every monitor whose fields changed since last time
+	fn write(&mut self, nvmes: &[Nvme]) -> io::Result<()> {
+		let ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+			.map(|d| d.as_millis()).unwrap_or(0);
+		for nvme in nvmes {
+			let Some(fields) = nvme.source.fields().filter(|f| !f.is_empty()) else { continue };
+			if self.last.get(&nvme.name) == Some(&fields) {
+				continue;
+			}
+			let vals: Vec<String> = fields.iter().map(u64::to_string).collect();
+			writeln!(self.f, "{} {} {}", ms, nvme.name.replace(char::is_whitespace, "_"), vals.join(" "))?;
+			self.last.insert(nvme.name.clone(), fields);
+		}
+		Ok(())
+	}
+}
+
+/// Appends a throughput time series to a CSV file (--csv)
+/// 
+/// One row per poll, or per `interval_ms` when sampling less often:
+/// `unix_ms,elapsed_ms,read_bytes,write_bytes,iops,busy_pct`, each covering
+/// the time since the previous row. Bytes and I/Os are summed over the
+/// devices, busy is the busiest device's. The totals come from the stat
+/// fields each poll captures anyway, so logging costs no extra reads. Rows
+/// are buffered and only flushed every CSV_FLUSH_MS (and on exit).
+struct CsvLog {
+	out: io::BufWriter<File>,				  // CSV file, opened for appending
+	interval_ms: u64,						  // Least time between rows (0 = every poll)
+	started: Instant,						  // Zero point of elapsed_ms
+	flushed: Instant,						  // Last flush to the file
+	last: Option<(Instant, Vec<[u64; 5]>)>,   // Previous row's time and per-device totals
+}
+
+impl CsvLog {
+	/// Open (or create) `path` for appending, with a header row if it's new
+	fn open(path: &str, interval_ms: u64) -> io::Result<Self> {
+		let mut f = OpenOptions::new().append(true).create(true).open(path)?;
+		if f.metadata()?.len() == 0 {
+			writeln!(f, "unix_ms,elapsed_ms,read_bytes,write_bytes,iops,busy_pct")?;
+		}
+		let now = Instant::now();
+		Ok(Self { out: io::BufWriter::new(f), interval_ms, started: now, flushed: now, last: None })
+	}
+	
+	/// Start over from the next poll (the set of devices changed)
+	fn rebase(&mut self) {
+		self.last = None;
+	}
+	
+	/// Write a row if one is due; the first call only takes the baseline
+	fn write(&mut self, nvmes: &[Nvme]) -> io::Result<()> {
+		let now = Instant::now();
+		if let Some((at, _)) = &self.last
+			&& now.duration_since(*at) < Duration::from_millis(self.interval_ms) {
+			return Ok(());
+		}
+		
+		let totals: Vec<[u64; 5]> = nvmes.iter().map(|n| {
+			let f = n.source.fields().unwrap_or_default();
+			let at = |i: usize| f.get(i).copied().unwrap_or(0);
+			[at(STAT_R_SECTORS), at(STAT_W_SECTORS), at(STAT_R_IOS), at(STAT_W_IOS), at(STAT_IO_TICKS)]
+		}).collect();
+		let Some((at, prev)) = self.last.replace((now, totals.clone())) else { return Ok(()) };
+		let ms = (now.duration_since(at).as_millis() as u64).max(1);
+		let (row, busy) = csv_row(&prev, &totals, ms);
+		
+		let unix_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+			.map(|d| d.as_millis()).unwrap_or(0);
+		writeln!(self.out, "{},{},{},{},{},{}", unix_ms, self.started.elapsed().as_millis(),
+			row[0], row[1], row[2], busy)?;
+		if self.flushed.elapsed() >= Duration::from_millis(CSV_FLUSH_MS) {
+			self.out.flush()?;
+			self.flushed = now;
+		}
+		Ok(())
+	}
+}
+
+/// One CSV row from two sets of per-device totals `ms` apart:
+/// ([read bytes, write bytes, I/Os per second], busiest device's busy %)
+/// 
+/// A counter going backwards (device re-added) counts as no change.
+fn csv_row(prev: &[[u64; 5]], now: &[[u64; 5]], ms: u64) -> ([u64; 3], u64) {
+	let (mut sum, mut busy) = ([0u64; 4], 0);
+	for (t, p) in now.iter().zip(prev) {
+		for i in 0..4 {
+			sum[i] += t[i].saturating_sub(p[i]);
+		}
+		busy = busy.max((t[4].saturating_sub(p[4]) * 100 / ms).min(100));
+	}
+	([sum[0] * SECTOR_BYTES, sum[1] * SECTOR_BYTES, (sum[2] + sum[3]) * 1000 / ms], busy)
+}
+
+/// One device's recorded samples: (ms since capture start, stat fields)
+type Samples = Vec<(u64, String)>;
+
+/// One device's samples from a capture file (--replay)
+/// 
+/// Samples come out at the recorded pace (each read returns the latest
+/// sample due by then, counting from the first read) or, with `fast`, one
+/// per read regardless of time. They go through the same field parsing
+/// as a live stat file, so the current nvme_mode and on_fields apply.
+struct ReplaySource {
+	samples: Samples,			  // Samples in capture order
+	pos: usize,					  // Samples handed out so far
+	started: Option<Instant>,	  // Replay clock zero (first read)
+	fast: bool,					  // One sample per read instead of real time
+	mode: NvmeMode,				  // Which fields to parse
+	discards: bool,				  // Also parse the discard fields
+}
+
+impl ActivitySource for ReplaySource {
+	fn read(&mut self, _scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<(Counts, bool)>> {
+		if self.fast {
+			self.pos = (self.pos + 1).min(self.samples.len());
+		} else {
+			let now_ms = self.started.get_or_insert_with(Instant::now).elapsed().as_millis() as u64;
+			while self.pos < self.samples.len() && self.samples[self.pos].0 <= now_ms {
+				self.pos += 1;
+			}
+		}
+		// Nothing due yet (a device first seen later in the capture)
+		let Some((_, line)) = self.pos.checked_sub(1).map(|i| &self.samples[i]) else {
+			return Ok(None);
+		};
+		Ok(parse_indexed_counters(line.split_whitespace(), self.mode, self.discards).map(|c| (c, false)))
+	}
+	
+	fn finished(&self) -> bool {
+		self.pos >= self.samples.len()
+	}
+}
+
+/// Load a capture file into per-device sample lists, in order of each
+/// device's first appearance; times are relative to the first sample
+fn load_capture(path: &str) -> io::Result<Vec<(String, Samples)>> {
+	let contents = std::fs::read_to_string(path)?;
+	let mut devices: Vec<(String, Samples)> = Vec::new();
+	let mut t0 = None;
+	for (n, line) in contents.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let mut t = line.splitn(3, char::is_whitespace);
+		let (Some(ms), Some(dev), Some(fields)) = (t.next().and_then(|v| v.parse::<u64>().ok()), t.next(), t.next()) else {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: malformed sample", path, n + 1)));
+		};
+		let at = ms.saturating_sub(*t0.get_or_insert(ms));
+		match devices.iter_mut().find(|(d, _)| d == dev) {
+			Some((_, samples)) => samples.push((at, fields.to_string())),
+			None => devices.push((dev.to_string(), vec![(at, fields.to_string())])),
+		}
+	}
+	if devices.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no samples", path)));
+	}
+	Ok(devices)
+}
+
+/// Counters taken from one stat source: (reads, writes, discards, extra),
+/// where extra is the mode's additional field (in_flight or io_ticks)
+type Counts = (u128, u128, u128, u128);
+
+/// Extract (read, write, discard, extra) counters from kernel stat
+/// fields by position
+/// 
+/// `tokens` are the stat fields in kernel order (field 0 = read I/Os).
+/// The known fields are collected into a fixed array first and then read
+/// by their STAT_* offsets: Sectors mode takes r/w/d_sectors, Io mode
+/// r/w/d_ios. InFlight and Busy modes take Io's fields plus in_flight or
+/// io_ticks respectively as the extra value (0 in the other modes).
+/// Returns None ("unavailable") for a line with fewer than STAT_MIN_FIELDS
+/// fields, a non-numeric field (which would shift every offset after it),
+/// or no in_flight/io_ticks field where the mode needs one. The discard
+/// fields only exist on 4.18+ kernels, so they are optional: if `discards`
+/// is false or the line is too short, discards read as 0.
+fn parse_indexed_counters<'a>(tokens: impl Iterator<Item = &'a str>, mode: NvmeMode, discards: bool) -> Option<Counts> {
+	// Fields beyond the known ones (future kernels) are ignored
+	let mut f = [0u64; STAT_FIELD_NAMES.len()];
+	let mut n = 0;
+	for token in tokens.take(f.len()) {
+		f[n] = token.parse().ok()?;
+		n += 1;
+	}
+	if n < STAT_MIN_FIELDS {
+		return None;
+	}
+	let field = |i: usize| (i < n).then(|| f[i] as u128);
+	
+	// Field offsets for this mode
+	let (ri, wi, di) = match mode {
+		// Sectors read, sectors written, sectors discarded (512-byte sectors)
+		NvmeMode::Sectors => (STAT_R_SECTORS, STAT_W_SECTORS, STAT_D_SECTORS),
+		// Read, write and discard I/Os completed successfully
+		NvmeMode::Io | NvmeMode::InFlight | NvmeMode::Busy => (STAT_R_IOS, STAT_W_IOS, STAT_D_IOS),
+	};
+	let extra = match mode {
+		NvmeMode::InFlight => field(STAT_IN_FLIGHT)?,
+		NvmeMode::Busy => field(STAT_IO_TICKS)?,
+		_ => 0,
+	};
+	
+	Some((f[ri] as u128, f[wi] as u128,
+		if discards { field(di).unwrap_or(0) } else { 0 },
+		extra))
+}
+
+/// Extract (read, write, discard, extra) counters from a
+/// header-prefixed stat source
+/// 
+/// The first non-empty, non-comment line names the columns using the
+/// kernel's documented field names (`r_ios`, `r_sectors`, `w_ios`,
+/// `w_sectors`, ...); the following line holds the values in that order.
+/// Returns None if the read or write column is missing or not numeric (or
+/// `in_flight`/`io_ticks` in in-flight/busy mode); a missing discard column
+/// (`d_sectors`/`d_ios`) reads as 0.
+fn parse_named_counters(s: &str, mode: NvmeMode, discards: bool) -> Option<Counts> {
+	let mut lines = s.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#'));
+	let header: Vec<&str> = lines.next()?.split_whitespace().collect();
+	let values: Vec<&str> = lines.next()?.split_whitespace().collect();
+	
+	let (rk, wk, dk) = match mode {
+		NvmeMode::Sectors => ("r_sectors", "w_sectors", "d_sectors"),
+		NvmeMode::Io | NvmeMode::InFlight | NvmeMode::Busy => ("r_ios", "w_ios", "d_ios"),
+	};
+	let get = |key: &str| header.iter().position(|h| *h == key)
+		.and_then(|i| values.get(i))
+		.and_then(|v| v.parse::<u64>().ok())
+		.map(u128::from);
+	let d = if discards { get(dk).unwrap_or(0) } else { 0 };
+	let q = match mode {
+		NvmeMode::InFlight => get("in_flight")?,
+		NvmeMode::Busy => get("io_ticks")?,
+		_ => 0,
+	};
+	Some((get(rk)?, get(wk)?, d, q))
+}
+
+/// A header-prefixed stat source's values in kernel field order (missing
+/// columns read as 0), as recorded by --record
+fn named_fields(s: &str) -> impl Iterator<Item = u64> {
+	let mut lines = s.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#'));
+	let header: Vec<&str> = lines.next().unwrap_or("").split_whitespace().collect();
+	let values: Vec<u64> = lines.next().unwrap_or("").split_whitespace().map(|v| v.parse().unwrap_or(0)).collect();
+	STAT_FIELD_NAMES.iter().map(move |name| header.iter().position(|h| h == name)
+		.and_then(|i| values.get(i).copied()).unwrap_or(0))
+}
+
+/// Devices whose counters a `SysfsSource` sums
+enum MemberGroup {
+	/// The members of an md array (its device dir)
+	Md(std::path::PathBuf),
+	
+	/// The namespaces of an NVMe controller (`nvme0`)
+	Controller(String),
+}
+
+impl MemberGroup {
+	/// Current member stat files, sorted
+	fn discover(&self) -> Vec<String> {
+		match self {
+			MemberGroup::Md(dir) => discover_md_members(dir),
+			MemberGroup::Controller(ctrl) => discover_namespaces(std::path::Path::new(SYS_BLOCK), ctrl),
+		}
+	}
+}
+
+/// Find the stat files of an md array's member devices
+/// 
+/// Prefers `slaves/` (symlinks to each member's block device dir) and falls
+/// back to `md/rd*/block` for arrays that only expose the md view. Sorted so
+/// comparisons between scans are stable.
+fn discover_md_members(dev_dir: &std::path::Path) -> Vec<String> {
+	let list = |dir: std::path::PathBuf, prefix: &str, suffix: &str| -> Vec<String> {
+		let Ok(rd) = std::fs::read_dir(&dir) else { return Vec::new() };
+		let mut v: Vec<String> = rd.flatten()
+			.filter(|e| e.file_name().to_string_lossy().starts_with(prefix))
+			.map(|e| e.path().join(suffix))
+			.filter(|p| p.exists())
+			.map(|p| p.to_string_lossy().into_owned())
+			.collect();
+		v.sort();
+		v
+	};
+	
+	let slaves = list(dev_dir.join("slaves"), "", "stat");
+	if !slaves.is_empty() {
+		return slaves;
+	}
+	list(dev_dir.join("md"), "rd", "block/stat")
+}
+
+/// Find the stat files of an NVMe controller's namespaces under `block_dir`
+/// 
+/// `nvme0` matches `nvme0n1`, `nvme0n2`, ... but not `nvme10n1`, partitions
+/// or multipath paths. Sorted so comparisons between scans are stable.
+fn discover_namespaces(block_dir: &std::path::Path, ctrl: &str) -> Vec<String> {
+	let Ok(rd) = std::fs::read_dir(block_dir) else { return Vec::new() };
+	let mut v: Vec<String> = rd.flatten()
+		.filter(|e| {
+			let name = e.file_name().to_string_lossy().into_owned();
+			is_nvme_namespace(&name) && name.strip_prefix(ctrl).is_some_and(|ns| ns.starts_with('n'))
+		})
+		.map(|e| e.path().join("stat").to_string_lossy().into_owned())
+		.collect();
+	v.sort();
+	v
+}
+
+/// True for NVMe controller names (nvme0, nvme12)
+fn is_nvme_controller(name: &str) -> bool {
+	name.strip_prefix("nvme").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Derive a device label from a stat path
+/// 
+/// `/sys/block/nvme0n1/stat` becomes `nvme0n1` (the device node basename);
+/// any other path falls back to its final component.
+fn device_name_from_path(path: &str) -> String {
+	let p = std::path::Path::new(path);
+	let node = if p.file_name().is_some_and(|f| f == "stat") { p.parent() } else { Some(p) };
+	node.and_then(|n| n.file_name())
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_else(|| path.to_string())
+}
+
+/// True for NVMe namespace block devices (nvme0n1, nvme12n3), but not
+/// partitions (nvme0n1p2) or hidden multipath paths (nvme0c0n1)
+fn is_nvme_namespace(name: &str) -> bool {
+	let Some(rest) = name.strip_prefix("nvme") else { return false };
+	let Some((ctrl, ns)) = rest.split_once('n') else { return false };
+	!ctrl.is_empty() && ctrl.bytes().all(|b| b.is_ascii_digit())
+		&& !ns.is_empty() && ns.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The NVMe namespace underneath a block device's sysfs directory
+/// 
+/// A partition's canonical path runs through its disk's directory; a
+/// device-mapper or md device lists what it sits on in `slaves/`, which
+/// are followed (a few levels deep, for LVM on LUKS and the like).
+fn nvme_under(dev_dir: &std::path::Path, depth: u32) -> Option<String> {
+	let real = std::fs::canonicalize(dev_dir).ok()?;
+	if let Some(name) = real.iter().map(|c| c.to_string_lossy()).find(|c| is_nvme_namespace(c)) {
+		return Some(name.into_owned());
+	}
+	if depth == 0 {
+		return None;
+	}
+	let mut slaves: Vec<_> = std::fs::read_dir(real.join("slaves")).ok()?.flatten().map(|e| e.path()).collect();
+	slaves.sort();
+	slaves.iter().find_map(|s| nvme_under(s, depth - 1))
+}
+
+/// The whole disk underneath a block device's sysfs directory, of any kind
+/// (sda, mmcblk0, vda), following `slaves/` like `nvme_under`
+/// 
+/// A device-mapper or md device with nothing underneath it is its own disk.
+fn disk_under(dev_dir: &std::path::Path, depth: u32) -> Option<String> {
+	let real = std::fs::canonicalize(dev_dir).ok()?;
+	if depth > 0 && let Ok(rd) = std::fs::read_dir(real.join("slaves")) {
+		let mut slaves: Vec<_> = rd.flatten().map(|e| e.path()).collect();
+		slaves.sort();
+		if let Some(name) = slaves.iter().find_map(|s| disk_under(s, depth - 1)) {
+			return Some(name);
+		}
+	}
+	
+	// .../block/sda/sda1 for a partition, .../block/sda for the disk
+	let mut parts = real.iter().map(|c| c.to_string_lossy());
+	parts.find(|c| c == "block")?;
+	parts.next().map(|c| c.into_owned())
+}
+
+/// Resolve a `nvme_path`/`device` entry to a stat file: a bare name such as
+/// `sda` or `mmcblk0` means `/sys/block/NAME/stat`, anything with a `/` is
+/// taken as a path
+fn stat_path_of(entry: &str) -> String {
+	if entry.contains('/') {
+		entry.to_string()
+	} else {
+		format!("{}/{}/stat", SYS_BLOCK, entry)
+	}
+}
+
+/// Pick a stat file when neither nvme_path nor device is configured
+/// 
+/// Prefers the NVMe namespace holding the root filesystem (found through
+/// the device number of `/`), so a machine booting from nvme1n1 doesn't
+/// watch an idle nvme0n1. A root filesystem on another kind of disk (SATA,
+/// SD card, virtio) uses that disk; otherwise the first namespace in
+/// `/sys/block`.
+fn detect_nvme_path() -> io::Result<String> {
+	use std::os::unix::fs::MetadataExt;
+	let stat_of = |name: &str| format!("{}/{}/stat", SYS_BLOCK, name);
+	
+	// glibc's major()/minor() encoding of dev_t
+	if let Ok(meta) = std::fs::metadata("/") {
+		let dev = meta.dev();
+		let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+		let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+		let dir = std::path::PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+		if let Some(name) = nvme_under(&dir, 4).or_else(|| disk_under(&dir, 4)) {
+			log!(Level::Info, "no nvme_path configured; using {} (holds the root filesystem)", name);
+			return Ok(stat_of(&name));
+		}
+	}
+	
+	let mut found: Vec<String> = std::fs::read_dir(SYS_BLOCK)?.flatten()
+		.map(|e| e.file_name().to_string_lossy().into_owned())
+		.filter(|n| is_nvme_namespace(n))
+		.collect();
+	found.sort();
+	match found.first() {
+		Some(name) => {
+			log!(Level::Info, "no nvme_path configured; using {} (first of: {})", name, found.join(", "));
+			Ok(stat_of(name))
+		}
+		None => Err(io::Error::new(io::ErrorKind::NotFound, format!(
+			"no nvme_path configured and no NVMe device found under {}; set device \
+			 (--device NAME, any block device such as sda or mmcblk0) or nvme_path", SYS_BLOCK))),
+	}
+}
+
+/// Print a table of block devices with their model and size (--list-devices)
+/// 
+/// Only NVMe namespaces unless `all` is set; the stat path column is what
+/// nvme_path takes.
+fn list_devices(all: bool) -> io::Result<()> {
+	let mut names: Vec<String> = std::fs::read_dir(SYS_BLOCK)?.flatten()
+		.map(|e| e.file_name().to_string_lossy().into_owned())
+		.filter(|n| all || is_nvme_namespace(n))
+		.collect();
+	names.sort();
+	let width = names.iter().map(String::len).max().unwrap_or(0).max(4);
+	println!("{:width$}  {:>10}  {:40}  STAT", "NAME", "SIZE", "MODEL", width = width);
+	for name in &names {
+		let dir = std::path::Path::new(SYS_BLOCK).join(name);
+		let model = std::fs::read_to_string(dir.join("device/model"))
+			.map(|v| v.trim().to_string())
+			.unwrap_or_else(|_| "-".to_string());
+		// `size` is in 512-byte sectors regardless of the logical block size
+		let size = std::fs::read_to_string(dir.join("size")).ok()
+			.and_then(|v| v.trim().parse::<u64>().ok())
+			.map(|sectors| format!("{:.1} GB", (sectors * SECTOR_BYTES) as f64 / 1e9))
+			.unwrap_or_else(|| "?".to_string());
+		println!("{:width$}  {:>10}  {:40}  {}", name, size, model, dir.join("stat").display(), width = width);
+	}
+	if names.is_empty() {
+		println!("(no {}block devices under {})", if all { "" } else { "NVMe " }, SYS_BLOCK);
+	}
+	Ok(())
+}
+
+/// Build one monitor per configured stat file
+/// 
+/// `nvme_path` is a comma-separated list (repeated --nvme appends to it) of
+/// stat files or block device names; unset, `device` lists names instead.
+/// `nvme_name` labels them positionally the same way. The list is capped at
+/// max_devices. Each monitor keeps its own baselines. With nvme_controller
+/// there is a single monitor summing the controller's namespaces instead.
+fn open_monitors(cfg: &Config) -> io::Result<Vec<Nvme>> {
+	if let Some(path) = &cfg.replay {
+		return open_replay(cfg, path);
+	}
+	
+	// With source=diskstats the list is of device names instead
+	let list = stat_list(cfg);
+	let controller = cfg.nvme_controller.as_deref().filter(|_| cfg.source == Source::Stat);
+	if let Some(ctrl) = controller.filter(|c| !is_nvme_controller(c)) {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput,
+			format!("nvme_controller {} is not a controller name like nvme0", ctrl)));
+	}
+	let paths: Vec<&str> = match controller {
+		Some(_) => vec![SYS_BLOCK],
+		None => list.split(',').map(str::trim).filter(|p| !p.is_empty()).collect(),
+	};
+	if paths.len() > cfg.max_devices {
+		log!(Level::Warn, "{} devices configured, monitoring only the first {} (max_devices)",
+			paths.len(), cfg.max_devices);
+	}
+	let mut names = cfg.nvme_name.as_deref().map(|n| n.split(',').map(str::trim));
+	
+	let mut nvmes = Vec::new();
+	for path in paths.into_iter().take(cfg.max_devices) {
+		let name = names.as_mut().and_then(|n| n.next()).filter(|n| !n.is_empty());
+		nvmes.push(open_monitor(cfg, path, name, controller)?);
+	}
+	
+	// Hotplug: matching disks present right now join in too
+	if let Some(pattern) = hotplug_pattern(cfg) {
+		for dev in block_devices_matching(pattern) {
+			if nvmes.len() >= cfg.max_devices {
+				log!(Level::Warn, "hotplug: {} matches but max_devices ({}) are monitored already", dev, cfg.max_devices);
+				continue;
+			}
+			if !nvmes.iter().any(|n| n.path == stat_path_of(&dev)) {
+				nvmes.push(open_monitor(cfg, &dev, None, None)?);
+			}
+		}
+	}
+	if nvmes.is_empty() && hotplug_pattern(cfg).is_none() {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, match cfg.source {
+			Source::Stat => "no nvme_path configured",
+			Source::DiskStats => "source=diskstats needs device=NAME",
+		}));
+	}
+	Ok(nvmes)
+}
+
+/// Build the monitor for one `nvme_path`/`device` entry (or, with
+/// `controller`, for all of that controller's namespaces)
+fn open_monitor(cfg: &Config, path: &str, name: Option<&str>, controller: Option<&str>) -> io::Result<Nvme> {
+	let (stat, name) = match cfg.source {
+		Source::Stat => (stat_path_of(path), name.or(controller).map(str::to_string).unwrap_or_else(|| device_name_from_path(path))),
+		Source::DiskStats => (DISKSTATS_PATH.to_string(), name.unwrap_or(path).to_string()),
+	};
+	let mut src = SysfsSource::new(&stat, &name, cfg.nvme_mode);
+	if cfg.source == Source::DiskStats {
+		src.diskstats_dev = Some(path.to_string());
+	}
+	src.format = cfg.stat_format;
+	src.discards = matches!(cfg.on_fields, FieldsSel::Discards | FieldsSel::All);
+	if cfg.record.is_some() || cfg.csv.is_some() {
+		*src.captured.get_mut() = Some(Vec::new());
+	}
+	if let Some(ctrl) = controller {
+		let n = src.aggregate_controller(ctrl, cfg.max_devices);
+		log!(Level::Info, "aggregating {} namespaces of {}", n, ctrl);
+	} else if cfg.aggregate_members {
+		let n = src.aggregate_members(cfg.max_devices)?;
+		log!(Level::Info, "aggregating {} members of {}", n, name);
+	}
+	
+	let mut nvme = Nvme::new(&stat, Some(&name), cfg.nvme_mode, Box::new(src));
+	nvme.min_read = cfg.min_read as u128;
+	nvme.min_write = cfg.min_write as u128;
+	nvme.alpha = cfg.smoothing;
+	Ok(nvme)
+}
+
+/// The hotplug device pattern, if hotplug applies (per-device stat files,
+/// not a controller or a replay)
+fn hotplug_pattern(cfg: &Config) -> Option<&str> {
+	cfg.hotplug.as_deref()
+		.filter(|_| cfg.source == Source::Stat && cfg.nvme_controller.is_none() && cfg.replay.is_none())
+}
+
+/// Whole disks in /sys/block whose name matches a glob `pattern`, sorted
+fn block_devices_matching(pattern: &str) -> Vec<String> {
+	let mut found: Vec<String> = std::fs::read_dir(SYS_BLOCK).into_iter().flatten().flatten()
+		.map(|e| e.file_name().to_string_lossy().into_owned())
+		.filter(|n| glob_match(pattern, n))
+		.collect();
+	found.sort();
+	found
+}
+
+/// Shell-style match of `name` against `pattern`: `*` is any run of
+/// characters, `?` any one; everything else matches itself
+fn glob_match(pattern: &str, name: &str) -> bool {
+	let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+	// Classic backtracking over the last `*`
+	let (mut pi, mut ni, mut star, mut mark) = (0, 0, None, 0);
+	while ni < n.len() {
+		if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+			(pi, ni) = (pi + 1, ni + 1);
+		} else if pi < p.len() && p[pi] == '*' {
+			(star, mark, pi) = (Some(pi), ni, pi + 1);
+		} else if let Some(s) = star {
+			(pi, mark) = (s + 1, mark + 1);
+			ni = mark;
+		} else {
+			return false;
+		}
+	}
+	p[pi..].iter().all(|&c| c == '*')
+}
+
+/// The configured device list: `nvme_path` (or, unset, `device`) for stat
+/// files, `device` for /proc/diskstats
+fn stat_list(cfg: &Config) -> &str {
+	match cfg.source {
+		Source::Stat if !cfg.nvme_path.is_empty() => &cfg.nvme_path,
+		_ => &cfg.device,
+	}
+}
+
+/// Build one monitor per device in a --replay capture
+fn open_replay(cfg: &Config, path: &str) -> io::Result<Vec<Nvme>> {
+	let devices = load_capture(path).map_err(|e| io::Error::new(e.kind(), format!("replay: {}", e)))?;
+	let mut nvmes = Vec::new();
+	for (name, samples) in devices.into_iter().take(cfg.max_devices) {
+		log!(Level::Info, "replaying {} samples of {} from {}{}", samples.len(), name, path,
+			if cfg.replay_fast { " (fast)" } else { "" });
+		let src = ReplaySource {
+			samples,
+			pos: 0,
+			started: None,
+			fast: cfg.replay_fast,
+			mode: cfg.nvme_mode,
+			discards: matches!(cfg.on_fields, FieldsSel::Discards | FieldsSel::All),
+		};
+		let mut nvme = Nvme::new(path, Some(&name), cfg.nvme_mode, Box::new(src));
+		nvme.min_read = cfg.min_read as u128;
+		nvme.min_write = cfg.min_write as u128;
+		nvme.alpha = cfg.smoothing;
+		nvmes.push(nvme);
+	}
+	Ok(nvmes)
+}
+
+/// Poll every monitor and combine their activity
+/// 
+/// Directions combine like the single-device case: a discard anywhere reports
+/// Discard, then a write anywhere reports Write, otherwise a read anywhere
+/// reports Read. A poll where reads and writes both moved (on one device
+/// or across several) is reported as `mixed` instead, with the flag set.
+/// Raw deltas, smoothed deltas and in-flight depths are each summed; the
+/// busy percentage is the busiest device's.
+fn poll_monitors(nvmes: &mut [Nvme], scratch: &mut [u8; STAT_BUF_LEN], mixed: Dir) -> io::Result<(Option<Dir>, bool, u128, f64, u128, u64)> {
+	let mut dir = None;
+	let mut delta = 0u128;
+	let mut smoothed = 0.0;
+	let mut depth = 0u128;
+	let mut busy = 0u64;
+	let (mut reads, mut both) = (false, false);
+	for nvme in nvmes.iter_mut() {
+		let d = nvme.activity_dir(scratch)?;
+		match d {
+			Some(Dir::Discard) => dir = Some(Dir::Discard),
+			Some(Dir::Write) if dir != Some(Dir::Discard) => dir = Some(Dir::Write),
+			Some(Dir::Read) if dir.is_none() => dir = Some(Dir::Read),
+			_ => {}
+		}
+		// Reads on one device and writes on another are mixed too
+		reads |= d == Some(Dir::Read);
+		both |= nvme.last_mixed;
+		delta += nvme.last_delta;
+		smoothed += nvme.ema;
+		depth += nvme.last_depth;
+		busy = busy.max(nvme.last_busy);
+	}
+	let is_mixed = dir == Some(Dir::Write) && (both || reads);
+	if is_mixed {
+		dir = Some(mixed);
+	}
+	Ok((dir, is_mixed, delta, smoothed, depth, busy))
+}
+
+/// Direction a poll in which reads and writes both moved is reported as
+/// (`longer`/`shorter` compare the configured blink durations)
+fn mixed_dir(cfg: &Config) -> Dir {
+	let (r, w) = (blink_duration(cfg, Dir::Read), blink_duration(cfg, Dir::Write));
+	match cfg.mixed_priority {
+		MixedPriority::Read => Dir::Read,
+		MixedPriority::Write => Dir::Write,
+		MixedPriority::Longer if r > w => Dir::Read,
+		MixedPriority::Shorter if r < w => Dir::Read,
+		MixedPriority::Longer | MixedPriority::Shorter => Dir::Write,
+	}
+}
+
+/// Combined label for output: the device names joined with '+'
+fn monitors_label(nvmes: &[Nvme]) -> String {
+	nvmes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>().join("+")
+}
+
+// ============================================================================
+// CONFIGURATION: Settings loaded from file and/or CLI
+// ============================================================================
+
+/// Configuration loaded from file and/or command-line arguments
+/// 
+/// Settings are loaded in this order (later overrides earlier):
+/// 1. Hard-coded defaults (DEFAULT_* constants)
+/// 2. Default config file (/etc/nvme-led-daemon.conf) if present
+/// 3. Custom config file(s) (--config PATH) if specified, in order
+/// 4. NVME_LED_* environment variables
+/// 5. Command-line arguments
+#[derive(Clone)]
+struct Config {
+	led_path: String,				   // Path to LED sysfs file
+	led_slot: Option<String>,		   // Enclosure slot to resolve into led_path
+	led_slot_attr: String,			   // Slot LED attribute (active/locate/fault)
+	read_led_path: Option<String>,	   // Separate LED for reads (None = main LED)
+	write_led_path: Option<String>,	   // Separate LED for writes (None = main LED)
+	led_open_policy: LedOpenPolicy,	   // Whether a per-direction LED failing is fatal
+	rgb_paths: [Option<String>; 3],	   // red/green/blue brightness files (all or none)
+	read_color: (u8, u8, u8),		   // RGB color shown for reads
+	write_color: (u8, u8, u8),		   // RGB color shown for writes
+	full_scale_mb_s: Option<u64>,	   // Throughput shown at full brightness (None = on/off)
+	full_scale_depth: Option<u64>,	   // In-flight depth shown at full brightness (None = on/off)
+	brightness_hysteresis: u32,		   // Ignore level changes of at most this many steps
+	pwm: bool,						   // Software-PWM dimming for on/off LEDs
+	pwm_freq_hz: u64,				   // PWM frequency (at least MIN_PWM_FREQ_HZ)
+	pwm_duty: u64,					   // PWM duty at light I/O, 0-100
+	fade_ms: u64,					   // Fade-out length on dimmable LEDs (0 = hard off)
+	fade_curve: FadeCurve,			   // Shape of the fade-out
+	nvme_path: String,				   // NVMe stat file(s), comma-separated
+	nvme_name: Option<String>,		   // Friendly label(s) for logs (default: device basename)
+	nvme_controller: Option<String>,   // Sum all namespaces of this controller (nvme0)
+	hotplug: Option<String>,		   // Follow disks matching this glob as they come and go
+	source: Source,					   // Per-device stat files or /proc/diskstats
+	device: String,					   // Device name(s) for source=diskstats, comma-separated
+	poll_ms: u64,					   // Polling interval in milliseconds
+	max_interval_ms: u64,			   // Idle polls back off up to this (0 = fixed interval)
+	min_read: u64,					   // Per-poll read delta must exceed this to count (mode units)
+	min_write: u64,					   // Per-poll write delta must exceed this to count (mode units)
+	min_in_bytes: bool,				   // A threshold came from a *_bytes key (sectors mode only)
+	smoothing: f64,					   // EMA alpha for deltas, (0, 1]; 1.0 = raw
+	backoff_factor: f64,			   // Interval growth per backoff step
+	backoff_after: u64,				   // Consecutive idle polls per backoff step
+	blink_ms: u64,					   // Default LED on duration in milliseconds
+	blink_ms_set: bool,				   // blink_ms was given explicitly (beats blink_pct)
+	blink_pct: Option<u64>,			   // Default duration as a % of the poll interval
+	read_blink_ms: Option<u64>,		   // Override blink duration for reads (if Some)
+	write_blink_ms: Option<u64>,	   // Override blink duration for writes (if Some)
+	discard_blink_ms: Option<u64>,	   // Override blink duration for discards (if Some)
+	min_on_ms: u64,					   // Keep a blink lit at least this long (0 = off)
+	min_off_ms: u64,				   // Keep the LED dark at least this long between blinks
+	active_high: bool,				   // LED polarity (true = writing "1" turns on)
+	log_level: Level,				   // Messages below this are dropped (log_level, quiet/verbose)
+	log_target: LogTarget,			   // stdout/stderr or syslog
+	nvme_mode: NvmeMode,			   // Which stat fields to monitor
+	on_fields: FieldsSel,			   // Which operations trigger LED
+	mixed_priority: MixedPriority,	   // Direction reported when reads and writes both moved
+	reconcile_ms: u64,				   // Re-read LED brightness this often (0 = off)
+	startup_grace_ms: u64,			   // Track but don't blink for this long after start
+	startup_blink: bool,			   // Blink (or ramp) once at startup to show we're alive
+	resume_blink: bool,				   // Blink twice after a system suspend/resume
+	aggregate_members: bool,		   // Sum md array members instead of the md device
+	align_polls: bool,				   // Start poll ticks on round interval boundaries
+	boottime_timers: bool,			   // Run the interval timers on CLOCK_BOOTTIME
+	stat_notify: bool,				   // Poll on stat file change notification, if it comes
+	max_devices: usize,				   // Cap on monitored devices (discovery is truncated)
+	mount_blink: bool,				   // Play a pattern when filesystems (un)mount
+	mount_pattern: Vec<u64>,		   // Pattern steps in ms (on, off, on, ...)
+	mount_throttle_ms: u64,			   // Minimum spacing between mount patterns
+	heartbeat_ms: u64,				   // Pulse the LED this often while idle (0 = off)
+	edge_only: bool,				   // Blink only when activity resumes after idle
+	idle_ms: u64,					   // Quiet gap that re-arms the edge blink
+	summary_ms: u64,				   // Log an activity summary this often (0 = off)
+	stat_format: StatFormat,		   // How to locate fields in the stat source
+	metrics_file: Option<String>,	   // OpenMetrics text file to rewrite (None = off)
+	metrics_addr: Option<String>,	   // host:port serving GET /metrics (None = off)
+	metrics_interval_ms: u64,		   // How often to rewrite the metrics file
+	csv: Option<String>,			   // Append a throughput time series here (None = off)
+	csv_interval_ms: u64,			   // Least time between CSV rows (0 = every poll)
+	panic_led: PanicLed,			   // LED state left behind on a fatal error
+	exit_led_state: ExitLedState,	   // LED state left behind on SIGTERM/SIGINT
+	pidfile: Option<String>,		   // Write our PID here while running (None = off)
+	control_socket: Option<String>,	   // Unix socket for runtime commands (None = off)
+	user: Option<String>,			   // Drop to this user after opening fds (None = stay)
+	rt_priority: u64,				   // SCHED_FIFO priority to run at (0 = normal scheduling)
+	lock_memory: bool,				   // mlockall() so our pages are never swapped out
+	cpu: Option<usize>,				   // Pin the daemon to this CPU (None = any)
+	led_off: bool,					   // Write the off state once and exit (--led-off)
+	test_led: bool,					   // Blink the LED to check wiring, then exit
+	dry_run: bool,					   // Log LED writes instead of performing them
+	record: Option<String>,			   // Append each poll's stat fields here (None = off)
+	replay: Option<String>,			   // Read activity from this capture instead of devices
+	replay_fast: bool,				   // Replay one sample per poll, not at the recorded pace
+	kernel_trigger: Option<String>,	   // Hand the LED to this kernel trigger and exit
+	probe_io: bool,					   // Read the device and check we see it, then exit
+	monitor: bool,					   // Show throughput in the terminal instead of the LED
+	print_config: bool,				   // Print the resolved config and exit
+	generate_systemd: bool,			   // Print a systemd unit for this setup and exit
+	list_leds: bool,				   // Print the LEDs in /sys/class/leds and exit
+	list_devices: Option<bool>,		   // Print block devices and exit (Some(true) = all, not just NVMe)
+	config_path: Option<String>,	   // Last --config PATH given (None = default file only)
+}
+
+/// Load configuration from a key=value file
+/// 
+/// File format:
+/// - Lines starting with # are comments
+/// - Empty lines are ignored
+/// - Settings are key=value pairs
+/// - Whitespace around = is trimmed
+/// 
+/// Example:
+/// ```
+/// # NVMe LED daemon configuration
+/// led_path=/sys/class/leds/tpacpi::power/brightness
+/// interval_ms=10
+/// blink_ms=20
+/// active_high=false
+/// ```
+fn load_config(path: &str) -> io::Result<HashMap<String, String>> {
+	// Read entire file as string
+	let contents = std::fs::read_to_string(path)?;
+	let mut map = HashMap::new();
+	
+	// Parse line by line
+	for line in contents.lines() {
+		let line = line.trim();
+		
+		// Skip comments and empty lines
+		if line.is_empty() || line.starts_with('#') { 
+			continue; 
+		}
+		
+		// Split on first '=' to get key and value
+		if let Some((k, v)) = line.split_once('=') {
+			// Trim whitespace and store in map
+			map.insert(k.trim().to_string(), v.trim().to_string());
+		}
+	}
+	Ok(map)
+}
+
+/// Collect NVME_LED_* environment variables into a config map
+/// 
+/// The rest of the name, lowercased, is the config file key
+/// (NVME_LED_BLINK_MS -> blink_ms), so values parse exactly as they would
+/// in the file. NVME_LED_PATH is accepted for led_path, which would
+/// otherwise be the stuttering NVME_LED_LED_PATH.
+fn env_config_map() -> HashMap<String, String> {
+	env::vars()
+		.filter_map(|(k, v)| {
+			let key = k.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase();
+			let key = if key == "path" { "led_path".to_string() } else { key };
+			Some((key, v.trim().to_string()))
+		})
+		.collect()
+}
+
+/// Parse boolean from config map with default fallback
+/// Accepts: true/yes/1 for true, false/no/0 for false
+/// Returns default if key not found or value not recognized
+fn get_bool(map: &HashMap<String, String>, key: &str, default: bool) -> bool {
+	map.get(key).and_then(|v| match v.as_str() {
+		"true" | "yes" | "1" => Some(true),
+		"false" | "no" | "0" => Some(false),
+		_ => None  // Unrecognized value, use default
+	}).unwrap_or(default)
+}
+
+/// Parse u64 from config map with default fallback
+/// Returns default if key not found or value not a valid u64
+fn get_u64(map: &HashMap<String, String>, key: &str, default: u64) -> u64 {
+	map.get(key)
+		.and_then(|v| v.parse().ok())  // Try to parse as u64
+		.unwrap_or(default)				// Use default if parse fails
+}
+
+/// Split "250ms" / "1.5 GB" into its number and (possibly empty) unit
+fn split_unit(v: &str) -> Option<(f64, &str)> {
+	let v = v.trim();
+	let i = v.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(v.len());
+	Some((v[..i].parse::<f64>().ok()?, v[i..].trim()))
+}
+
+/// Parse a duration into milliseconds
+/// A bare number is already ms; otherwise `ms`, `s` or `m` suffixes
+/// ("250ms", "5s", "1.5s", "2m")
+fn parse_ms(v: &str) -> Option<u64> {
+	if let Ok(n) = v.trim().parse::<u64>() {
+		return Some(n);
+	}
+	let (n, unit) = split_unit(v)?;
+	let scale = match unit {
+		"ms" => 1.0,
+		"s" => 1000.0,
+		"m" => 60_000.0,
+		_ => return None,
+	};
+	Some((n * scale).round() as u64)
+}
+
+/// A byte threshold in whole sectors: a sector delta exceeds the result
+/// exactly when its bytes exceed `bytes` (so rounded down, not up)
+fn sectors_exceeding(bytes: u64) -> u64 {
+	bytes / SECTOR_BYTES
+}
+
+/// Parse a size into bytes
+/// A bare number is already bytes; otherwise `B`, `KB`/`MB`/`GB` (powers
+/// of 1000) or `KiB`/`MiB`/`GiB` (powers of 1024), in any case ("4MB")
+fn parse_bytes(v: &str) -> Option<u64> {
+	if let Ok(n) = v.trim().parse::<u64>() {
+		return Some(n);
+	}
+	let (n, unit) = split_unit(v)?;
+	let scale = match unit.to_ascii_lowercase().as_str() {
+		"b" => 1.0,
+		"kb" => 1e3,
+		"mb" => 1e6,
+		"gb" => 1e9,
+		"kib" => 1024.0,
+		"mib" => 1024.0 * 1024.0,
+		"gib" => 1024.0 * 1024.0 * 1024.0,
+		_ => return None,
+	};
+	Some((n * scale).round() as u64)
+}
+
+/// Parse a throughput into MB/s
+/// A bare number is already MB/s; otherwise a size per second ("500MB/s",
+/// "1GB/s", "200MiB/s")
+fn parse_mb_s(v: &str) -> Option<u64> {
+	if let Ok(n) = v.trim().parse::<u64>() {
+		return Some(n);
+	}
+	let per_s = parse_bytes(v.trim().strip_suffix("/s")?)?;
+	Some((per_s as f64 / 1e6).round() as u64)
+}
+
+/// Parse a duration (see parse_ms) from config map with default fallback
+fn get_ms(map: &HashMap<String, String>, key: &str, default: u64) -> u64 {
+	map.get(key).and_then(|v| parse_ms(v)).unwrap_or(default)
+}
+
+/// Get string from config map with default fallback
+/// Returns reference to value in map, or default if key not found
+fn get_str<'a>(map: &'a HashMap<String, String>, key: &str, default: &'a str) -> &'a str {
+	map.get(key)
+		.map(|s| s.as_str())  // Convert String to &str
+		.unwrap_or(default)    // Use default if key not found
+}
+
+impl Default for Config {
+	/// Hard-coded defaults (DEFAULT_* constants), before any file or CLI layer
+	fn default() -> Self {
+		Self {
+			led_path: DEFAULT_LED_PATH.to_string(),
+			led_slot: None,
+			led_slot_attr: "active".to_string(),
+			read_led_path: None,
+			write_led_path: None,
+			led_open_policy: LedOpenPolicy::BestEffort,
+			rgb_paths: [None, None, None],
+			read_color: parse_color(DEFAULT_READ_COLOR).unwrap(),
+			write_color: parse_color(DEFAULT_WRITE_COLOR).unwrap(),
+			full_scale_mb_s: None,
+			full_scale_depth: None,
+			brightness_hysteresis: 0,
+			pwm: false,
+			pwm_freq_hz: DEFAULT_PWM_FREQ_HZ,
+			pwm_duty: DEFAULT_PWM_DUTY,
+			fade_ms: 0,
+			fade_curve: FadeCurve::Linear,
+			nvme_path: String::new(),  // Unset: detected at startup
+			nvme_name: None,
+			nvme_controller: None,
+			hotplug: None,
+			source: Source::Stat,
+			device: String::new(),
+			poll_ms: DEFAULT_POLL_INTERVAL_MS,
+			max_interval_ms: 0,
+			min_read: 0,
+			min_write: 0,
+			min_in_bytes: false,
+			smoothing: 1.0,
+			backoff_factor: DEFAULT_BACKOFF_FACTOR,
+			backoff_after: DEFAULT_BACKOFF_AFTER,
+			blink_ms: DEFAULT_BLINK_ON_MS,
+			blink_ms_set: false,
+			blink_pct: None,
+			read_blink_ms: None,
+			discard_blink_ms: None,
+			min_on_ms: 0,
+			min_off_ms: 0,
+			write_blink_ms: None,
+			active_high: false,
+			log_level: Level::Info,
+			log_target: LogTarget::Stdout,
+			nvme_mode: NvmeMode::Sectors,
+			on_fields: FieldsSel::Both,
+			mixed_priority: MixedPriority::Write,
+			reconcile_ms: 0,
+			startup_grace_ms: 0,
+			startup_blink: false,
+			resume_blink: false,
+			aggregate_members: false,
+			align_polls: false,
+			boottime_timers: false,
+			stat_notify: false,
+			max_devices: DEFAULT_MAX_DEVICES,
+			mount_blink: false,
+			mount_pattern: parse_pattern(DEFAULT_MOUNT_PATTERN).unwrap_or_default(),
+			mount_throttle_ms: DEFAULT_MOUNT_THROTTLE_MS,
+			heartbeat_ms: 0,
+			rt_priority: 0,
+			lock_memory: false,
+			cpu: None,
+			edge_only: false,
+			idle_ms: DEFAULT_IDLE_MS,
+			summary_ms: 0,
+			stat_format: StatFormat::Indexed,
+			metrics_file: None,
+			metrics_addr: None,
+			metrics_interval_ms: DEFAULT_METRICS_INTERVAL_MS,
+			csv: None,
+			csv_interval_ms: 0,
+			panic_led: PanicLed::None,
+			exit_led_state: ExitLedState::Off,
+			pidfile: None,
+			control_socket: None,
+			user: None,
+			
+			// Administrative actions are CLI-only
+			led_off: false,
+			test_led: false,
+			dry_run: false,
+			record: None,
+			replay: None,
+			replay_fast: false,
+			kernel_trigger: None,
+			probe_io: false,
+			monitor: false,
+			print_config: false,
+			generate_systemd: false,
+			list_leds: false,
+			list_devices: None,
+			config_path: None,
+		}
+	}
+}
+
+/// Apply settings from a parsed config file on top of an existing Config
+/// 
+/// Keys missing from the map keep their current value, so this is used both
+/// for the default config file and for a later `--config PATH`.
+fn apply_config_map(cfg: &mut Config, map: &HashMap<String, String>) {
+	cfg.led_path = get_str(map, "led_path", &cfg.led_path).to_string();
+	if let Some(v) = map.get("led_slot") {
+		cfg.led_slot = Some(v.clone());
+	}
+	cfg.led_slot_attr = get_str(map, "led_slot_attr", &cfg.led_slot_attr).to_string();
+	if let Some(v) = map.get("read_led_path") {
+		cfg.read_led_path = Some(v.clone());
+	}
+	if let Some(v) = map.get("write_led_path") {
+		cfg.write_led_path = Some(v.clone());
+	}
+	for (i, key) in ["red_path", "green_path", "blue_path"].into_iter().enumerate() {
+		if let Some(v) = map.get(key) {
+			cfg.rgb_paths[i] = Some(v.clone());
+		}
+	}
+	if let Some(v) = map.get("full_scale_mb_s").and_then(|v| parse_mb_s(v)) { 
+		cfg.full_scale_mb_s = Some(v); 
+	}
+	if let Some(v) = map.get("full_scale_depth").and_then(|v| v.parse::<u64>().ok()) { 
+		cfg.full_scale_depth = Some(v.max(1)); 
+	}
+	cfg.brightness_hysteresis = get_u64(map, "brightness_hysteresis", cfg.brightness_hysteresis as u64) as u32;
+	cfg.pwm = get_bool(map, "pwm", cfg.pwm);
+	cfg.pwm_freq_hz = get_u64(map, "pwm_freq_hz", cfg.pwm_freq_hz).max(MIN_PWM_FREQ_HZ);
+	cfg.pwm_duty = get_u64(map, "pwm_duty", cfg.pwm_duty).min(100);
+	cfg.fade_ms = get_ms(map, "fade_ms", cfg.fade_ms);
+	cfg.fade_curve = match get_str(map, "fade_curve",
+		match cfg.fade_curve {
+			FadeCurve::Linear => "linear",
+			FadeCurve::Exp => "exp"
+		}) {
+		"exp" => FadeCurve::Exp,
+		_ => FadeCurve::Linear,  // Default to linear for any other value
+	};
+	if let Some(c) = map.get("read_color").and_then(|v| parse_color(v)) {
+		cfg.read_color = c;
+	}
+	if let Some(c) = map.get("write_color").and_then(|v| parse_color(v)) {
+		cfg.write_color = c;
+	}
+	cfg.led_open_policy = match get_str(map, "led_open_policy",
+		match cfg.led_open_policy {
+			LedOpenPolicy::Strict => "strict",
+			LedOpenPolicy::BestEffort => "best-effort"
+		}) {
+		"strict" => LedOpenPolicy::Strict,
+		_ => LedOpenPolicy::BestEffort,  // Default to best-effort for any other value
+	};
+	cfg.nvme_path = get_str(map, "nvme_path", &cfg.nvme_path).to_string();
+	cfg.source = match get_str(map, "source",
+		match cfg.source {
+			Source::Stat => "stat",
+			Source::DiskStats => "diskstats"
+		}) {
+		"diskstats" => Source::DiskStats,
+		_ => Source::Stat,  // Default to stat files for any other value
+	};
+	cfg.device = get_str(map, "device", &cfg.device).to_string();
+	if let Some(v) = map.get("nvme_name") {
+		cfg.nvme_name = Some(v.clone());
+	}
+	if let Some(v) = map.get("nvme_controller") {
+		cfg.nvme_controller = Some(v.clone()).filter(|v| !v.is_empty());
+	}
+	if let Some(v) = map.get("hotplug") {
+		cfg.hotplug = Some(v.clone()).filter(|v| !v.is_empty());
+	}
+	cfg.poll_ms = get_ms(map, "interval_ms", cfg.poll_ms);
+	// min_interval_ms reads better next to max_interval_ms; same setting
+	cfg.poll_ms = get_ms(map, "min_interval_ms", cfg.poll_ms);
+	cfg.max_interval_ms = get_ms(map, "max_interval_ms", cfg.max_interval_ms);
+	
+	// Activity thresholds, general before per-direction so the specific
+	// keys win; byte counts become whole sectors (see sectors_exceeding)
+	if let Some(v) = map.get("min_sectors").and_then(|v| v.parse::<u64>().ok()) {
+		(cfg.min_read, cfg.min_write, cfg.min_in_bytes) = (v, v, false);
+	}
+	if let Some(v) = map.get("min_bytes").and_then(|v| parse_bytes(v)) {
+		let v = sectors_exceeding(v);
+		(cfg.min_read, cfg.min_write, cfg.min_in_bytes) = (v, v, true);
+	}
+	if let Some(v) = map.get("min_read_bytes").and_then(|v| parse_bytes(v)) {
+		(cfg.min_read, cfg.min_in_bytes) = (sectors_exceeding(v), true);
+	}
+	if let Some(v) = map.get("min_write_bytes").and_then(|v| parse_bytes(v)) {
+		(cfg.min_write, cfg.min_in_bytes) = (sectors_exceeding(v), true);
+	}
+	if let Some(v) = map.get("smoothing").and_then(|v| v.parse::<f64>().ok()).filter(|v| *v > 0.0 && *v <= 1.0) {
+		cfg.smoothing = v;
+	}
+	if let Some(v) = map.get("backoff_factor").and_then(|v| v.parse::<f64>().ok()).filter(|v| *v > 1.0) {
+		cfg.backoff_factor = v;
+	}
+	cfg.backoff_after = get_u64(map, "backoff_after", cfg.backoff_after).max(1);
+	if map.contains_key("blink_ms") {
+		cfg.blink_ms = get_ms(map, "blink_ms", cfg.blink_ms);
+		cfg.blink_ms_set = true;
+	}
+	
+	// Optional values: only override if present in the map
+	if let Some(v) = map.get("blink_pct").and_then(|v| v.parse().ok()) { 
+		cfg.blink_pct = Some(v); 
+	}
+	if let Some(v) = map.get("read_blink_ms").and_then(|v| parse_ms(v)) { 
+		cfg.read_blink_ms = Some(v); 
+	}
+	if let Some(v) = map.get("write_blink_ms").and_then(|v| parse_ms(v)) { 
+		cfg.write_blink_ms = Some(v); 
+	}
+	if let Some(v) = map.get("discard_blink_ms").and_then(|v| parse_ms(v)) { 
+		cfg.discard_blink_ms = Some(v); 
+	}
+	cfg.min_on_ms = get_ms(map, "min_on_ms", cfg.min_on_ms);
+	cfg.min_off_ms = get_ms(map, "min_off_ms", cfg.min_off_ms);
+	
+	cfg.active_high = get_bool(map, "active_high", cfg.active_high);
+	cfg.reconcile_ms = get_ms(map, "reconcile_ms", cfg.reconcile_ms);
+	cfg.startup_grace_ms = get_ms(map, "startup_grace_ms", cfg.startup_grace_ms);
+	cfg.startup_blink = get_bool(map, "startup_blink", cfg.startup_blink);
+	cfg.resume_blink = get_bool(map, "resume_blink", cfg.resume_blink);
+	
+	// quiet = errors only, verbose = everything. A file has no ordering,
+	// so if one sets both, verbose wins, and an explicit log_level wins
+	// over either; across layers the last one wins.
+	if get_bool(map, "quiet", false) { cfg.log_level = Level::Error; }
+	if get_bool(map, "verbose", false) { cfg.log_level = Level::Debug; }
+	if let Some(l) = map.get("log_level").and_then(|v| Level::parse(v)) {
+		cfg.log_level = l;
+	}
+	cfg.log_target = match get_str(map, "log",
+		match cfg.log_target {
+			LogTarget::Stdout => "stdout",
+			LogTarget::Syslog => "syslog",
+			LogTarget::Journald => "journald"
+		}) {
+		"syslog" => LogTarget::Syslog,
+		"journald" => LogTarget::Journald,
+		_ => LogTarget::Stdout,  // Default to stdout for any other value
+	};
+	cfg.aggregate_members = get_bool(map, "aggregate_members", cfg.aggregate_members);
+	cfg.align_polls = get_bool(map, "align_polls", cfg.align_polls);
+	cfg.boottime_timers = get_bool(map, "boottime_timers", cfg.boottime_timers);
+	cfg.stat_notify = get_bool(map, "stat_notify", cfg.stat_notify);
+	cfg.max_devices = get_u64(map, "max_devices", cfg.max_devices as u64) as usize;
+	cfg.mount_blink = get_bool(map, "mount_blink", cfg.mount_blink);
+	if let Some(p) = map.get("mount_pattern").and_then(|v| parse_pattern(v)) {
+		cfg.mount_pattern = p;
+	}
+	cfg.mount_throttle_ms = get_ms(map, "mount_throttle_ms", cfg.mount_throttle_ms);
+	cfg.heartbeat_ms = get_ms(map, "heartbeat_ms", cfg.heartbeat_ms);
+	cfg.rt_priority = get_u64(map, "rt_priority", cfg.rt_priority).min(RT_PRIORITY_MAX);
+	cfg.lock_memory = get_bool(map, "lock_memory", cfg.lock_memory);
+	if let Some(v) = map.get("cpu").and_then(|v| v.parse::<usize>().ok()) {
+		cfg.cpu = Some(v);
+	}
+	cfg.edge_only = get_bool(map, "edge_only", cfg.edge_only);
+	cfg.idle_ms = get_ms(map, "idle_ms", cfg.idle_ms);
+	cfg.summary_ms = get_ms(map, "summary_ms", cfg.summary_ms);
+	if let Some(v) = map.get("metrics_file") {
+		cfg.metrics_file = Some(v.clone());
+	}
+	if let Some(v) = map.get("metrics_addr").filter(|v| !v.is_empty()) {
+		cfg.metrics_addr = Some(v.clone());
+	}
+	// As on the CLI: 0 can't be armed as a periodic timer
+	cfg.metrics_interval_ms = get_ms(map, "metrics_interval_ms", cfg.metrics_interval_ms).max(1);
+	if let Some(v) = map.get("csv") {
+		cfg.csv = Some(v.clone());
+	}
+	cfg.csv_interval_ms = get_ms(map, "csv_interval_ms", cfg.csv_interval_ms);
+	if let Some(v) = map.get("kernel_trigger").filter(|v| !v.is_empty()) {
+		cfg.kernel_trigger = Some(v.clone());
+	}
+	if let Some(v) = map.get("pidfile") {
+		cfg.pidfile = Some(v.clone());
+	}
+	if let Some(v) = map.get("control_socket") {
+		cfg.control_socket = Some(v.clone());
+	}
+	if let Some(v) = map.get("user") {
+		cfg.user = Some(v.clone());
+	}
+	cfg.stat_format = match get_str(map, "stat_format",
+		match cfg.stat_format {
+			StatFormat::Indexed => "indexed",
+			StatFormat::Named => "named"
+		}) {
+		"named" => StatFormat::Named,
+		_ => StatFormat::Indexed,  // Default to indexed for any other value
+	};
+	cfg.panic_led = match get_str(map, "panic_led",
+		match cfg.panic_led {
+			PanicLed::None => "none",
+			PanicLed::On => "on",
+			PanicLed::Strobe => "strobe"
+		}) {
+		"on" => PanicLed::On,
+		"strobe" => PanicLed::Strobe,
+		_ => PanicLed::None,  // Default to none for any other value
+	};
+	cfg.exit_led_state = match get_str(map, "exit_led_state",
+		match cfg.exit_led_state {
+			ExitLedState::Off => "off",
+			ExitLedState::On => "on",
+			ExitLedState::Restore => "restore"
+		}) {
+		"on" => ExitLedState::On,
+		"restore" => ExitLedState::Restore,
+		_ => ExitLedState::Off,  // Default to off for any other value
+	};
+	
+	// Parse enum values with current value as default
+	cfg.nvme_mode = match get_str(map, "nvme_mode", 
+		match cfg.nvme_mode { 
+			NvmeMode::Io => "io", 
+			NvmeMode::Sectors => "sectors",
+			NvmeMode::InFlight => "inflight",
+			NvmeMode::Busy => "busy"
+		}) {
+		"io" => NvmeMode::Io,
+		"inflight" => NvmeMode::InFlight,
+		"busy" => NvmeMode::Busy,
+		_ => NvmeMode::Sectors,  // Default to sectors for any other value
+	};
+	
+	cfg.on_fields = match get_str(map, "on_fields", 
+		match cfg.on_fields { 
+			FieldsSel::Reads => "reads", 
+			FieldsSel::Writes => "writes", 
+			FieldsSel::Both => "both",
+			FieldsSel::Discards => "discards",
+			FieldsSel::All => "all"
+		}) {
+		"reads" => FieldsSel::Reads,
+		"writes" => FieldsSel::Writes,
+		"discards" => FieldsSel::Discards,
+		"all" => FieldsSel::All,
+		_ => FieldsSel::Both,  // Default to both for any other value
+	};
+	
+	cfg.mixed_priority = match get_str(map, "mixed_priority",
+		match cfg.mixed_priority {
+			MixedPriority::Read => "read",
+			MixedPriority::Write => "write",
+			MixedPriority::Longer => "longer",
+			MixedPriority::Shorter => "shorter"
+		}) {
+		"read" => MixedPriority::Read,
+		"longer" => MixedPriority::Longer,
+		"shorter" => MixedPriority::Shorter,
+		_ => MixedPriority::Write,  // Default to write for any other value
+	};
+}
+
+/// Render a resolved Config as a config file (the format load_config reads)
+/// 
+/// Every setting is written, so the output shows what actually took effect
+/// after the file, environment and CLI layers, and can be saved as a
+/// starting config. Unset optional settings are left out; CLI-only
+/// actions (--led-off, --probe-io, ...) aren't settings and never appear.
+fn render_config(cfg: &Config) -> String {
+	let mut out = String::from("# nvme-led-daemon effective configuration\n");
+	let mut kv = |k: &str, v: &dyn std::fmt::Display| out.push_str(&format!("{}={}\n", k, v));
+	let color = |(r, g, b): (u8, u8, u8)| format!("#{:02x}{:02x}{:02x}", r, g, b);
+	
+	kv("led_path", &cfg.led_path);
+	if let Some(v) = &cfg.led_slot { kv("led_slot", v); }
+	kv("led_slot_attr", &cfg.led_slot_attr);
+	if let Some(v) = &cfg.read_led_path { kv("read_led_path", v); }
+	if let Some(v) = &cfg.write_led_path { kv("write_led_path", v); }
+	kv("led_open_policy", &match cfg.led_open_policy {
+		LedOpenPolicy::Strict => "strict",
+		LedOpenPolicy::BestEffort => "best-effort"
+	});
+	for (key, path) in ["red_path", "green_path", "blue_path"].into_iter().zip(&cfg.rgb_paths) {
+		if let Some(v) = path { kv(key, v); }
+	}
+	kv("read_color", &color(cfg.read_color));
+	kv("write_color", &color(cfg.write_color));
+	if let Some(v) = cfg.full_scale_mb_s { kv("full_scale_mb_s", &v); }
+	if let Some(v) = cfg.full_scale_depth { kv("full_scale_depth", &v); }
+	kv("brightness_hysteresis", &cfg.brightness_hysteresis);
+	kv("pwm", &cfg.pwm);
+	kv("pwm_freq_hz", &cfg.pwm_freq_hz);
+	kv("pwm_duty", &cfg.pwm_duty);
+	kv("fade_ms", &cfg.fade_ms);
+	kv("fade_curve", &match cfg.fade_curve {
+		FadeCurve::Linear => "linear",
+		FadeCurve::Exp => "exp"
+	});
+	kv("nvme_path", &cfg.nvme_path);
+	if let Some(v) = &cfg.nvme_name { kv("nvme_name", v); }
+	if let Some(v) = &cfg.nvme_controller { kv("nvme_controller", v); }
+	if let Some(v) = &cfg.hotplug { kv("hotplug", v); }
+	kv("source", &match cfg.source {
+		Source::Stat => "stat",
+		Source::DiskStats => "diskstats"
+	});
+	kv("device", &cfg.device);
+	kv("interval_ms", &cfg.poll_ms);
+	kv("max_interval_ms", &cfg.max_interval_ms);
+	kv("backoff_factor", &cfg.backoff_factor);
+	kv("backoff_after", &cfg.backoff_after);
+	
+	// Thresholds are stored in mode units; the per-direction keys take
+	// bytes, which round-trip exactly since sectors_exceeding divides them
+	// back down
+	if cfg.min_read == cfg.min_write {
+		kv("min_sectors", &cfg.min_read);
+	} else {
+		kv("min_read_bytes", &(cfg.min_read * SECTOR_BYTES));
+		kv("min_write_bytes", &(cfg.min_write * SECTOR_BYTES));
+	}
+	kv("smoothing", &cfg.smoothing);
+	
+	// blink_ms in a file counts as explicit and would override blink_pct
+	if cfg.blink_ms_set || cfg.blink_pct.is_none() { kv("blink_ms", &cfg.blink_ms); }
+	if let Some(v) = cfg.blink_pct { kv("blink_pct", &v); }
+	if let Some(v) = cfg.read_blink_ms { kv("read_blink_ms", &v); }
+	if let Some(v) = cfg.write_blink_ms { kv("write_blink_ms", &v); }
+	if let Some(v) = cfg.discard_blink_ms { kv("discard_blink_ms", &v); }
+	kv("min_on_ms", &cfg.min_on_ms);
+	kv("min_off_ms", &cfg.min_off_ms);
+	kv("active_high", &cfg.active_high);
+	kv("nvme_mode", &match cfg.nvme_mode {
+		NvmeMode::Sectors => "sectors",
+		NvmeMode::Io => "io",
+		NvmeMode::InFlight => "inflight",
+		NvmeMode::Busy => "busy"
+	});
+	kv("stat_format", &match cfg.stat_format {
+		StatFormat::Indexed => "indexed",
+		StatFormat::Named => "named"
+	});
+	kv("on_fields", &match cfg.on_fields {
+		FieldsSel::Reads => "reads",
+		FieldsSel::Writes => "writes",
+		FieldsSel::Both => "both",
+		FieldsSel::Discards => "discards",
+		FieldsSel::All => "all"
+	});
+	kv("mixed_priority", &match cfg.mixed_priority {
+		MixedPriority::Read => "read",
+		MixedPriority::Write => "write",
+		MixedPriority::Longer => "longer",
+		MixedPriority::Shorter => "shorter"
+	});
+	kv("reconcile_ms", &cfg.reconcile_ms);
+	kv("startup_grace_ms", &cfg.startup_grace_ms);
+	kv("startup_blink", &cfg.startup_blink);
+	kv("resume_blink", &cfg.resume_blink);
+	kv("log_level", &cfg.log_level.name());
+	kv("log", &match cfg.log_target {
+		LogTarget::Stdout => "stdout",
+		LogTarget::Syslog => "syslog",
+		LogTarget::Journald => "journald"
+	});
+	kv("aggregate_members", &cfg.aggregate_members);
+	kv("align_polls", &cfg.align_polls);
+	kv("boottime_timers", &cfg.boottime_timers);
+	kv("stat_notify", &cfg.stat_notify);
+	kv("max_devices", &cfg.max_devices);
+	kv("mount_blink", &cfg.mount_blink);
+	kv("mount_pattern", &cfg.mount_pattern.iter().map(u64::to_string).collect::<Vec<_>>().join(","));
+	kv("mount_throttle_ms", &cfg.mount_throttle_ms);
+	kv("heartbeat_ms", &cfg.heartbeat_ms);
+	kv("edge_only", &cfg.edge_only);
+	kv("idle_ms", &cfg.idle_ms);
+	kv("summary_ms", &cfg.summary_ms);
+	if let Some(v) = &cfg.metrics_file { kv("metrics_file", v); }
+	if let Some(v) = &cfg.metrics_addr { kv("metrics_addr", v); }
+	kv("metrics_interval_ms", &cfg.metrics_interval_ms);
+	if let Some(v) = &cfg.csv { kv("csv", v); }
+	kv("csv_interval_ms", &cfg.csv_interval_ms);
+	if let Some(v) = &cfg.kernel_trigger { kv("kernel_trigger", v); }
+	if let Some(v) = &cfg.pidfile { kv("pidfile", v); }
+	if let Some(v) = &cfg.control_socket { kv("control_socket", v); }
+	if let Some(v) = &cfg.user { kv("user", v); }
+	kv("rt_priority", &cfg.rt_priority);
+	kv("lock_memory", &cfg.lock_memory);
+	if let Some(v) = &cfg.cpu { kv("cpu", v); }
+	kv("exit_led_state", &match cfg.exit_led_state {
+		ExitLedState::Off => "off",
+		ExitLedState::On => "on",
+		ExitLedState::Restore => "restore"
+	});
+	kv("panic_led", &match cfg.panic_led {
+		PanicLed::None => "none",
+		PanicLed::On => "on",
+		PanicLed::Strobe => "strobe"
+	});
+	out
+}
+
+/// Render a systemd service unit that runs this binary with this config
+/// 
+/// `exe` is the daemon's own path (from /proc/self/exe), so the unit works
+/// wherever it was installed. Matches the daemon's systemd support:
+/// Type=notify for READY=1, a watchdog it pings, SIGHUP for reloads, and
+/// --led-off after stop. The sandbox leaves only the LED, metrics and
+/// PID file directories writable.
+fn render_systemd_unit(cfg: &Config, exe: &str) -> String {
+	let config = match &cfg.config_path {
+		Some(p) => format!(" --config {}", p),
+		None => String::new(),
+	};
+	
+	// Every LED we may write (main, per-direction, RGB channels), plus
+	// the metrics, CSV and PID files and the control socket
+	let mut dirs: Vec<String> = Vec::new();
+	let leds = [Some(&cfg.led_path), cfg.read_led_path.as_ref(), cfg.write_led_path.as_ref()];
+	let files = [cfg.metrics_file.as_ref(), cfg.csv.as_ref(), cfg.pidfile.as_ref(), cfg.control_socket.as_ref()];
+	for path in leds.into_iter().chain(cfg.rgb_paths.iter().map(Option::as_ref)).chain(files).flatten() {
+		if let Some(dir) = std::path::Path::new(path).parent() {
+			let dir = dir.to_string_lossy().into_owned();
+			if !dirs.contains(&dir) {
+				dirs.push(dir);
+			}
+		}
+	}
+	
+	format!(
+"# Generated by nvme-led-daemon --generate-systemd
+# Settings are read from {default_cfg}{config_note}
+[Unit]
+Description=NVMe Power LED Activity Monitor
+After=multi-user.target
+
+[Service]
+Type=notify
+ExecStart={exe}{config}
+ExecReload=/bin/kill -HUP $MAINPID
+ExecStopPost={exe}{config} --led-off
+Restart=on-failure
+RestartPreventExitStatus={usage} {bad_config} {denied}
+WatchdogSec=10
+Nice=-10
+ProtectSystem=full
+ProtectHome=true
+PrivateTmp=true
+NoNewPrivileges=true
+ReadOnlyDirectories=/
+ReadWriteDirectories={dirs}
+
+[Install]
+WantedBy=multi-user.target
+",
+		default_cfg = DEFAULT_CONFIG_PATH,
+		usage = EXIT_USAGE,
+		bad_config = EXIT_CONFIG,
+		denied = EXIT_PERMISSION,
+		config_note = match &cfg.config_path {
+			Some(p) => format!(", then {}", p),
+			None => String::new(),
+		},
+		exe = exe,
+		config = config,
+		dirs = dirs.join(" "),
+	)
+}
+
+/// Parse the numeric value following a CLI flag, exiting with status 2
+/// if it is missing or not a valid u64
+fn next_u64(it: &mut impl Iterator<Item = String>, flag: &str) -> u64 {
+	it.next()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or_else(|| { 
+			eprintln!("invalid {}", flag); 
+			process::exit(EXIT_USAGE) 
+		})
+}
+
+/// Parse the duration following a CLI flag (see parse_ms), exiting with
+/// status 2 if it is missing or invalid
+fn next_ms(it: &mut impl Iterator<Item = String>, flag: &str) -> u64 {
+	it.next()
+		.and_then(|v| parse_ms(&v))
+		.unwrap_or_else(|| { 
+			eprintln!("invalid {}", flag); 
+			process::exit(EXIT_USAGE) 
+		})
+}
+
+/// Parse the size following a CLI flag (see parse_bytes), exiting with
+/// status 2 if it is missing or invalid
+fn next_bytes(it: &mut impl Iterator<Item = String>, flag: &str) -> u64 {
+	it.next()
+		.and_then(|v| parse_bytes(&v))
+		.unwrap_or_else(|| { 
+			eprintln!("invalid {}", flag); 
+			process::exit(EXIT_USAGE) 
+		})
+}
+
+/// Print help message and exit with `code`
+/// Called when user passes --help (0) or invalid arguments (EXIT_USAGE)
+fn help(code: i32) -> ! {
+	eprintln!(
+"nvme-led-daemon (GPL-3.0-or-later)
+Usage:
+  nvme-led-daemon [--config PATH] [OPTIONS]
+
+Config file (optional): {default_cfg}
+{env}KEY environment variables (e.g. {env}BLINK_MS) override the file
+and any --config file; CLI options override all of them.
+
+Options:
+  --config PATH    Load config from PATH
+  --led PATH
+  --led-slot [ENCLOSURE/]SLOT  Use an enclosure slot's LED instead of --led
+  --led-slot-attr active|locate|fault  Which slot LED (default active)
+  --read-led PATH  Separate LED for reads (default: the main LED)
+  --write-led PATH Separate LED for writes (default: the main LED)
+  --led-open-policy strict|best-effort  If a --read/--write-led fails to open
+  --red PATH --green PATH --blue PATH  RGB LED channels; activity shows colors
+  --full-scale-mb-s N  Dimmable LEDs: brightness tracks throughput, full at N MB/s
+  --full-scale-depth N  Same for --nvme-mode inflight: full at N queued I/Os
+  --brightness-hysteresis N  Only re-level a lit LED when it moves by more than N
+  --pwm            Software-PWM dim blinks on on/off LEDs
+  --pwm-freq-hz N  PWM frequency (default {pf}, minimum {pmin})
+  --pwm-duty N     PWM duty % at light I/O; rises with --full-scale-mb-s (default {pd})
+  --fade-ms N      Dimmable LEDs: fade out over N ms instead of a hard off
+  --fade-curve linear|exp  Shape of the fade (default linear)
+  --read-color #RRGGBB   RGB color for reads (default {rc})
+  --write-color #RRGGBB  RGB color for writes (default {wc})
+  --nvme PATH      Stat file or device name (repeat to monitor several
+                   devices; default: detected)
+  --source stat|diskstats  Per-device stat files or /proc/diskstats
+  --device NAME    Block device(s) by name: nvme0n1, sda, mmcblk0, dm-0
+                   (comma list; /sys/block/NAME/stat or the diskstats line)
+  --nvme-name NAME Label(s) for the device(s) in output (default: basename)
+  --nvme-controller NAME  Sum all namespaces of a controller (e.g. nvme0),
+                   following namespaces as they come and go
+  --interval-ms N
+  --min-interval-ms N  Same as --interval-ms (the fast, active interval)
+  --max-interval-ms N  Back off toward N ms while idle (default 0 = fixed)
+  --backoff-factor F   Interval growth per backoff step (default {bf})
+  --backoff-after N    Idle polls per backoff step (default {ba})
+  --min-sectors N  Ignore polls moving a counter by N or less (mode units)
+  --min-bytes N    Same in bytes (sectors mode); also --min-read-bytes N
+                   and --min-write-bytes N per direction
+  --smoothing A    Average activity over polls (EMA weight 0..1, default 1 = off)
+  --blink-ms N
+  --blink-pct N    Default blink as N% of the poll interval (--blink-ms wins)
+  --read-blink-ms N
+  --write-blink-ms N
+  --discard-blink-ms N
+  --min-on-ms N    Keep each blink lit for at least N ms (0 = off)
+  --min-off-ms N   Don't relight within N ms of a blink ending (0 = off)
+  --reconcile-ms N  Re-read LED brightness every N ms (0 = off)
+  --startup-grace-ms N  Don't blink for the first N ms after start
+  --startup-blink  Blink three times (or ramp if dimmable) at startup
+  --resume-blink   Blink twice after the system resumes from suspend
+  --max-devices N  Monitor at most N devices (default {md})
+  --on-fields reads|writes|both|discards|all  (discards = TRIM; all adds them to both)
+  --mixed-priority read|write|longer|shorter  Direction shown when reads and
+                   writes both moved in one poll (default write)
+  --nvme-mode io|sectors|inflight|busy  (inflight = lit while I/Os are queued,
+                   busy = brightness follows % of time the disk is busy)
+  --stat-format indexed|named  Fixed field positions or header-named columns
+  --active-high
+  --aggregate-members  For an md array, sum its member disks' activity
+  --hotplug PATTERN  Also monitor disks matching PATTERN (e.g. 'nvme*'),
+                   picking them up and dropping them as they come and go
+  --md NAME        Monitor md array NAME through its member disks (following
+                   rebuilds and spares); same as --nvme NAME --aggregate-members
+  --align-polls    Align poll ticks to round interval boundaries
+  --boottime-timers  Keep interval timers counting through suspend
+  --stat-notify    Poll when the stat file signals a change, if it ever does
+                   (timer polling carries on until then)
+  --summary-ms N   Log an activity summary every N ms (0 = off)
+  --metrics-file PATH  Rewrite an OpenMetrics text file with the counters
+  --metrics-interval-ms N  How often to rewrite it (default {mi})
+  --csv FILE       Append read/write bytes, IOPS and busy % per poll as CSV
+  --csv-interval-ms N  Write a CSV row at most every N ms (default 0 = every poll)
+  --metrics-addr HOST:PORT  Serve the counters at http://HOST:PORT/metrics
+  --edge-only      Blink only when activity resumes after --idle-ms of quiet
+  --idle-ms N      Idle gap that re-arms the edge-only blink (default {im})
+  --mount-blink    Play a pattern when filesystems are mounted/unmounted
+  --mount-pattern MS,MS,...  On/off step durations (default {mp})
+  --mount-throttle-ms N  Minimum gap between mount patterns (default {mt})
+  --heartbeat-ms N Pulse the LED every N ms while the disk is idle (0 = off)
+  --user NAME      Drop to this user once the LED and timers are open
+  --rt-priority N  Run as SCHED_FIFO at priority N (1-99; needs CAP_SYS_NICE)
+  --lock-memory    Lock the daemon's memory so it's never swapped out
+  --cpu N          Pin the daemon to CPU N
+  --pidfile PATH    Write the PID here; refuse to start if it's already running
+  --control-socket PATH  Accept status/set/led/pause/resume commands on this Unix socket
+  --exit-led-state off|on|restore  LED state to leave on SIGTERM/SIGINT
+  --panic-led none|on|strobe  LED state to leave behind on a fatal error
+  --quiet          Only log errors
+  --verbose        Also log debug traces (last of --quiet/--verbose wins)
+  --log-level error|warn|info|debug  Set the threshold directly (default info)
+  --log stdout|syslog|journald  Log to stdout/stderr (default), syslog via /dev/log
+                   or journald's native socket (with structured fields)
+  --dry-run        Monitor as usual but log LED writes instead of making them
+  --record FILE    Append every change in the stat counters to FILE
+  --replay FILE    Take activity from a --record capture instead of the devices
+                   (at the recorded pace; exits when it ends)
+  --replay-fast    Replay one sample per poll instead
+  --led-off        Turn the LED off and exit (no monitoring)
+  --test-led       Blink the LED (and sweep brightness if dimmable), then exit
+  --kernel-trigger Let the kernel's {kt} trigger drive the LED and exit
+                   (falls back to polling if the kernel lacks it)
+  --probe-io       Read a little from the device and report whether the
+                   read counters moved, then exit (reads only)
+  --monitor        Show read/write MB/s, IOPS, busy % and a sparkline in the
+                   terminal instead of driving the LED (Ctrl-C quits)
+  --print-config   Print the effective configuration (file format) and exit
+  --generate-systemd  Print a systemd unit running this binary and exit
+  --list-leds      List LEDs with their brightness and whether writable, then exit
+  --list-devices   List NVMe devices with model, size and stat path, then exit
+  --list-all-devices  Same for every block device
+  --version, -V    Print version and build commit
+  --help
+
+Defaults:
+  led_path	  {lp}
+  nvme_path    auto (NVMe device holding /, else the first one)
+  interval_ms	 {pi}
+  blink_ms	  {bm}
+  nvme_mode    sectors
+  on_fields    both
+",
+		default_cfg = DEFAULT_CONFIG_PATH,
+		env = ENV_PREFIX,
+		lp = DEFAULT_LED_PATH, 
+		pi = DEFAULT_POLL_INTERVAL_MS, 
+		bm = DEFAULT_BLINK_ON_MS,
+		md = DEFAULT_MAX_DEVICES,
+		mp = DEFAULT_MOUNT_PATTERN,
+		mt = DEFAULT_MOUNT_THROTTLE_MS,
+		mi = DEFAULT_METRICS_INTERVAL_MS,
+		pf = DEFAULT_PWM_FREQ_HZ,
+		pmin = MIN_PWM_FREQ_HZ,
+		pd = DEFAULT_PWM_DUTY,
+		kt = DEFAULT_KERNEL_TRIGGER,
+		bf = DEFAULT_BACKOFF_FACTOR,
+		ba = DEFAULT_BACKOFF_AFTER,
+		rc = DEFAULT_READ_COLOR,
+		wc = DEFAULT_WRITE_COLOR,
+		im = DEFAULT_IDLE_MS
+	);
+	process::exit(code)
+}
+
+/// Print version (and the git commit, if known) and exit
+/// Called when user passes --version
+fn version() -> ! {
+	match GIT_HASH {
+		Some(hash) => println!("nvme-led-daemon {} ({})", env!("CARGO_PKG_VERSION"), hash),
+		None => println!("nvme-led-daemon {}", env!("CARGO_PKG_VERSION")),
+	}
+	process::exit(0)
+}
+
+/// Startup configuration: load_config_layers(), exiting with EXIT_CONFIG
+/// if the files or settings are unusable
+fn parse_args() -> Config {
+	load_config_layers().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		process::exit(EXIT_CONFIG)
+	})
+}
+
+/// Layer the config files, environment and command line into a Config
+/// 
+/// Loading order (the same as on Config):
+/// 1. Start from the hard-coded defaults
+/// 2. Apply /etc/nvme-led-daemon.conf (silently ignored if missing)
+/// 3. Apply each --config file in the order given (an error if unreadable),
+///    wherever it appears on the command line
+/// 4. Apply NVME_LED_* environment variables over all config files
+/// 5. Process the other CLI arguments, which override everything above
+/// 
+/// This allows flexible configuration: you can use just CLI args, just a
+/// config file, just the environment (containers), or a mix, with CLI
+/// args overriding everything else.
+/// 
+/// Used at startup and again on SIGHUP. A config file that can't be read
+/// or settings that don't fit together are returned as an error, so a
+/// reload can keep the running configuration; malformed flags still exit,
+/// as the command line can't change between startup and a reload.
+fn load_config_layers() -> Result<Config, String> {
+	config_from_layers(env::args().skip(1), &env_config_map())
+}
+
+/// load_config_layers() over the given arguments (without argv[0]) and
+/// NVME_LED_* map
+fn config_from_layers(args: impl Iterator<Item = String>, env_map: &HashMap<String, String>) -> Result<Config, String> {
+	// Try loading default config file first (silently ignore if missing)
+	// unwrap_or_else returns empty HashMap if file doesn't exist
+	let config_map = load_config(DEFAULT_CONFIG_PATH)
+		.unwrap_or_else(|_| HashMap::new());
+
+	// Initialize config with hard-coded defaults, then layer the
+	// default config file on top
+	let mut cfg = Config::default();
+	apply_config_map(&mut cfg, &config_map);
+	
+	// Custom config files next, so the environment and every other flag
+	// override them wherever --config appears
+	let args: Vec<String> = args.collect();
+	let mut rest = args.iter();
+	while let Some(a) = rest.next() {
+		if a != "--config" {
+			continue;
+		}
+		let path = rest.next().unwrap_or_else(|| { 
+			eprintln!("--config requires PATH"); 
+			process::exit(EXIT_USAGE) 
+		});
+		
+		// Load the custom config file (error if it doesn't exist)
+		// Current values act as defaults for keys the file doesn't set
+		let new_map = load_config(path)
+			.map_err(|e| format!("Failed to load config {}: {}", path, e))?;
+		apply_config_map(&mut cfg, &new_map);
+		cfg.config_path = Some(path.clone());
+	}
+	
+	// Environment overrides the files but not the command line
+	apply_config_map(&mut cfg, env_map);
+
+	// Process command-line arguments, overriding config file values
+	let mut it = args.into_iter().peekable();
+	
+	// The first --nvme replaces the config file's list, later ones append
+	let mut nvme_from_cli = false;
+	
+	while let Some(a) = it.next() {
+		match a.as_str() {
+			"--help" | "-h" => help(0),	// Print help and exit
+			"--version" | "-V" => version(),	// Print version and exit
+			
+			// Boolean flags (no argument)
+			"--quiet" => cfg.log_level = Level::Error,
+			"--verbose" | "-v" => cfg.log_level = Level::Debug,
+			"--log-level" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--log-level requires error|warn|info|debug"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.log_level = Level::parse(&v).unwrap_or_else(|| { 
+					eprintln!("--log-level must be error, warn, info or debug"); 
+					process::exit(EXIT_USAGE) 
+				});
+			}
+			"--active-high" => cfg.active_high = true,
+			"--led-off" => cfg.led_off = true,
+			"--test-led" => cfg.test_led = true,
+			"--dry-run" => cfg.dry_run = true,
+			"--replay-fast" => cfg.replay_fast = true,
+			"--probe-io" => cfg.probe_io = true,
+			"--monitor" => cfg.monitor = true,
+			"--print-config" => cfg.print_config = true,
+			"--generate-systemd" => cfg.generate_systemd = true,
+			"--list-leds" => cfg.list_leds = true,
+			"--list-devices" => cfg.list_devices = Some(false),
+			"--list-all-devices" => cfg.list_devices = Some(true),
+			"--kernel-trigger" => cfg.kernel_trigger = Some(DEFAULT_KERNEL_TRIGGER.to_string()),
+			"--aggregate-members" => cfg.aggregate_members = true,
+			"--align-polls" => cfg.align_polls = true,
+			"--boottime-timers" => cfg.boottime_timers = true,
+			"--stat-notify" => cfg.stat_notify = true,
+			"--mount-blink" => cfg.mount_blink = true,
+			"--pwm" => cfg.pwm = true,
+			"--edge-only" => cfg.edge_only = true,
+			"--startup-blink" => cfg.startup_blink = true,
+			"--resume-blink" => cfg.resume_blink = true,
+			
+			// Path arguments (require next argument)
+			"--led" => { 
+				cfg.led_path = it.next().unwrap_or_else(|| { 
+					eprintln!("--led requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				}); 
+			}
+			
+			"--led-slot" => { 
+				cfg.led_slot = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--led-slot requires [ENCLOSURE/]SLOT"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--read-led" => { 
+				cfg.read_led_path = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--read-led requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--write-led" => { 
+				cfg.write_led_path = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--write-led requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--red" | "--green" | "--blue" => {
+				let i = match a.as_str() { "--red" => 0, "--green" => 1, _ => 2 };
+				cfg.rgb_paths[i] = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("{} requires PATH", a); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--full-scale-mb-s" => {
+				// Store as Some with minimum of 1 MB/s
+				cfg.full_scale_mb_s = Some(it.next().and_then(|v| parse_mb_s(&v)).unwrap_or_else(|| { 
+					eprintln!("invalid --full-scale-mb-s"); 
+					process::exit(EXIT_USAGE) 
+				}).max(1));
+			}
+			
+			"--full-scale-depth" => {
+				// Store as Some with minimum of 1 I/O
+				cfg.full_scale_depth = Some(next_u64(&mut it, "--full-scale-depth").max(1));
+			}
+			
+			"--brightness-hysteresis" => {
+				cfg.brightness_hysteresis = next_u64(&mut it, "--brightness-hysteresis") as u32;
+			}
+			
+			"--pwm-freq-hz" => {
+				// Below ~100 Hz the toggling is visible as flicker
+				cfg.pwm_freq_hz = next_u64(&mut it, "--pwm-freq-hz").max(MIN_PWM_FREQ_HZ);
+			}
+			
+			"--pwm-duty" => {
+				cfg.pwm_duty = next_u64(&mut it, "--pwm-duty").min(100);
+			}
+			
+			"--fade-ms" => {
+				// 0 turns the LED off in one write, as before
+				cfg.fade_ms = next_ms(&mut it, "--fade-ms");
+			}
+			
+			"--fade-curve" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--fade-curve requires linear|exp"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.fade_curve = match v.as_str() {
+					"linear" => FadeCurve::Linear,
+					"exp" => FadeCurve::Exp,
+					_ => { 
+						eprintln!("--fade-curve must be linear or exp"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--read-color" | "--write-color" => {
+				let c = it.next().as_deref().and_then(parse_color).unwrap_or_else(|| { 
+					eprintln!("{} requires #rrggbb", a); 
+					process::exit(EXIT_USAGE) 
+				});
+				if a == "--read-color" { cfg.read_color = c } else { cfg.write_color = c }
+			}
+			
+			"--led-open-policy" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--led-open-policy requires strict|best-effort"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.led_open_policy = match v.as_str() {
+					"strict" => LedOpenPolicy::Strict,
+					"best-effort" => LedOpenPolicy::BestEffort,
+					_ => { 
+						eprintln!("--led-open-policy must be strict or best-effort"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--led-slot-attr" => { 
+				cfg.led_slot_attr = it.next().unwrap_or_else(|| { 
+					eprintln!("--led-slot-attr requires active|locate|fault"); 
+					process::exit(EXIT_USAGE) 
+				}); 
+			}
+			
+			"--nvme" => { 
+				let path = it.next().unwrap_or_else(|| { 
+					eprintln!("--nvme requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				}); 
+				if nvme_from_cli {
+					cfg.nvme_path = format!("{},{}", cfg.nvme_path, path);
+				} else {
+					cfg.nvme_path = path;
+					nvme_from_cli = true;
+				}
+			}
+			
+			"--source" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--source requires stat|diskstats"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.source = match v.as_str() {
+					"stat" => Source::Stat,
+					"diskstats" => Source::DiskStats,
+					_ => { 
+						eprintln!("--source must be stat or diskstats"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			// Any block device by name; takes over from a config file's
+			// nvme_path (--nvme NAME does the same for one device)
+			"--device" => { 
+				cfg.device = it.next().unwrap_or_else(|| { 
+					eprintln!("--device requires NAME"); 
+					process::exit(EXIT_USAGE) 
+				}); 
+				if !nvme_from_cli {
+					cfg.nvme_path.clear();
+				}
+			}
+			
+			"--nvme-name" => { 
+				cfg.nvme_name = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--nvme-name requires NAME"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--nvme-controller" => { 
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--nvme-controller requires NAME"); 
+					process::exit(EXIT_USAGE) 
+				}); 
+				if !is_nvme_controller(&v) {
+					eprintln!("--nvme-controller must be a controller name like nvme0");
+					process::exit(EXIT_USAGE)
+				}
+				cfg.nvme_controller = Some(v);
+			}
+			
+			"--hotplug" => { 
+				cfg.hotplug = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--hotplug requires PATTERN"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			// Shorthand for an md array's members: --nvme mdX --aggregate-members
+			"--md" => { 
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--md requires NAME"); 
+					process::exit(EXIT_USAGE) 
+				}); 
+				if !v.starts_with("md") || v.contains('/') {
+					eprintln!("--md must be an md array name like md0");
+					process::exit(EXIT_USAGE)
+				}
+				cfg.nvme_path = stat_path_of(&v);
+				nvme_from_cli = true;
+				cfg.aggregate_members = true;
+			}
+			
+			// Numeric arguments with validation
+			"--interval-ms" | "--min-interval-ms" => {
+				cfg.poll_ms = next_ms(&mut it, &a);
+				// Enforce minimum of 1ms (0 would cause busy loop)
+				if cfg.poll_ms == 0 { cfg.poll_ms = 1; }
+			}
+			
+			"--min-sectors" => {
+				let v = next_u64(&mut it, "--min-sectors");
+				(cfg.min_read, cfg.min_write, cfg.min_in_bytes) = (v, v, false);
+			}
+			
+			"--min-bytes" => {
+				let v = sectors_exceeding(next_bytes(&mut it, "--min-bytes"));
+				(cfg.min_read, cfg.min_write, cfg.min_in_bytes) = (v, v, true);
+			}
+			
+			"--min-read-bytes" => {
+				cfg.min_read = sectors_exceeding(next_bytes(&mut it, "--min-read-bytes"));
+				cfg.min_in_bytes = true;
+			}
+			
+			"--min-write-bytes" => {
+				cfg.min_write = sectors_exceeding(next_bytes(&mut it, "--min-write-bytes"));
+				cfg.min_in_bytes = true;
+			}
+			
+			"--smoothing" => {
+				cfg.smoothing = it.next().and_then(|v| v.parse::<f64>().ok())
+					.filter(|v| *v > 0.0 && *v <= 1.0)
+					.unwrap_or_else(|| { 
+						eprintln!("--smoothing requires a number in (0, 1]"); 
+						process::exit(EXIT_USAGE) 
+					});
+			}
+			
+			"--max-interval-ms" => {
+				// 0 (or anything <= the interval) keeps polling fixed
+				cfg.max_interval_ms = next_ms(&mut it, "--max-interval-ms");
+			}
+			
+			"--backoff-factor" => {
+				cfg.backoff_factor = it.next().and_then(|v| v.parse::<f64>().ok())
+					.filter(|v| *v > 1.0)
+					.unwrap_or_else(|| { 
+						eprintln!("--backoff-factor requires a number > 1"); 
+						process::exit(EXIT_USAGE) 
+					});
+			}
+			
+			"--backoff-after" => {
+				cfg.backoff_after = next_u64(&mut it, "--backoff-after").max(1);
+			}
+			
+			"--blink-ms" => {
+				cfg.blink_ms = next_ms(&mut it, "--blink-ms");
+				// Enforce minimum of 1ms
+				if cfg.blink_ms == 0 { cfg.blink_ms = 1; }
+				cfg.blink_ms_set = true;
+			}
+			
+			"--blink-pct" => {
+				cfg.blink_pct = Some(next_u64(&mut it, "--blink-pct"));
+			}
+			
+			"--read-blink-ms" => {
+				// Store as Some with minimum of 1ms
+				cfg.read_blink_ms = Some(next_ms(&mut it, "--read-blink-ms").max(1));
+			}
+			
+			"--write-blink-ms" => {
+				// Store as Some with minimum of 1ms
+				cfg.write_blink_ms = Some(next_ms(&mut it, "--write-blink-ms").max(1));
+			}
+			
+			"--discard-blink-ms" => {
+				// Store as Some with minimum of 1ms
+				cfg.discard_blink_ms = Some(next_ms(&mut it, "--discard-blink-ms").max(1));
+			}
+			
+			"--min-on-ms" => {
+				cfg.min_on_ms = next_ms(&mut it, "--min-on-ms");
+			}
+			
+			"--min-off-ms" => {
+				cfg.min_off_ms = next_ms(&mut it, "--min-off-ms");
+			}
+			
+			"--reconcile-ms" => {
+				// 0 disables reconciliation
+				cfg.reconcile_ms = next_ms(&mut it, "--reconcile-ms");
+			}
+			
+			"--startup-grace-ms" => {
+				cfg.startup_grace_ms = next_ms(&mut it, "--startup-grace-ms");
+			}
+			
+			"--max-devices" => {
+				cfg.max_devices = next_u64(&mut it, "--max-devices") as usize;
+			}
+			
+			"--mount-pattern" => {
+				cfg.mount_pattern = it.next()
+					.and_then(|v| parse_pattern(&v))
+					.unwrap_or_else(|| { 
+						eprintln!("--mount-pattern requires MS,MS,... (on/off steps)"); 
+						process::exit(EXIT_USAGE) 
+					});
+			}
+			
+			"--summary-ms" => {
+				// 0 disables the summary
+				cfg.summary_ms = next_ms(&mut it, "--summary-ms");
+			}
+			
+			"--idle-ms" => {
+				cfg.idle_ms = next_ms(&mut it, "--idle-ms");
+			}
+			
+			"--mount-throttle-ms" => {
+				cfg.mount_throttle_ms = next_ms(&mut it, "--mount-throttle-ms");
+			}
+			
+			"--heartbeat-ms" => {
+				cfg.heartbeat_ms = next_ms(&mut it, "--heartbeat-ms");
+			}
+			
+			"--lock-memory" => cfg.lock_memory = true,
+			"--cpu" => cfg.cpu = Some(next_u64(&mut it, "--cpu") as usize),
+			
+			"--rt-priority" => {
+				cfg.rt_priority = next_u64(&mut it, "--rt-priority");
+				if cfg.rt_priority > RT_PRIORITY_MAX {
+					eprintln!("--rt-priority must be 0-{}", RT_PRIORITY_MAX);
+					process::exit(EXIT_USAGE)
+				}
+			}
+			
+			// Enum arguments with validation
+			"--nvme-mode" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--nvme-mode requires io|sectors|inflight|busy"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.nvme_mode = match v.as_str() {
+					"io" => NvmeMode::Io,
+					"sectors" => NvmeMode::Sectors,
+					"inflight" => NvmeMode::InFlight,
+					"busy" => NvmeMode::Busy,
+					_ => { 
+						eprintln!("--nvme-mode must be io, sectors, inflight or busy"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--metrics-file" => { 
+				cfg.metrics_file = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--metrics-file requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--csv" => { 
+				cfg.csv = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--csv requires FILE"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--csv-interval-ms" => cfg.csv_interval_ms = next_ms(&mut it, "--csv-interval-ms"),
+			
+			"--metrics-addr" => { 
+				cfg.metrics_addr = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--metrics-addr requires HOST:PORT"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--pidfile" => { 
+				cfg.pidfile = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--pidfile requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--control-socket" => { 
+				cfg.control_socket = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--control-socket requires PATH"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--record" => { 
+				cfg.record = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--record requires FILE"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--replay" => { 
+				cfg.replay = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--replay requires FILE"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--user" => { 
+				cfg.user = Some(it.next().unwrap_or_else(|| { 
+					eprintln!("--user requires NAME"); 
+					process::exit(EXIT_USAGE) 
+				})); 
+			}
+			
+			"--metrics-interval-ms" => {
+				cfg.metrics_interval_ms = next_ms(&mut it, "--metrics-interval-ms");
+				// Enforce minimum of 1ms (0 can't be armed as a periodic timer)
+				if cfg.metrics_interval_ms == 0 { 
+					cfg.metrics_interval_ms = 1; 
+				}
+			}
+			
+			a if a == "--log" || a.starts_with("--log=") => {
+				let v = match a.strip_prefix("--log=") {
+					Some(v) => v.to_string(),
+					None => it.next().unwrap_or_else(|| { 
+						eprintln!("--log requires stdout|syslog|journald"); 
+						process::exit(EXIT_USAGE) 
+					}),
+				};
+				cfg.log_target = match v.as_str() {
+					"stdout" => LogTarget::Stdout,
+					"syslog" => LogTarget::Syslog,
+					"journald" => LogTarget::Journald,
+					_ => { 
+						eprintln!("--log must be stdout, syslog or journald"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--exit-led-state" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--exit-led-state requires off|on|restore"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.exit_led_state = match v.as_str() {
+					"off" => ExitLedState::Off,
+					"on" => ExitLedState::On,
+					"restore" => ExitLedState::Restore,
+					_ => { 
+						eprintln!("--exit-led-state must be off, on or restore"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--panic-led" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--panic-led requires none|on|strobe"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.panic_led = match v.as_str() {
+					"none" => PanicLed::None,
+					"on" => PanicLed::On,
+					"strobe" => PanicLed::Strobe,
+					_ => { 
+						eprintln!("--panic-led must be none, on or strobe"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--stat-format" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--stat-format requires indexed|named"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.stat_format = match v.as_str() {
+					"indexed" => StatFormat::Indexed,
+					"named" => StatFormat::Named,
+					_ => { 
+						eprintln!("--stat-format must be indexed or named"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--on-fields" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--on-fields requires reads|writes|both|discards|all"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.on_fields = match v.as_str() {
+					"reads" => FieldsSel::Reads,
+					"writes" => FieldsSel::Writes,
+					"both" => FieldsSel::Both,
+					"discards" => FieldsSel::Discards,
+					"all" => FieldsSel::All,
+					_ => { 
+						eprintln!("--on-fields must be reads|writes|both|discards|all"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			"--mixed-priority" => {
+				let v = it.next().unwrap_or_else(|| { 
+					eprintln!("--mixed-priority requires read|write|longer|shorter"); 
+					process::exit(EXIT_USAGE) 
+				});
+				cfg.mixed_priority = match v.as_str() {
+					"read" => MixedPriority::Read,
+					"write" => MixedPriority::Write,
+					"longer" => MixedPriority::Longer,
+					"shorter" => MixedPriority::Shorter,
+					_ => { 
+						eprintln!("--mixed-priority must be read|write|longer|shorter"); 
+						process::exit(EXIT_USAGE) 
+					}
+				}
+			}
+			
+			// Custom config file: already applied, before the environment
+			"--config" => {
+				it.next();
+			}
+			
+			// Unknown argument
+			other => { 
+				eprintln!("Unknown arg: {}", other); 
+				help(EXIT_USAGE);  // Print help and exit
+			}
+		}
+	}
+	
+	// Validate values that have no sensible clamp
+	if cfg.max_devices == 0 {
+		return Err("max_devices must be at least 1".to_string());
+	}
+	if cfg.rgb_paths.iter().any(Option::is_some) && !cfg.rgb_paths.iter().all(Option::is_some) {
+		return Err("red_path, green_path and blue_path must be set together".to_string());
+	}
+	// Only sectors mode counts data; I/Os, depth or busy time can't be
+	// compared against bytes
+	if cfg.min_in_bytes && cfg.nvme_mode != NvmeMode::Sectors {
+		return Err("min_bytes, min_read_bytes and min_write_bytes need nvme_mode=sectors; use min_sectors in other modes".to_string());
+	}
+	
+	Ok(cfg)
+}
+
+// ============================================================================
+// STATISTICS: Activity counters for periodic summaries
+// ============================================================================
+
+/// Activity counters accumulated by the event loop
+#[derive(Copy, Clone, Debug, Default)]
+struct Counters {
+	polls: u64,			  // Poll timer ticks handled
+	blinks: u64,		  // LED off -> on transitions caused by activity
+	reads: u64,			  // Polls reporting read activity
+	writes: u64,		  // Polls reporting write activity
+	discards: u64,		  // Polls reporting discard activity
+	peak_delta: u128,	  // Largest single-poll counter increase (mode units)
+	missed_ticks: u64,	  // Poll expirations we were too late to handle
+	errors: u64,		  // Non-fatal errors (logged and carried on)
+	bytes_read: u128,	  // Bytes read by the devices (sectors mode only)
+	bytes_written: u128,  // Bytes written by the devices (sectors mode only)
+}
+
+/// Lifetime counters plus the current summary interval's counters
+/// 
+/// Every update goes to both; `take_interval` hands back the interval set
+/// and starts a fresh one, leaving the lifetime totals untouched.
+#[derive(Default)]
+struct Stats {
+	total: Counters,
+	interval: Counters,
+}
+
+impl Stats {
+	/// Apply the same update to the lifetime and interval counters
+	#[inline(always)]
+	fn bump(&mut self, f: impl Fn(&mut Counters)) {
+		f(&mut self.total);
+		f(&mut self.interval);
+	}
+	
+	/// Return the interval counters and reset them for the next interval
+	fn take_interval(&mut self) -> Counters {
+		std::mem::take(&mut self.interval)
+	}
+}
+
+/// Recent throughput: the busiest second and 1/5-minute averages
+/// 
+/// Each poll's byte deltas are added into one-second buckets kept in a
+/// ring of THROUGHPUT_WINDOW_S, so memory is bounded regardless of poll_ms.
+/// The peak is the busiest completed second since start, which says more
+/// about what the disk sustained than a single 10ms poll would.
+struct Throughput {
+	buckets: std::collections::VecDeque<[u128; 2]>,  // Bytes read/written per second, newest (still filling) last
+	started: Instant,								   // Bucket clock zero
+	second: u64,									   // Seconds since `started` of the newest bucket
+	peak: [u128; 2],								   // Busiest completed second (bytes read, written)
+}
+
+impl Throughput {
+	fn new(now: Instant) -> Self {
+		Self { buckets: std::collections::VecDeque::from([[0, 0]]), started: now, second: 0, peak: [0, 0] }
+	}
+	
+	/// Account one poll's bytes at `now`, closing the buckets of any
+	/// seconds that have gone by (idle ones stay at zero)
+	fn add(&mut self, now: Instant, read: u128, written: u128) {
+		let sec = now.duration_since(self.started).as_secs();
+		let passed = sec.saturating_sub(self.second);
+		for _ in 0..passed.min(THROUGHPUT_WINDOW_S as u64 + 1) {
+			if let Some(b) = self.buckets.back() {
+				self.peak = [self.peak[0].max(b[0]), self.peak[1].max(b[1])];
+			}
+			self.buckets.push_back([0, 0]);
+			if self.buckets.len() > THROUGHPUT_WINDOW_S + 1 {
+				self.buckets.pop_front();
+			}
+		}
+		self.second = self.second.max(sec);
+		if let Some(b) = self.buckets.back_mut() {
+			b[0] += read;
+			b[1] += written;
+		}
+	}
+	
+	/// Mean bytes per second read and written over the last `secs`
+	/// completed seconds (all of them if fewer have passed)
+	fn mean(&self, secs: usize) -> [u128; 2] {
+		let done = self.buckets.iter().rev().skip(1).take(secs);
+		let n = done.len().max(1) as u128;
+		let sum = done.fold([0, 0], |a, b| [a[0] + b[0], a[1] + b[1]]);
+		[sum[0] / n, sum[1] / n]
+	}
+	
+	/// One-line summary in MB/s: peak, 1-minute and 5-minute means
+	fn summary(&self) -> String {
+		let mb = |b: u128| b as f64 / 1e6;
+		let (m1, m5) = (self.mean(60), self.mean(300));
+		format!("read_peak_mb_s={:.1} write_peak_mb_s={:.1} read_1m_mb_s={:.1} write_1m_mb_s={:.1} read_5m_mb_s={:.1} write_5m_mb_s={:.1}",
+			mb(self.peak[0]), mb(self.peak[1]), mb(m1[0]), mb(m1[1]), mb(m5[0]), mb(m5[1]))
+	}
+}
+
+/// Render the lifetime counters in OpenMetrics text format
+/// 
+/// Counters get the `_total` suffix; the peak delta is a gauge since it
+/// can only be compared, not rated, as are the throughput peaks and
+/// averages. Every sample carries the device label.
+fn render_metrics(name: &str, c: &Counters, tp: &Throughput, led_on: bool) -> String {
+	let mut out = String::new();
+	let mut metric = |m: &str, kind: &str, help: &str, v: u128| {
+		let sample = if kind == "counter" { format!("{}_total", m) } else { m.to_string() };
+		out.push_str(&format!("# TYPE {} {}\n# HELP {} {}\n{}{{device=\"{}\"}} {}\n",
+			m, kind, m, help, sample, name, v));
+	};
+	metric("nvme_led_polls", "counter", "Poll timer ticks handled.", c.polls as u128);
+	metric("nvme_led_blinks", "counter", "LED off to on transitions caused by activity.", c.blinks as u128);
+	metric("nvme_led_read_polls", "counter", "Polls reporting read activity.", c.reads as u128);
+	metric("nvme_led_write_polls", "counter", "Polls reporting write activity.", c.writes as u128);
+	metric("nvme_led_discard_polls", "counter", "Polls reporting discard activity.", c.discards as u128);
+	metric("nvme_led_missed_ticks", "counter", "Poll expirations handled late.", c.missed_ticks as u128);
+	metric("nvme_led_errors", "counter", "Non-fatal errors.", c.errors as u128);
+	metric("nvme_led_peak_delta", "gauge", "Largest single-poll counter increase.", c.peak_delta);
+	metric("nvme_led_bytes_read", "counter", "Bytes read by the monitored devices (nvme_mode=sectors only).", c.bytes_read);
+	metric("nvme_led_bytes_written", "counter", "Bytes written by the monitored devices (nvme_mode=sectors only).", c.bytes_written);
+	metric("nvme_led_led_on", "gauge", "Whether the LED is currently lit by activity.", led_on as u128);
+	let (m1, m5) = (tp.mean(60), tp.mean(300));
+	for (i, dir) in ["read", "write"].into_iter().enumerate() {
+		metric(&format!("nvme_led_{}_peak_bytes_per_second", dir), "gauge",
+			&format!("Busiest second of {}s since start (nvme_mode=sectors only).", dir), tp.peak[i]);
+		metric(&format!("nvme_led_{}_bytes_per_second_1m", dir), "gauge",
+			&format!("Average {} throughput over the last minute.", dir), m1[i]);
+		metric(&format!("nvme_led_{}_bytes_per_second_5m", dir), "gauge",
+			&format!("Average {} throughput over the last 5 minutes.", dir), m5[i]);
+	}
+	out.push_str("# EOF\n");
+	out
+}
+
+/// Atomically replace `path` with the rendered counters
+/// 
+/// Written to a sibling temp file and renamed over the target, so a
+/// collector scraping the file never sees a partial write.
+fn write_metrics(path: &str, name: &str, c: &Counters, tp: &Throughput, led_on: bool) -> io::Result<()> {
+	let tmp = format!("{}.tmp", path);
+	std::fs::write(&tmp, render_metrics(name, c, tp, led_on))?;
+	std::fs::rename(&tmp, path)
+}
+
+/// Minimal HTTP listener serving the counters at `GET /metrics` (metrics_addr)
+/// 
+/// One request per connection, answered and closed inline from the event
+/// loop; no keep-alive, no threads. A client that doesn't finish its
+/// request headers within METRICS_HTTP_TIMEOUT_MS gets nothing.
+struct MetricsServer {
+	listener: std::net::TcpListener,  // Non-blocking listener
+}
+
+impl MetricsServer {
+	/// Listen on `addr` (host:port)
+	fn bind(addr: &str) -> io::Result<Self> {
+		let listener = std::net::TcpListener::bind(addr)
+			.map_err(|e| io::Error::new(e.kind(), format!("metrics_addr {}: {}", addr, e)))?;
+		listener.set_nonblocking(true)?;
+		Ok(Self { listener })
+	}
+	
+	/// File descriptor for epoll registration (readable = client waiting)
+	fn fd(&self) -> RawFd {
+		std::os::unix::io::AsRawFd::as_raw_fd(&self.listener)
+	}
+	
+	/// Accept a waiting client and answer its request; `body` renders the
+	/// metrics only if they were actually asked for
+	fn serve(&self, body: impl FnOnce() -> String) -> io::Result<()> {
+		let Ok((mut stream, _)) = self.listener.accept() else {
+			return Ok(());  // Nobody waiting after all
+		};
+		let timeout = Some(Duration::from_millis(METRICS_HTTP_TIMEOUT_MS));
+		stream.set_nonblocking(false)?;
+		stream.set_read_timeout(timeout)?;
+		stream.set_write_timeout(timeout)?;
+		
+		// Only the request line matters, but read the headers through so
+		// the client isn't reset while still sending them
+		let mut req = Vec::new();
+		let mut chunk = [0u8; 512];
+		while req.len() < 8192 && !req.windows(4).any(|w| w == b"\r\n\r\n") {
+			match stream.read(&mut chunk)? {
+				0 => break,
+				n => req.extend_from_slice(&chunk[..n]),
+			}
+		}
+		let req = String::from_utf8_lossy(&req);
+		let mut first = req.lines().next().unwrap_or("").split_whitespace();
+		let (status, ctype, text) = match (first.next(), first.next()) {
+			(Some("GET"), Some("/metrics")) => ("200 OK", "application/openmetrics-text; version=1.0.0; charset=utf-8", body()),
+			(Some("GET"), _) => ("404 Not Found", "text/plain", "not found; try /metrics\n".to_string()),
+			_ => ("405 Method Not Allowed", "text/plain", "only GET is supported\n".to_string()),
+		};
+		write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+			status, ctype, text.len(), text)
+	}
+}
+
+// ============================================================================
+// BLINK DECISION: Pure mapping from activity to LED action
+// ============================================================================
+
+/// What the event loop should do with the LED after a poll
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+	Blink(u64),	 // Turn on (if off) and (re)arm the off-timer for N ms
+	Off,		 // Turn off now (the blink has run its course)
+	Leave		 // Don't touch the LED or its timer
+}
+
+/// LED/timer state the decision depends on
+#[derive(Copy, Clone, Debug)]
+struct BlinkState {
+	in_grace: bool,			// Still inside startup_grace_ms
+	idle_for: Option<Duration>,  // Time since the previous activity (None = never)
+	pattern_playing: bool,	// A blink pattern currently owns the LED
+	dark_for: Option<Duration>,  // Time since the LED went off (None = lit or never)
+}
+
+/// Brightness for one poll's throughput, for dimmable LEDs
+/// 
+/// `delta_sectors` is the summed sector increase over `poll_ms`; at
+/// `full_scale_mb_s` or more the LED is at `max`. Never below 1, so any
+/// activity stays visible.
+fn throughput_level(delta_sectors: u128, poll_ms: u64, full_scale_mb_s: u64, max: u32) -> u32 {
+	let bytes_per_s = delta_sectors * SECTOR_BYTES as u128 * 1000 / poll_ms.max(1) as u128;
+	let full = (full_scale_mb_s.max(1) as u128) * 1_000_000;
+	((bytes_per_s * max as u128 / full).min(max as u128) as u32).max(1)
+}
+
+/// Brightness for a level-type reading (queue depth, busy percentage), for
+/// dimmable LEDs: `max` at `full_scale` or more, never below 1
+fn scaled_level(value: u128, full_scale: u64, max: u32) -> u32 {
+	((value * max as u128 / full_scale.max(1) as u128).min(max as u128) as u32).max(1)
+}
+
+/// Level to write for a computed brightness `lvl` given the LED's `current`
+/// one: a lit LED keeps its level unless `lvl` is more than `band` away
+fn with_hysteresis(current: Option<u32>, lvl: u32, band: u32) -> u32 {
+	match current {
+		Some(cur) if cur > 0 && cur.abs_diff(lvl) <= band => cur,
+		_ => lvl,
+	}
+}
+
+/// Poll interval to use after a poll, under adaptive polling
+/// 
+/// Activity snaps straight back to the fast interval (poll_ms). Every
+/// `backoff_after` consecutive idle polls stretch the interval by
+/// `backoff_factor`, up to max_interval_ms. With max_interval_ms at or
+/// below poll_ms, adaptive polling is off and this is always poll_ms.
+fn backoff_interval(cfg: &Config, cur_ms: u64, idle_polls: u64, active: bool) -> u64 {
+	if active || cfg.max_interval_ms <= cfg.poll_ms {
+		return cfg.poll_ms;
+	}
+	if idle_polls == 0 || !idle_polls.is_multiple_of(cfg.backoff_after.max(1)) {
+		return cur_ms;
+	}
+	((cur_ms as f64 * cfg.backoff_factor).ceil() as u64).clamp(cfg.poll_ms, cfg.max_interval_ms)
+}
+
+/// Default blink duration: explicit blink_ms, else blink_pct of the poll
+/// interval (derived here so it always tracks the current interval)
+fn base_blink_ms(cfg: &Config) -> u64 {
+	match cfg.blink_pct {
+		Some(pct) if !cfg.blink_ms_set => (cfg.poll_ms * pct / 100).max(1),
+		_ => cfg.blink_ms,
+	}
+}
+
+/// Blink duration for a direction: per-direction override, else the default
+fn blink_duration(cfg: &Config, dir: Dir) -> u64 {
+	match dir {
+		Dir::Read => cfg.read_blink_ms.unwrap_or_else(|| base_blink_ms(cfg)),
+		Dir::Write => cfg.write_blink_ms.unwrap_or_else(|| base_blink_ms(cfg)),
+		Dir::Discard => cfg.discard_blink_ms.unwrap_or_else(|| base_blink_ms(cfg)),
+	}
+}
+
+/// How much longer a blink lit at `lit_at` must stay on to have lasted
+/// `min_on_ms` (rounded up to whole ms); None once it has, or if unlit
+fn min_on_left(lit_at: Option<Instant>, min_on_ms: u64, now: Instant) -> Option<u64> {
+	let left = Duration::from_millis(min_on_ms).checked_sub(now.duration_since(lit_at?))?;
+	(!left.is_zero()).then(|| left.as_micros().div_ceil(1000) as u64)
+}
+
+/// Decide the LED action for one poll result
+/// 
+/// This is the whole blink policy with no syscalls: the on_fields filter,
+/// per-direction durations, the startup grace period, edge-only mode and
+/// pattern ownership. The event loop only
+/// applies the returned Action.
+fn decide(cfg: &Config, dir: Option<Dir>, state: BlinkState) -> Action {
+	let Some(dir) = dir else { return Action::Leave };
+	
+	// Determine if we should blink for it based on the on_fields filter
+	let relevant = match (cfg.on_fields, dir) {
+		(FieldsSel::All, _) => true,			   // All: always relevant
+		(FieldsSel::Both, Dir::Read | Dir::Write) => true,	// Both: reads and writes
+		(FieldsSel::Reads, Dir::Read) => true,	   // Reads only: relevant if read
+		(FieldsSel::Writes, Dir::Write) => true,   // Writes only: relevant if write
+		(FieldsSel::Discards, Dir::Discard) => true,  // Discards only: relevant if TRIM
+		_ => false,								   // Filtered out
+	};
+	
+	// Nothing shows during the grace period, and a playing pattern owns
+	// the LED until it finishes
+	if !relevant || state.in_grace || state.pattern_playing {
+		return Action::Leave;
+	}
+	
+	// Anti-flicker: a blink that just ended keeps the LED dark for
+	// min_off_ms, so light steady I/O doesn't strobe
+	if state.dark_for.is_some_and(|d| d < Duration::from_millis(cfg.min_off_ms)) {
+		return Action::Leave;
+	}
+	
+	// Edge-only: blink on the idle -> active transition, then stay quiet
+	// while activity continues (the very first activity counts as an edge)
+	if cfg.edge_only && state.idle_for.is_some_and(|d| d < Duration::from_millis(cfg.idle_ms)) {
+		return Action::Leave;
+	}
+	
+	// In-flight and busy modes are levels, not edges: hold the LED at
+	// least until the next poll so it stays solid while the disk is busy
+	let dur = blink_duration(cfg, dir);
+	if matches!(cfg.nvme_mode, NvmeMode::InFlight | NvmeMode::Busy) {
+		return Action::Blink(dur.max(cfg.poll_ms));
+	}
+	Action::Blink(dur)
+}
+
+/// Decide the LED action when a blink's off-timer fires
+/// 
+/// A blink lit less than min_on_ms ago stays on for the rest (a Blink of
+/// what's left); otherwise it ends. An LED that isn't lit is left alone.
+fn decide_off(cfg: &Config, lit: bool, lit_at: Option<Instant>, now: Instant) -> Action {
+	if !lit {
+		return Action::Leave;
+	}
+	match min_on_left(lit_at, cfg.min_on_ms, now) {
+		Some(ms) => Action::Blink(ms),
+		None => Action::Off,
+	}
+}
+
+// ============================================================================
+// ERRORS: What stopped the daemon, and on which path
+// ============================================================================
+
+/// A fatal error out of run(), saying what was being done when it failed
+/// 
+/// Helpers keep returning io::Error; run() tags the failures it can't
+/// recover from, so the last log line names the LED or stat file at fault
+/// and main() can tell which exit paths still make sense.
+#[derive(Debug)]
+enum DaemonError {
+	LedOpen { path: String, source: io::Error },   // Opening an LED brightness file
+	StatRead { path: String, source: io::Error },  // Opening or reading the stat source(s)
+	TimerArm(io::Error),						   // Creating or arming a timerfd
+	Config(String),								   // A setting that can't be applied
+	Io(io::Error),								   // Anything else (epoll, sockets, ...)
+}
+
+impl DaemonError {
+	/// map_err adapter tagging an error with the LED it happened on
+	fn led_open(path: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+		move |source| Self::LedOpen { path: path.to_string(), source }
+	}
+	
+	/// map_err adapter tagging an error with the stat source it happened on
+	fn stat_read(path: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+		move |source| Self::StatRead { path: path.to_string(), source }
+	}
+}
+
+// Process exit statuses, one per failure class (README "Exit status")
+// 2, 3 and 6 won't go away by themselves: the generated unit lists them in
+// RestartPreventExitStatus=, while a missing LED or disk may still appear
+const EXIT_FAILURE: i32 = 1;		// Anything else (runtime failure)
+const EXIT_USAGE: i32 = 2;			// Bad command line
+const EXIT_CONFIG: i32 = 3;			// A setting that can't be applied
+const EXIT_LED: i32 = 4;			// LED not found or can't be opened
+const EXIT_NO_DEVICE: i32 = 5;		// Stat file or device not found
+const EXIT_PERMISSION: i32 = 6;		// Permission denied (LED, stat file, ...)
+
+impl DaemonError {
+	/// The process exit status for this failure
+	/// Permission problems are one class whatever they happened on: the
+	/// fix (run as root, adjust the unit's sandboxing) is the same
+	fn exit_code(&self) -> i32 {
+		let io = match self {
+			Self::LedOpen { source, .. } | Self::StatRead { source, .. } => Some(source),
+			Self::TimerArm(e) | Self::Io(e) => Some(e),
+			Self::Config(_) => None,
+		};
+		if io.is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied) {
+			return EXIT_PERMISSION;
+		}
+		match self {
+			Self::LedOpen { .. } => EXIT_LED,
+			Self::StatRead { source, .. } if source.kind() == io::ErrorKind::NotFound => EXIT_NO_DEVICE,
+			Self::Config(_) => EXIT_CONFIG,
+			_ => EXIT_FAILURE,
+		}
+	}
+}
+
+/// Exit status for a failure of the device-only modes (--probe-io,
+/// --monitor), which report io::Error rather than DaemonError
+fn device_exit_code(e: &io::Error) -> i32 {
+	match e.kind() {
+		io::ErrorKind::NotFound => EXIT_NO_DEVICE,
+		io::ErrorKind::PermissionDenied => EXIT_PERMISSION,
+		_ => EXIT_FAILURE,
+	}
+}
+
+impl std::fmt::Display for DaemonError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::LedOpen { path, source } => write!(f, "opening LED {}: {}", path, source),
+			Self::StatRead { path, source } => write!(f, "reading {}: {}", path, source),
+			Self::TimerArm(e) => write!(f, "arming timer: {}", e),
+			Self::Config(msg) => write!(f, "configuration: {}", msg),
+			Self::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl std::error::Error for DaemonError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::LedOpen { source, .. } | Self::StatRead { source, .. } => Some(source),
+			Self::TimerArm(e) | Self::Io(e) => Some(e),
+			Self::Config(_) => None,
+		}
+	}
+}
+
+/// Untagged io errors (`?` in run) are plain Io
+impl From<io::Error> for DaemonError {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+// ============================================================================
+// MAIN: Event loop that ties everything together
+// ============================================================================
+
+/// Main event loop: monitor NVMe activity and blink LED accordingly
+/// 
+/// Architecture:
+/// 1. Set up epoll with two timerfds (poll timer and off timer)
+/// 2. Loop waiting for timer events until SIGTERM/SIGINT
+/// 3. On poll timer: check NVMe stats, turn LED on if activity detected
+/// 4. On off timer: turn LED off
+/// 5. On shutdown: leave the LED in the configured exit state
+/// 
+/// The key insight is that we use two independent timers:
+/// - Poll timer fires regularly (e.g., every 10ms) to check for activity
+/// - Off timer is armed when activity detected and fires once to turn LED off
+/// 
+/// This allows precise control of LED on-duration while maintaining efficient
+/// polling. The LED stays on as long as activity continues (each activity
+/// event resets the off timer).
+fn main() {
+	// Load configuration from file and CLI arguments
+	let mut cfg = parse_args();
+	
+	// Show what the file, environment and CLI layers resolved to
+	if cfg.print_config {
+		print!("{}", render_config(&cfg));
+		process::exit(0);
+	}
+	
+	// Discovery helpers: read-only tables of what could be configured
+	if cfg.list_leds || cfg.list_devices.is_some() {
+		let result = if cfg.list_leds { list_leds() } else { Ok(()) }
+			.and_then(|_| match cfg.list_devices {
+				Some(all) => list_devices(all),
+				None => Ok(()),
+			});
+		if let Err(e) = result {
+			log!(Level::Error, "{}", e);
+			process::exit(EXIT_FAILURE);
+		}
+		process::exit(0);
+	}
+	
+	// Unit file for this binary, wherever it's installed
+	if cfg.generate_systemd {
+		let exe = std::fs::read_link("/proc/self/exe")
+			.map(|p| p.to_string_lossy().into_owned())
+			.unwrap_or_else(|_| "/usr/local/bin/nvme-led-daemon".to_string());
+		print!("{}", render_systemd_unit(&cfg, &exe));
+		process::exit(0);
+	}
+	
+	// Everything below logs through log!, so set the threshold first
+	LOG_LEVEL.store(cfg.log_level as u8, Ordering::Relaxed);
+	if let Err(e) = set_log_target(cfg.log_target) {
+		log!(Level::Warn, "can't log to {:?}: {}; using stdout", cfg.log_target, e);
+	}
+	
+	// An enclosure slot, if given, decides the LED path
+	if let Some(spec) = &cfg.led_slot {
+		match resolve_enclosure_slot(std::path::Path::new(ENCLOSURE_ROOT), spec, &cfg.led_slot_attr) {
+			Ok(path) => {
+				log!(Level::Info, "enclosure slot {} resolved to {}", spec, path);
+				cfg.led_path = path;
+			}
+			Err(e) => {
+				log!(Level::Error, "{}", e);
+				process::exit(EXIT_LED);
+			}
+		}
+	}
+	
+	// Default LED missing (not a ThinkPad): look for another one
+	// (--monitor and --probe-io don't use an LED)
+	if cfg.led_slot.is_none() && cfg.led_path == DEFAULT_LED_PATH && !cfg.monitor && !cfg.probe_io
+		&& !std::path::Path::new(DEFAULT_LED_PATH).exists() {
+		match detect_led_path() {
+			Ok(path) => cfg.led_path = path,
+			Err(e) => {
+				log!(Level::Error, "{}", e);
+				process::exit(EXIT_LED);
+			}
+		}
+	}
+	
+	// No stat file configured: find the NVMe device ourselves
+	if cfg.source == Source::Stat && stat_list(&cfg).is_empty() && cfg.nvme_controller.is_none() && cfg.hotplug.is_none() && cfg.replay.is_none() {
+		match detect_nvme_path() {
+			Ok(path) => cfg.nvme_path = path,
+			Err(e) => {
+				log!(Level::Error, "{}", e);
+				process::exit(EXIT_NO_DEVICE);
+			}
+		}
+	}
+	
+	// Self-test instead of monitoring; the exit status is the verdict
+	if cfg.probe_io {
+		match probe_io(&cfg) {
+			Ok(true) => process::exit(0),
+			Ok(false) => process::exit(EXIT_FAILURE),
+			Err(e) => {
+				log!(Level::Error, "probe: {}", e);
+				process::exit(device_exit_code(&e));
+			}
+		}
+	}
+	
+	// Terminal view instead of the LED
+	if cfg.monitor {
+		if let Err(e) = monitor(&cfg) {
+			log!(Level::Error, "monitor: {}", e);
+			process::exit(device_exit_code(&e));
+		}
+		process::exit(0);
+	}
+	
+	// PID file for non-systemd supervisors (not for one-shot --led-off/--test-led);
+	// a reload can't move it, so remember the path we created (and keep
+	// it open for emptying, should --user leave us unable to remove it)
+	let pidfile = cfg.pidfile.clone().filter(|_| !cfg.led_off && !cfg.test_led)
+		.map(|p| match create_pidfile(&p) {
+			Ok(f) => (p, f),
+			Err(e) => {
+				log!(Level::Error, "{}", e);
+				process::exit(EXIT_FAILURE);
+			}
+		});
+	
+	// Fatal errors are reported through the logger too, so they follow
+	// the same format as every other message
+	let result = run(&cfg);
+	if let Some((p, f)) = &pidfile {
+		remove_pidfile(p, f);
+	}
+	if let Err(e) = result {
+		log!(Level::Error, "{}", e);
+		// Leave a sign at the machine that we died (best effort; not
+		// when it's the LED itself we couldn't open)
+		if !matches!(e, DaemonError::LedOpen { .. })
+			&& let Err(e) = apply_panic_led(&cfg) {
+			log!(Level::Warn, "setting panic LED on {} failed: {}", cfg.led_path, e);
+		}
+		process::exit(e.exit_code());
+	}
+}
+
+/// End-to-end check that activity on the monitored device is visible
+/// 
+/// Takes a baseline through the same Nvme path the daemon polls, reads a few
+/// small blocks straight from /dev/NAME (page cache dropped first so they
+/// reach the device), then polls again. Unlike a fake source this proves
+/// the stat file really belongs to the device. Never writes.
+/// 
+/// # Returns
+/// * `Ok(true)` - The read counters moved
+/// * `Ok(false)` - They didn't (wrong stat file, or a counter-less source)
+fn probe_io(cfg: &Config) -> io::Result<bool> {
+	probe_io_with(cfg, |nvme| read_probe_blocks(cfg, nvme))
+}
+
+/// probe_io with the device reads supplied by `read`, which returns what
+/// it read from and how many reads it made
+fn probe_io_with(cfg: &Config, read: impl FnOnce(&Nvme) -> io::Result<(String, usize)>) -> io::Result<bool> {
+	// With several devices configured, the first one is probed
+	let mut nvme = open_monitors(cfg)?.into_iter().next()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no device to probe"))?;
+	let mut sbuf = [0u8; STAT_BUF_LEN];
+	nvme.activity_dir(&mut sbuf)?;
+	let before = nvme.last_reads;
+	
+	let (dev, done) = read(&nvme)?;
+	
+	nvme.activity_dir(&mut sbuf)?;
+	let after = nvme.last_reads;
+	if after > before {
+		log!(Level::Info, "probe: {} reads from {} moved {} read counters by {}", done, dev, nvme.name, after - before);
+		Ok(true)
+	} else {
+		log!(Level::Error, "probe: {} reads from {} left {} read counters unchanged; is {} the right stat file?",
+			done, dev, nvme.name, nvme.path);
+		Ok(false)
+	}
+}
+
+/// Read PROBE_READS small blocks straight from the probed device's /dev node
+fn read_probe_blocks(cfg: &Config, nvme: &Nvme) -> io::Result<(String, usize)> {
+	// Read-only open; O_DIRECT would need aligned buffers, so drop the
+	// cached pages instead to force real device reads
+	let dev = format!("/dev/{}", match cfg.source {
+		Source::Stat => device_name_from_path(&nvme.path),
+		Source::DiskStats => cfg.device.split(',').map(str::trim).find(|d| !d.is_empty()).unwrap_or_default().to_string(),
+	});
+	let f = File::open(&dev).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dev, e)))?;
+	let fd = std::os::unix::io::AsRawFd::as_raw_fd(&f);
+	let mut buf = vec![0u8; PROBE_READ_BYTES];
+	let mut done = 0;
+	for i in 0..PROBE_READS {
+		let off = (i * PROBE_STRIDE) as libc::off_t;
+		unsafe { libc::posix_fadvise(fd, off, PROBE_READ_BYTES as libc::off_t, libc::POSIX_FADV_DONTNEED) };
+		let n = unsafe { libc::pread(fd, buf.as_mut_ptr().cast(), buf.len(), off) };
+		if n <= 0 {
+			break;  // Past the end of a small device (or an error): enough
+		}
+		done += 1;
+	}
+	Ok((dev, done))
+}
+
+/// Show live throughput in the terminal instead of driving the LED (--monitor)
+/// 
+/// The configured devices are polled through the same `Nvme::activity_dir`
+/// as the LED, every interval_ms (at least MONITOR_MIN_MS), in three modes
+/// side by side: sectors for MB/s, io for IOPS and busy for the busy
+/// percentage. The screen is redrawn in place with plain ANSI escapes;
+/// SIGINT/SIGTERM end it with the cursor shown again.
+fn monitor(cfg: &Config) -> io::Result<()> {
+	let open = |mode| open_monitors(&Config { nvme_mode: mode, ..cfg.clone() });
+	let mut sets = [open(NvmeMode::Sectors)?, open(NvmeMode::Io)?, open(NvmeMode::Busy)?];
+	let label = monitors_label(&sets[0]);
+	
+	const TICK_TAG: u64 = 1;	// Refresh timer
+	const SIGNAL_TAG: u64 = 2;	// SIGINT/SIGTERM
+	let ep = Epoll::new()?;
+	let tick = Tfd::periodic(cfg.poll_ms.max(MONITOR_MIN_MS), false)?;
+	ep.add_fd(tick.0, TICK_TAG, libc::EPOLLIN as u32)?;
+	let sigfd = SigFd::new(&[libc::SIGTERM, libc::SIGINT])?;
+	ep.add_fd(sigfd.0, SIGNAL_TAG, libc::EPOLLIN as u32)?;
+	
+	let mut out = io::stdout().lock();
+	write!(out, "\x1b[?25l\x1b[2J")?;  // Hide the cursor, clear the screen
+	let mut history: std::collections::VecDeque<f64> = std::collections::VecDeque::new();
+	let mut sbuf = [0u8; STAT_BUF_LEN];
+	let mut tbuf = [0u8; 8];
+	let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+	let mut last = Instant::now();
+	let result = (|| -> io::Result<()> {
+		loop {
+			let n = ep.wait(&mut events)?;
+			if events[..n].iter().any(|e| e.u64 == SIGNAL_TAG) {
+				return Ok(());
+			}
+			if n == 0 || tick.ack(&mut tbuf) == 0 {
+				continue;
+			}
+			
+			let secs = last.elapsed().as_secs_f64().max(1e-3);
+			last = Instant::now();
+			for nvme in sets.iter_mut().flatten() {
+				nvme.activity_dir(&mut sbuf)?;
+			}
+			let per_sec = |set: &[Nvme], f: fn(&Nvme) -> u128| set.iter().map(f).sum::<u128>() as f64 / secs;
+			let mb = |sectors: f64| sectors * SECTOR_BYTES as f64 / 1e6;
+			let (rmb, wmb) = (mb(per_sec(&sets[0], |n| n.last_read_delta)), mb(per_sec(&sets[0], |n| n.last_write_delta)));
+			let (riops, wiops) = (per_sec(&sets[1], |n| n.last_read_delta), per_sec(&sets[1], |n| n.last_write_delta));
+			let busy = sets[2].iter().map(|n| n.last_busy).max().unwrap_or(0);
+			
+			if history.len() == MONITOR_HISTORY {
+				history.pop_front();
+			}
+			history.push_back(rmb + wmb);
+			
+			// Home, then each line followed by clear-to-end-of-line
+			write!(out, "\x1b[H{} (every {} ms, Ctrl-C quits)\x1b[K\r\n\x1b[K\r\n", label, cfg.poll_ms.max(MONITOR_MIN_MS))?;
+			write!(out, "  read  {:9.1} MB/s {:9.0} IOPS\x1b[K\r\n", rmb, riops)?;
+			write!(out, "  write {:9.1} MB/s {:9.0} IOPS\x1b[K\r\n", wmb, wiops)?;
+			write!(out, "  busy  {:9} %\x1b[K\r\n\x1b[K\r\n", busy)?;
+			write!(out, "  [{:<width$}]\x1b[K\r\n", sparkline(history.make_contiguous()), width = MONITOR_HISTORY)?;
+			write!(out, "  peak {:.1} MB/s\x1b[K\x1b[J", history.iter().copied().fold(0.0, f64::max))?;
+			out.flush()?;
+		}
+	})();
+	write!(out, "\x1b[?25h\r\n")?;  // Show the cursor again
+	out.flush()?;
+	result
+}
+
+/// One block character per value, scaled so the largest fills the cell
+/// (idle values stay blank)
+fn sparkline(values: &[f64]) -> String {
+	const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+	let max = values.iter().copied().fold(0.0, f64::max);
+	values.iter().map(|&v| {
+		if v <= 0.0 || max <= 0.0 {
+			' '
+		} else {
+			BARS[((v / max * BARS.len() as f64).ceil() as usize).clamp(1, BARS.len()) - 1]
+		}
+	}).collect()
+}
+
+/// Set up the LED, monitor and timers, then run the event loop
+/// Returns only on a fatal error (or after an administrative action)
+fn run(cfg: &Config) -> Result<(), DaemonError> {
+	// Administrative "off then exit": open the LED, write the off state
+	// (respecting active_high) and return without creating any timers.
+	// Useful from a service manager's pre-stop/post-stop hook.
+	if cfg.led_off {
+		open_led(cfg, &cfg.led_path, cfg.active_high).map_err(DaemonError::led_open(&cfg.led_path))?.off()?;
+		return Ok(());
+	}
+	
+	// Wiring check: blink and exit, also without timers
+	if cfg.test_led {
+		return test_led(cfg);
+	}
+	
+	// Kernel-driven blinking: once the trigger is set there's nothing left
+	// for us to do. Without it, carry on polling from userspace.
+	if let Some(name) = &cfg.kernel_trigger {
+		if cfg.dry_run {
+			log!(Level::Info, "dry run: would hand {} to the kernel {} trigger", cfg.led_path, name);
+			return Ok(());
+		}
+		match set_kernel_trigger(&cfg.led_path, name) {
+			Ok(true) => {
+				log!(Level::Info, "{} now driven by the kernel {} trigger", cfg.led_path, name);
+				return Ok(());
+			}
+			Ok(false) => log!(Level::Warn, "kernel trigger {} not available for {}; polling instead", name, cfg.led_path),
+			Err(e) => log!(Level::Warn, "can't read triggers of {}: {}; polling instead", cfg.led_path, e),
+		}
+	}
+	
+	// The engine gets its own copy, replaced wholesale when SIGHUP reloads
+	// the config; process-wide settings below stay as started
+	let mut engine = Engine::new(cfg.clone())?;
+	
+	// Pinned first, so a real-time priority only ever competes on that CPU
+	if let Some(cpu) = cfg.cpu {
+		pin_to_cpu(cpu).map_err(|e| DaemonError::Config(e.to_string()))?;
+		log!(Level::Info, "pinned to CPU {}", cpu);
+	}
+	
+	// Real-time scheduling, while we still have the privilege to ask
+	if cfg.rt_priority > 0 {
+		set_rt_priority(cfg.rt_priority)?;
+		log!(Level::Info, "running as SCHED_FIFO priority {}", cfg.rt_priority);
+	}
+	// Not worth refusing to start over: we'd just be as swappable as before
+	if cfg.lock_memory {
+		match lock_memory() {
+			Ok(()) => log!(Level::Info, "memory locked"),
+			Err(e) => log!(Level::Warn, "can't lock memory: {}; continuing unlocked", e),
+		}
+	}
+	
+	// Everything privileged is open now; the stat file is reopened every
+	// poll though, so make sure the new user can still read it
+	if let Some(user) = &cfg.user {
+		drop_privileges(user)?;
+		for nvme in &engine.nvmes {
+			File::open(&nvme.path).map_err(|e| io::Error::new(e.kind(),
+				format!("{} not readable as user {}: {}", nvme.path, user, e)))?;
+		}
+		log!(Level::Info, "dropped privileges to user {}", user);
+	}
+	
+	// LED open and every timer armed: tell systemd (Type=notify) we're up
+	if let Err(e) = sd_notify("READY=1") {
+		log!(Level::Warn, "sd_notify READY failed: {}", e);
+	}
+	
+	// Main event loop - runs until SIGTERM/SIGINT
+	engine.run()
+}
+
+/// Timer and LED state for status replies: whether the poll and off
+/// timers are armed, the time to their next expiry, and when the LED last
+/// went on or off (unix ms)
+fn timer_status(poll: &Tfd, off: &Tfd, lit_at: Option<Instant>, off_at: Option<Instant>) -> String {
+	let timer = |name: &str, t: &Tfd| match t.time_remaining() {
+		Ok(Some(left)) => format!("{}_timer=armed {}_left_ms={}", name, name, left.as_millis()),
+		Ok(None) => format!("{}_timer=disarmed {}_left_ms=0", name, name),
+		Err(e) => format!("{}_timer=error({}) {}_left_ms=0", name, e.raw_os_error().unwrap_or(0), name),
+	};
+	let changed = match lit_at.max(off_at) {
+		Some(at) => {
+			let then = std::time::SystemTime::now().checked_sub(at.elapsed()).unwrap_or(std::time::UNIX_EPOCH);
+			then.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0).to_string()
+		}
+		None => "never".to_string(),
+	};
+	format!("{} {} led_changed_at={}", timer("poll", poll), timer("off", off), changed)
+}
+
+/// Stat files that can signal changes: not /proc/diskstats, controller
+/// aggregation or replays, which never do
+fn stat_watchable(c: &Config) -> bool {
+	c.stat_notify && c.source == Source::Stat && c.nvme_controller.is_none() && c.replay.is_none()
+}
+
+// Tags identifying which fd woke epoll_wait (see Engine)
+const POLL_TAG: u64 = 1;  // Poll timer identifier
+const OFF_TAG: u64 = 2;   // Off timer identifier
+const RECONCILE_TAG: u64 = 3;  // LED reconciliation timer identifier
+const PATTERN_TAG: u64 = 4;    // Blink pattern step timer identifier
+const MOUNTS_TAG: u64 = 5;     // Mount table change identifier
+const SUMMARY_TAG: u64 = 6;    // Periodic summary timer identifier
+const HW_CHANGED_TAG: u64 = 7; // LED brightness_hw_changed notification
+const METRICS_TAG: u64 = 8;    // Metrics file rewrite timer identifier
+const SIGNAL_TAG: u64 = 9;     // Signal (reload/shutdown) identifier
+const WATCHDOG_TAG: u64 = 10;  // systemd watchdog keep-alive timer
+const READ_OFF_TAG: u64 = 11;  // Off timer of the separate read LED
+const WRITE_OFF_TAG: u64 = 12; // Off timer of the separate write LED
+const PWM_TAG: u64 = 13;       // Software PWM edge timer
+const FADE_TAG: u64 = 14;      // Fade-out step timer
+const CONTROL_TAG: u64 = 15;   // Control socket client waiting
+const METRICS_HTTP_TAG: u64 = 16; // Metrics HTTP client waiting
+const HEARTBEAT_TAG: u64 = 17; // Idle heartbeat timer
+const STAT_NOTIFY_TAG: u64 = 18; // A stat file signalled a change
+const HOTPLUG_TAG: u64 = 19;	 // A kernel uevent (disk added/removed)
+
+/// The event loop and everything it owns: the epoll set with its timers
+/// and watchers, the LED(s), the monitored devices and the counters
+/// 
+/// new() does the setup, step() handles one batch of epoll events, and
+/// run() steps until SIGTERM/SIGINT (or a finished replay), then leaves
+/// the LEDs in the configured exit state.
+struct Engine {
+	cfg: Config,							// Current settings (replaced on SIGHUP)
+	ep: Epoll,								// Every fd below that can wake us
+	sigfd: SigFd,							// SIGHUP/SIGTERM/SIGINT/SIGUSR1
+	poll_tfd: Tfd,							// Periodic stat poll
+	off_tfd: Tfd,							// Ends the main LED's blink
+	reconcile_tfd: Option<Tfd>,				// Periodic LED re-read (no hw_changed)
+	hw_changed: Option<HwChangedWatch>,		// LED brightness_hw_changed notification
+	heartbeat_tfd: Option<Tfd>,				// Idle heartbeat pulses
+	summary_tfd: Option<Tfd>,				// Periodic summary line
+	metrics_tfd: Option<Tfd>,				// Metrics file rewrites
+	watchdog_tfd: Option<Tfd>,				// systemd watchdog pings
+	metrics_http: Option<MetricsServer>,	// GET /metrics listener
+	control: Option<ControlSocket>,			// Runtime command socket
+	mounts: Option<MountWatch>,				// Mount table changes
+	uevents: Option<UeventWatch>,			// Disks coming and going (hotplug)
+	stat_watch: Option<StatWatch>,			// Change notification on the stat files
+	led: Box<dyn LedBackend>,				// Main LED
+	dir_leds: HashMap<u64, BlinkLed>,		// Per-direction LEDs, by off-timer tag
+	rgb: Option<RgbLed>,					// RGB LED taking over activity blinks
+	pattern: Pattern,						// Timed blink sequences (mounts, heartbeat, resume)
+	pwm: Option<Pwm>,						// Software PWM on an on/off LED
+	fade: Option<Fade>,						// Fade-out of a dimmable LED
+	dimming: bool,							// Brightness tracks throughput
+	nvmes: Vec<Nvme>,						// Monitored devices
+	label: String,							// Their names, for log lines
+	recorder: Option<Recorder>,				// --record capture
+	csv: Option<CsvLog>,					// Throughput time series
+	stats: Stats,							// Counters for summaries and metrics
+	throughput: Throughput,					// Peak and windowed MB/s
+	paused: bool,							// Control socket: ignoring activity, LED off
+	led_override: Option<bool>,				// Control socket: LED held on/off by hand
+	pulsing: bool,							// The pattern playing is a heartbeat pulse
+	led_on: bool,							// Main LED lit by a blink
+	lit_at: Option<Instant>,				// When it lit (min_on_ms)
+	off_at: Option<Instant>,				// When it last went dark (min_off_ms)
+	grace_until: Instant,					// No blinks before this (startup_grace_ms)
+	last_active: Option<Instant>,			// When activity was last seen
+	cur_poll_ms: u64,						// Poll interval currently armed
+	idle_polls: u64,						// Idle polls in a row (adaptive polling)
+	notify_driven: bool,					// Polling on stat file change, timer stopped
+	last_poll_at: Instant,					// When the last poll ran
+	clocks_at: (u64, u64),					// Boottime/monotonic at the last poll (suspend)
+	events: [libc::epoll_event; 32],		// epoll_wait output (one slot per fd)
+	tbuf: [u8; 8],							// Timer acknowledgment reads
+	sbuf: [u8; STAT_BUF_LEN],				// Stat file reads
+	ubuf: Vec<u8>,							// Uevent reads
+}
+
+impl Engine {
+	/// Open the LED(s) and devices, create the timers and watchers and
+	/// register them with epoll
+	fn new(cfg: Config) -> Result<Self, DaemonError> {
+		// Set up epoll for event-driven I/O
+		// This allows us to wait on multiple timers efficiently
+		let ep = Epoll::new()?;
+		
+		// Interval timers (poll, heartbeat, summary, metrics) optionally run on
+		// CLOCK_BOOTTIME so time spent suspended counts towards them
+		let timer_clock = if cfg.boottime_timers { libc::CLOCK_BOOTTIME } else { libc::CLOCK_MONOTONIC };
+		
+		// Create two timers:
+		// 1. Periodic timer for polling NVMe stats at regular intervals
+		let poll_tfd = Tfd::periodic_on(timer_clock, cfg.poll_ms, cfg.align_polls).map_err(DaemonError::TimerArm)?;
+		
+		// 2. One-shot timer for turning LED off after blink duration
+		//	  Created disarmed; we arm it when activity is detected
+		let off_tfd = Tfd::oneshot().map_err(DaemonError::TimerArm)?;
+		
+		// Register both timers with epoll
+		// EPOLLIN means we want to be notified when the fd is readable
+		// (timerfds become readable when they expire)
+		ep.add_fd(poll_tfd.0, POLL_TAG, libc::EPOLLIN as u32)?;
+		ep.add_fd(off_tfd.0, OFF_TAG, libc::EPOLLIN as u32)?;
+		
+		// 3. Out-of-band LED change detection: the driver's
+		//    brightness_hw_changed notification when available, otherwise an
+		//    optional periodic timer that re-reads brightness
+		// (a file epoll can't watch, e.g. not on sysfs, also falls back)
+		let hw_changed = HwChangedWatch::open(&cfg.led_path)
+			.filter(|_| !cfg.dry_run)
+			.filter(|w| ep.add_fd(w.fd(), HW_CHANGED_TAG, libc::EPOLLPRI as u32).is_ok());
+		if hw_changed.is_some() && cfg.reconcile_ms > 0 {
+			log!(Level::Info, "LED reports brightness_hw_changed; using it instead of periodic reconcile");
+		}
+		let reconcile_tfd = if cfg.reconcile_ms > 0 && hw_changed.is_none() {
+			let t = Tfd::periodic(cfg.reconcile_ms, false).map_err(DaemonError::TimerArm)?;
+			ep.add_fd(t.0, RECONCILE_TAG, libc::EPOLLIN as u32)?;
+			Some(t)
+		} else {
+			None
+		};
+
+		// 4. One-shot step timer for blink patterns (mount changes, ...)
+		let pattern = Pattern::new().map_err(DaemonError::TimerArm)?;
+		ep.add_fd(pattern.tfd.0, PATTERN_TAG, libc::EPOLLIN as u32)?;
+		
+		// 4b. Optional idle heartbeat: a short pulse every heartbeat_ms while
+		//     nothing else is lighting the LED; a pulse is a one-step pattern
+		let heartbeat_tfd = if cfg.heartbeat_ms > 0 {
+			let t = Tfd::periodic_on(timer_clock, cfg.heartbeat_ms, false).map_err(DaemonError::TimerArm)?;
+			ep.add_fd(t.0, HEARTBEAT_TAG, libc::EPOLLIN as u32)?;
+			Some(t)
+		} else {
+			None
+		};
+		// The pattern playing is a heartbeat pulse (activity may cut it short)
+		let pulsing = false;
+		
+		// 5. Optional periodic timer for the activity summary line
+		let summary_tfd = if cfg.summary_ms > 0 {
+			// First summary after one full interval, not at startup
+			let t = Tfd::oneshot_on(timer_clock).map_err(DaemonError::TimerArm)?;
+			t.set_periodic(cfg.summary_ms, cfg.summary_ms).map_err(DaemonError::TimerArm)?;
+			ep.add_fd(t.0, SUMMARY_TAG, libc::EPOLLIN as u32)?;
+			Some(t)
+		} else {
+			None
+		};
+		
+		// 6. Optional periodic timer for rewriting the metrics file
+		let metrics_tfd = if cfg.metrics_file.is_some() {
+			let t = Tfd::periodic_on(timer_clock, cfg.metrics_interval_ms, false).map_err(DaemonError::TimerArm)?;
+			ep.add_fd(t.0, METRICS_TAG, libc::EPOLLIN as u32)?;
+			Some(t)
+		} else {
+			None
+		};
+		
+		// 6b. Optional HTTP listener serving the same counters on request
+		let metrics_http = match &cfg.metrics_addr {
+			Some(addr) => {
+				let m = MetricsServer::bind(addr)?;
+				ep.add_fd(m.fd(), METRICS_HTTP_TAG, libc::EPOLLIN as u32)?;
+				log!(Level::Info, "serving metrics on http://{}/metrics", addr);
+				Some(m)
+			}
+			None => None,
+		};
+		
+		// 7. SIGHUP reloads the configuration (systemd ExecReload);
+		//    SIGTERM/SIGINT end the loop so the exit LED state gets written;
+		//    SIGUSR1 logs a one-line summary of the totals so far
+		let sigfd = SigFd::new(&[libc::SIGHUP, libc::SIGTERM, libc::SIGINT, libc::SIGUSR1])?;
+		ep.add_fd(sigfd.0, SIGNAL_TAG, libc::EPOLLIN as u32)?;
+		
+		// 8. systemd watchdog keep-alive (WatchdogSec=), independent of
+		//    disk activity so long idle periods still ping
+		let watchdog_tfd = match watchdog_ping_ms() {
+			Some(ms) => {
+				let t = Tfd::periodic(ms, false).map_err(DaemonError::TimerArm)?;
+				ep.add_fd(t.0, WATCHDOG_TAG, libc::EPOLLIN as u32)?;
+				log!(Level::Info, "systemd watchdog enabled, pinging every {}ms", ms);
+				Some(t)
+			}
+			None => None,
+		};
+		
+		// 9. Optional control socket for runtime commands; like the PID file,
+		//    a reload can't move it
+		let control = match &cfg.control_socket {
+			Some(path) => {
+				let c = ControlSocket::bind(path)?;
+				ep.add_fd(c.fd(), CONTROL_TAG, libc::EPOLLIN as u32)?;
+				log!(Level::Info, "accepting commands on {}", path);
+				Some(c)
+			}
+			None => None,
+		};
+		
+		// Set from the control socket: activity blinks are ignored while
+		// paused (LED off) or while the LED is held on/off by hand
+		let paused = false;
+		let led_override: Option<bool> = None;
+		
+		// Optional mount table watcher; POLLPRI signals a change
+		let mounts = if cfg.mount_blink {
+			let w = MountWatch::new()?;
+			ep.add_fd(w.fd(), MOUNTS_TAG, libc::EPOLLPRI as u32)?;
+			Some(w)
+		} else {
+			None
+		};
+
+		// Initialize LED controller and NVMe monitor
+		let mut led = open_led(&cfg, &cfg.led_path, cfg.active_high).map_err(DaemonError::led_open(&cfg.led_path))?;
+		
+		// Optional per-direction LEDs, keyed by their off-timer's epoll tag
+		let mut dir_leds: HashMap<u64, BlinkLed> = HashMap::new();
+		for (tag, path) in [(READ_OFF_TAG, &cfg.read_led_path), (WRITE_OFF_TAG, &cfg.write_led_path)] {
+			let Some(path) = path else { continue };
+			match open_led(&cfg, path, cfg.active_high).and_then(BlinkLed::new) {
+				Ok(b) => {
+					ep.add_fd(b.off.0, tag, libc::EPOLLIN as u32)?;
+					dir_leds.insert(tag, b);
+				}
+				Err(e) if cfg.led_open_policy == LedOpenPolicy::BestEffort => {
+					log!(Level::Warn, "opening LED {} failed: {}; using {} instead", path, e, cfg.led_path);
+				}
+				Err(e) => return Err(DaemonError::led_open(path)(e)),
+			}
+		}
+		
+		// Throughput-proportional brightness needs a dimmable LED and sector
+		// deltas (depth-proportional in in-flight mode; busy mode always scales
+		// 0-100%); otherwise it's on/off
+		let full_scale = match cfg.nvme_mode {
+			NvmeMode::InFlight => cfg.full_scale_depth,
+			NvmeMode::Busy => Some(100),
+			_ => cfg.full_scale_mb_s,
+		};
+		let dimming = match full_scale {
+			Some(_) if led.max_brightness() <= 1 => {
+				// (software PWM still uses it to scale the duty cycle; busy mode
+				// has no setting to ignore)
+				if !cfg.pwm && cfg.nvme_mode != NvmeMode::Busy {
+					log!(Level::Info, "{} has max_brightness 1; full scale ignored (on/off)", cfg.led_path);
+				}
+				false
+			}
+			Some(_) if cfg.nvme_mode == NvmeMode::Io => {
+				log!(Level::Warn, "full_scale_mb_s needs nvme_mode=sectors; using on/off");
+				false
+			}
+			Some(_) => true,
+			None => false,
+		};
+		
+		// Software PWM only makes sense where nothing better is available
+		let pwm = if cfg.pwm && !dimming {
+			let p = Pwm::new(cfg.pwm_freq_hz).map_err(DaemonError::TimerArm)?;
+			ep.add_fd(p.tfd.0, PWM_TAG, libc::EPOLLIN as u32)?;
+			Some(p)
+		} else {
+			None
+		};
+		
+		// Fade-out needs real brightness levels (and PWM owns the LED if on)
+		let fade = if cfg.fade_ms == 0 || pwm.is_some() {
+			None
+		} else if led.max_brightness() <= 1 {
+			log!(Level::Warn, "{} is on/off only (max_brightness 1); fade_ms ignored", cfg.led_path);
+			None
+		} else {
+			let f = Fade::new(cfg.fade_curve).map_err(DaemonError::TimerArm)?;
+			ep.add_fd(f.tfd.0, FADE_TAG, libc::EPOLLIN as u32)?;
+			Some(f)
+		};
+		
+		// Optional RGB LED: takes over activity blinks from the main LED
+		// (which still shows patterns and the exit state)
+		let rgb = match &cfg.rgb_paths {
+			[Some(_), Some(_), Some(_)] if cfg.dry_run => {
+				log!(Level::Info, "dry run: RGB LED not driven; activity shows on {}", cfg.led_path);
+				None
+			}
+			[Some(r), Some(g), Some(b)] => {
+				let mut l = RgbLed::new([r, g, b])?;
+				l.set_color(0, 0, 0)?;
+				Some(l)
+			}
+			_ => None,
+		};
+		
+		let nvmes = open_monitors(&cfg).map_err(|e| match e.kind() {
+			io::ErrorKind::InvalidInput => DaemonError::Config(e.to_string()),
+			_ => DaemonError::stat_read(stat_list(&cfg))(e),
+		})?;
+		let label = monitors_label(&nvmes);
+		
+		// Capture of what the monitors read, for replaying elsewhere
+		let recorder = match &cfg.record {
+			Some(path) => Some(Recorder::open(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?),
+			None => None,
+		};
+
+		// Throughput time series, fed from the same captured fields
+		let csv = match &cfg.csv {
+			Some(path) => Some(CsvLog::open(path, cfg.csv_interval_ms).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?),
+			None => None,
+		};
+		
+		// Optional change notification on the stat files (not for
+		// /proc/diskstats or replays, which never signal)
+		let stat_watch = if stat_watchable(&cfg) {
+			let w = StatWatch::open(&nvmes, &ep, STAT_NOTIFY_TAG);
+			if w.is_none() {
+				log!(Level::Warn, "can't watch {} for changes; polling on the timer only", cfg.nvme_path);
+			}
+			w
+		} else {
+			None
+		};
+		// Set once a notification has arrived: polls then follow changes and
+		// the poll timer is stopped
+		let notify_driven = false;
+		let last_poll_at = Instant::now();
+		
+		// Clock readings at the last poll, to notice a suspend in between
+		let clocks_at = (clock_ms(libc::CLOCK_BOOTTIME), clock_ms(libc::CLOCK_MONOTONIC));
+		
+		// Disks matching `hotplug` join and leave the monitored set as the
+		// kernel reports them
+		let uevents = match hotplug_pattern(&cfg) {
+			Some(pattern) => match UeventWatch::open() {
+				Ok(w) => {
+					ep.add_fd(w.0, HOTPLUG_TAG, libc::EPOLLIN as u32)?;
+					log!(Level::Info, "hotplug: following disks matching {}", pattern);
+					Some(w)
+				}
+				Err(e) => {
+					log!(Level::Warn, "hotplug: can't listen for uevents ({}); monitoring the disks present now only", e);
+					None
+				}
+			},
+			None => None,
+		};
+		let ubuf = vec![0u8; UEVENT_BUF_LEN];
+
+		// Buffers for epoll events and file reads
+		// One slot per registered fd (any extra wait for the next epoll_wait)
+		let events = [libc::epoll_event { events: 0, u64: 0 }; 32];
+		
+		// Buffer for timer acknowledgment reads (timerfds return u64)
+		let tbuf = [0u8; 8];
+		
+		// Buffer for reading NVMe stat file (256 bytes is plenty)
+		let sbuf = [0u8; STAT_BUF_LEN];
+
+		// Track LED state to avoid redundant operations
+		// This is redundant with Led::current_logical but makes the logic clearer
+		let led_on = false;
+		// When the current blink lit the LED (Instant is CLOCK_MONOTONIC)
+		let lit_at: Option<Instant> = None;
+		// ... and when it last went dark (for min_off_ms)
+		let off_at: Option<Instant> = None;
+
+		// Print startup message (Info level, so hidden by quiet)
+		// This helps with debugging and confirms the daemon started successfully
+		log!(Level::Info,
+				"led={} nvme={} ({}) interval={}ms blink={}ms read_blink={:?} write_blink={:?} active_high={} mode={:?} on_fields={:?} (pid={})",
+				cfg.led_path,			// LED sysfs path
+				match (&cfg.replay, cfg.source) {	// NVMe stat file path(s)
+					(Some(path), _) => path.as_str(),
+					(None, Source::Stat) => cfg.nvme_controller.as_deref()
+						.or(cfg.hotplug.as_deref().filter(|_| stat_list(&cfg).is_empty()))
+						.unwrap_or(stat_list(&cfg)),
+					(None, Source::DiskStats) => DISKSTATS_PATH
+				},
+				label,					// Device label(s)
+				cfg.poll_ms,			// Polling interval
+				base_blink_ms(&cfg),	// Default blink duration
+				cfg.read_blink_ms,		// Read-specific blink duration (if set)
+				cfg.write_blink_ms,		// Write-specific blink duration (if set)
+				cfg.active_high,		// LED polarity
+				match cfg.nvme_mode {	// Which stat fields we're monitoring
+					NvmeMode::Sectors => "sectors", 
+					NvmeMode::Io => "io",
+					NvmeMode::InFlight => "inflight",
+					NvmeMode::Busy => "busy"
+				},
+				match cfg.on_fields {	// Which operations trigger LED
+					FieldsSel::Reads => "reads", 
+					FieldsSel::Writes => "writes", 
+					FieldsSel::Both => "both",
+					FieldsSel::Discards => "discards",
+					FieldsSel::All => "all"
+				},
+				std::process::id()		// Our PID (useful for systemd, etc.)
+		);
+
+		// Ensure LED starts in off state
+		// Ignore errors here (LED might already be off)
+		let _ = led.off();
+		
+		// Alive signal for headless machines; ends with the LED off again
+		if cfg.startup_blink {
+			startup_blink(led.as_mut())?;
+		}
+		
+		// Blinks are suppressed until this instant so the boot-time I/O storm
+		// doesn't produce a flurry right as we start (Instant is monotonic)
+		let grace_until = Instant::now() + Duration::from_millis(cfg.startup_grace_ms);
+		
+		// When activity was last seen (edge-only mode measures idle gaps from it)
+		let last_active: Option<Instant> = None;
+		
+		// Counters for the periodic summary
+		let stats = Stats::default();
+		
+		// Peak and windowed throughput (sectors mode)
+		let throughput = Throughput::new(Instant::now());
+		
+		// Adaptive polling: the interval currently armed, and how many polls
+		// in a row have been idle
+		let cur_poll_ms = cfg.poll_ms;
+		let idle_polls = 0u64;
+
+		Ok(Self {
+			cfg, ep, sigfd, poll_tfd, off_tfd, reconcile_tfd,
+			hw_changed, heartbeat_tfd, summary_tfd, metrics_tfd, watchdog_tfd, metrics_http,
+			control, mounts, uevents, stat_watch, led, dir_leds,
+			rgb, pattern, pwm, fade, dimming, nvmes,
+			label, recorder, csv, stats, throughput, paused,
+			led_override, pulsing, led_on, lit_at, off_at, grace_until,
+			last_active, cur_poll_ms, idle_polls, notify_driven, last_poll_at, clocks_at,
+			events, tbuf, sbuf, ubuf,
+		})
+	}
+	
+	/// Add an fd to the running engine's epoll set; its events come back
+	/// to `step` under `tag`
+	fn register(&self, fd: RawFd, tag: u64, events: u32) -> io::Result<()> {
+		self.ep.add_fd(fd, tag, events)
+	}
+
+	/// Wait up to `timeout_ms` (-1 = for ever) for events and handle them
+	/// 
+	/// Returns false once the daemon should stop: SIGTERM/SIGINT, or a
+	/// replay that has run out.
+	fn step(&mut self, timeout_ms: i32) -> Result<bool, DaemonError> {
+		// Every field borrowed separately, so the handlers below can use
+		// several at once
+		let Self {
+			cfg, ep, sigfd, poll_tfd, off_tfd, reconcile_tfd, hw_changed, heartbeat_tfd,
+			summary_tfd, metrics_tfd, watchdog_tfd, metrics_http, control, mounts, uevents,
+			stat_watch, led, dir_leds, rgb, pattern, pwm, fade, dimming, nvmes, label, recorder,
+			csv, stats, throughput, paused, led_override, pulsing, led_on, lit_at, off_at,
+			grace_until, last_active, cur_poll_ms, idle_polls, notify_driven, last_poll_at,
+			clocks_at, events, tbuf, sbuf, ubuf,
+		} = self;
+		
+		// Wait for timer events (blocks until at least one timer expires)
+		// This is efficient: the process sleeps and the kernel wakes it
+		// when a timer fires. No busy-waiting or polling.
+		let n = ep.wait_timeout(events, timeout_ms)?;
+		
+		// Process events in a fixed order rather than epoll's: polls first.
+		// If the poll and off timers are both ready, the poll may extend the
+		// blink by re-arming the off-timer, which cancels its pending
+		// expiration; OFF_TAG then sees a stale event (ack() == 0) and
+		// leaves the LED on. Handling the off first would instead drop the
+		// LED and immediately relight it - a visible glitch.
+		events[..n].sort_by_key(|e| if e.u64 == POLL_TAG || e.u64 == STAT_NOTIFY_TAG { 0 } else { 1 });
+		
+		// Several stat files can signal at once; one poll covers them all
+		let mut polled = false;
+		// SIGHUP seen; the reload runs once the batch is handled
+		let mut reload = false;
+		
+		// Process all events that occurred
+		// Usually n=1 (one timer fired), but could be 2 if both fired
+		// between epoll_wait calls (unlikely but possible)
+		for ev in &events[..n] {
+			// Extract the tag we set when registering the fd
+			// This tells us which timer fired
+			let tag = ev.u64;
+			
+			match tag {
+				POLL_TAG | STAT_NOTIFY_TAG => {
+					// Polling timer fired (or a stat file changed) - time to
+					// check for NVMe activity
+					
+					// First, acknowledge the timer to clear its readable state
+					// This prevents epoll from immediately triggering again
+					// More than one expiration means we fell behind
+					let missed = if tag == POLL_TAG {
+						let expirations = poll_tfd.ack(tbuf);
+						if *notify_driven {
+							continue;  // Stale tick from before the switch
+						}
+						expirations.saturating_sub(1)
+					} else {
+						if let Some(w) = stat_watch {
+							w.drain();
+						}
+						if polled {
+							continue;
+						}
+						if !*notify_driven {
+							log!(Level::Info, "{} signals changes; polling on change instead of every {}ms", label, *cur_poll_ms);
+							poll_tfd.set_periodic(0, 0).map_err(DaemonError::TimerArm)?;  // Zero disarms
+							*notify_driven = true;
+						}
+						0
+					};
+					polled = true;
+					
+					// Back from suspend: CLOCK_BOOTTIME ran on while
+					// CLOCK_MONOTONIC (and so our timers) stood still. The
+					// counters may have moved across the sleep (resume
+					// I/O, or a reset), so take them as the new baseline
+					// instead of blinking for them
+					let clocks = (clock_ms(libc::CLOCK_BOOTTIME), clock_ms(libc::CLOCK_MONOTONIC));
+					let slept = slept_ms(*clocks_at, clocks);
+					*clocks_at = clocks;
+					if slept >= RESUME_GAP_MS {
+						log!(Level::Info, "resumed after {:.1}s asleep; re-baselining {}", slept as f64 / 1000.0, label);
+						for n in nvmes.iter_mut() {
+							n.rebaseline();
+						}
+						if cfg.resume_blink && !*paused && led_override.is_none() {
+							if let Some(p) = pwm {
+								p.stop()?;
+							}
+							if let Some(f) = fade {
+								f.cancel()?;
+							}
+							pattern.start(&RESUME_PATTERN, led.as_mut())?;
+						}
+					}
+					
+					// Check if there's been any disk activity since last poll
+					// Returns Some(Dir) if activity detected, None otherwise
+					// Baselines are updated even during the startup grace
+					// period; only the blink itself is held back
+					let (activity, mixed, delta, smoothed, depth, busy) = poll_monitors(nvmes, sbuf, mixed_dir(cfg))
+						.map_err(DaemonError::stat_read(Some(stat_list(cfg)).filter(|l| !l.is_empty()).unwrap_or(label)))?;
+					if let Some(r) = recorder
+						&& let Err(e) = r.write(nvmes) {
+						log!(Level::Warn, "recording to {} failed: {}; recording stopped", cfg.record.as_deref().unwrap_or(""), e);
+						*recorder = None;
+					}
+					if let Some(c) = csv
+						&& let Err(e) = c.write(nvmes) {
+						log!(Level::Warn, "writing {} failed: {}; CSV logging stopped", cfg.csv.as_deref().unwrap_or(""), e);
+						*csv = None;
+					}
+					
+					// A replay that has run out ends the run once the LED
+					// has finished its last blink
+					if activity.is_none() && !*led_on && !nvmes.is_empty() && nvmes.iter().all(|n| n.source.finished()) {
+						log!(Level::Info, "replay finished");
+						return Ok(false);
+					}
+					
+					// Deltas cover the interval that just elapsed; then
+					// back off while idle, snap back on activity
+					// (on change, the time since the last poll, up to an interval)
+					let elapsed_ms = if *notify_driven {
+						(last_poll_at.elapsed().as_millis() as u64).clamp(1, cfg.poll_ms)
+					} else {
+						*cur_poll_ms
+					};
+					*last_poll_at = Instant::now();
+					*idle_polls = if activity.is_some() { 0 } else { *idle_polls + 1 };
+					let next_ms = backoff_interval(cfg, *cur_poll_ms, *idle_polls, activity.is_some());
+					if next_ms != *cur_poll_ms && !*notify_driven {
+						log!(Level::Debug, "poll interval {}ms -> {}ms", *cur_poll_ms, next_ms);
+						poll_tfd.set_periodic(next_ms, if activity.is_some() { 1 } else { next_ms }).map_err(DaemonError::TimerArm)?;
+						*cur_poll_ms = next_ms;
+					}
+					// Byte counts only mean something when counting sectors
+					let (bytes_read, bytes_written) = match cfg.nvme_mode {
+						NvmeMode::Sectors => nvmes.iter().fold((0, 0), |(r, w), n|
+							(r + n.last_read_delta * SECTOR_BYTES as u128, w + n.last_write_delta * SECTOR_BYTES as u128)),
+						_ => (0, 0),
+					};
+					stats.bump(|c| {
+						c.polls += 1;
+						c.bytes_read += bytes_read;
+						c.bytes_written += bytes_written;
+						c.missed_ticks += missed;
+						c.peak_delta = c.peak_delta.max(delta);
+						match activity {
+							Some(Dir::Read) => c.reads += 1,
+							Some(Dir::Write) => c.writes += 1,
+							Some(Dir::Discard) => c.discards += 1,
+							None => {}
+						}
+					});
+					throughput.add(Instant::now(), bytes_read, bytes_written);
+					
+					// Paused or held by hand: keep tracking, don't blink
+					if *paused || led_override.is_some() {
+						continue;
+					}
+					
+					// Real activity takes over from a heartbeat pulse
+					*pulsing &= pattern.playing();
+					if *pulsing && activity.is_some() {
+						pattern.cancel()?;
+						*pulsing = false;
+					}
+					
+					// A separate LED for a direction blinks on its own
+					// (discards have no LED of their own; they use the main one)
+					let (own_read, own_write) = (dir_leds.contains_key(&READ_OFF_TAG), dir_leds.contains_key(&WRITE_OFF_TAG));
+					let own_led = |d: Dir| match d {
+						Dir::Read => own_read.then_some(READ_OFF_TAG),
+						Dir::Write => own_write.then_some(WRITE_OFF_TAG),
+						Dir::Discard => None,
+					};
+					
+					// A mixed poll lights both directions where one has an LED
+					// of its own; the main LED alone shows the mixed_priority
+					// pick (an RGB LED blends both colors)
+					let split = mixed && (own_read || own_write);
+					let shown = if split { [Some(Dir::Read), Some(Dir::Write)] } else { [activity, None] };
+					let blend = mixed && !split;
+					
+					// Decide what each LED should do, then apply it
+					let now = Instant::now();
+					let in_grace = cfg.startup_grace_ms > 0 && now < *grace_until;
+					let idle_for = last_active.map(|t| now.duration_since(t));
+					if activity.is_some() {
+						*last_active = Some(now);
+					}
+					for dir in shown.into_iter().flatten() {
+						let tag = own_led(dir);
+						let dark_since = match tag.and_then(|t| dir_leds.get(&t)) {
+							Some(b) => b.off_at.filter(|_| !b.lit),
+							None => off_at.filter(|_| !*led_on),
+						};
+						let state = BlinkState {
+							in_grace,
+							idle_for,
+							pattern_playing: pattern.playing(),
+							dark_for: dark_since.map(|t| now.duration_since(t)),
+						};
+						let Action::Blink(dur) = decide(cfg, Some(dir), state) else { continue };
+						log!(Level::Debug, fields: &[
+								("DIRECTION", format!("{:?}", dir).to_lowercase()),
+								("LED_STATE", "on".to_string()),
+								("BYTES", (bytes_read + bytes_written).to_string()),
+								("BLINK_MS", dur.to_string()),
+							],
+							"{} {:?} activity, LED on for {}ms", label, dir, dur);
+						
+						if let Some(b) = tag.and_then(|t| dir_leds.get_mut(&t)) {
+							if !b.lit {
+								b.led.on()?;
+								b.lit = true;
+								b.lit_at = Some(Instant::now());
+								stats.bump(|c| c.blinks += 1);
+							}
+							b.off.arm_after_ms(dur)?;
+							continue;
+						}
+						
+						// Turn LED on if not already on
+						// The LED::on() method will skip the write if already on
+						// (an RGB LED is always set: the color may change)
+						// Activity mid-fade cancels it and relights fully
+						if let Some(f) = fade
+							&& f.active() {
+							f.cancel()?;
+							*led_on = false;
+						}
+						if let Some(l) = rgb {
+							let (r, g, b) = match dir {
+								_ if blend => blend_colors(cfg.read_color, cfg.write_color),
+								Dir::Read => cfg.read_color,
+								_ => cfg.write_color,
+							};
+							l.set_color(r, g, b)?;
+						} else if *dimming {
+							// Re-level every active poll; same level is a no-op
+							// Smoothed so a bursty workload doesn't flicker
+							let lvl = match cfg.nvme_mode {
+								NvmeMode::InFlight => scaled_level(depth,
+									cfg.full_scale_depth.unwrap_or(1), led.max_brightness()),
+								NvmeMode::Busy => scaled_level(busy as u128, 100, led.max_brightness()),
+								_ => throughput_level(smoothed.round() as u128, elapsed_ms,
+									cfg.full_scale_mb_s.unwrap_or(1), led.max_brightness()),
+							};
+							// Hysteresis catches the near-identical ones
+							led.set_level(with_hysteresis(led.level(), lvl, cfg.brightness_hysteresis))?;
+						} else if fade.is_some() {
+							// Fading LEDs blink at full so there's a range to fade
+							led.set_level(led.max_brightness())?;
+						} else if let Some(p) = pwm {
+							// Busy mode's percentage is a duty cycle already
+							let duty = match cfg.nvme_mode {
+								NvmeMode::Busy => busy,
+								_ => pwm_duty(cfg.pwm_duty, smoothed.round() as u128, elapsed_ms, cfg.full_scale_mb_s),
+							};
+							p.start(duty, led.as_mut())?;
+						} else if !*led_on { 
+							led.on()?; 
+						}
+						if !*led_on {
+							*led_on = true; 
+							*lit_at = Some(Instant::now());
+							stats.bump(|c| c.blinks += 1);
+						}
+						
+						// Schedule LED turn-off after blink duration
+						// If the timer is already armed (from previous activity),
+						// this resets it to the new duration. This is how we
+						// extend the LED blink on continuous activity: each new
+						// activity event pushes the off-time further into the future.
+						off_tfd.arm_after_ms(dur)?;
+					}
+				}
+				
+				OFF_TAG => {
+					// Off-timer fired - time to turn LED off
+					
+					// Acknowledge the timer to clear its readable state
+					// Nothing to read means a poll in this same batch re-armed
+					// it: the blink was extended, so the LED stays on
+					if off_tfd.ack(tbuf) == 0 {
+						continue;
+					}
+					
+					// Too soon for a perceptible blink: stay on for the rest
+					// Otherwise turn the LED off if it's currently on
+					// (a playing pattern owns the LED and turns it off itself)
+					match decide_off(cfg, *led_on, *lit_at, Instant::now()) {
+						Action::Blink(ms) => off_tfd.arm_after_ms(ms)?,
+						Action::Leave => {}
+						Action::Off => {
+							if let Some(p) = pwm {
+								p.stop()?;
+							}
+							if let Some(l) = rgb {
+								l.set_color(0, 0, 0)?;
+							} else if pattern.playing() {
+								// The pattern turns the LED off itself
+							} else if let Some(f) = fade {
+								f.start(led.level().unwrap_or(led.max_brightness()), cfg.fade_ms)?;
+							} else {
+								led.off()?;
+							}
+							*led_on = false;
+							*off_at = Some(Instant::now());
+						}
+					}
+				}
+				
+				READ_OFF_TAG | WRITE_OFF_TAG => {
+					// A per-direction LED's off-timer fired; same stale-event
+					// rule as the main LED
+					if let Some(b) = dir_leds.get_mut(&tag)
+						&& b.off.ack(tbuf) != 0 {
+						match decide_off(cfg, b.lit, b.lit_at, Instant::now()) {
+							Action::Blink(ms) => b.off.arm_after_ms(ms)?,
+							Action::Leave => {}
+							Action::Off => {
+								b.led.off()?;
+								b.lit = false;
+								b.off_at = Some(Instant::now());
+							}
+						}
+					}
+				}
+				
+				FADE_TAG => {
+					// Fade step - dim one notch (no-op once cancelled)
+					if let Some(f) = fade {
+						f.tick(led.as_mut(), tbuf)?;
+					}
+				}
+				
+				PWM_TAG => {
+					// PWM edge - toggle the LED (no-op once stopped)
+					if let Some(p) = pwm {
+						p.tick(led.as_mut(), tbuf)?;
+					}
+				}
+				
+				PATTERN_TAG => {
+					// Pattern step timer fired - show the next step
+					pattern.advance(led.as_mut(), tbuf)?;
+				}
+				
+				MOUNTS_TAG => {
+					// Mount table changed - play the mount pattern unless
+					// we just did (mount storms are throttled)
+					if let Some(w) = mounts
+						&& w.changed(cfg.mount_throttle_ms)? {
+						log!(Level::Debug, "mount table changed");
+						// The pattern owns the LED now; don't fight it
+						if let Some(p) = pwm {
+							p.stop()?;
+						}
+						if let Some(f) = fade {
+							f.cancel()?;
+						}
+						pattern.start(&cfg.mount_pattern, led.as_mut())?;
+					}
+				}
+				
+				HEARTBEAT_TAG => {
+					// Heartbeat timer fired - pulse if the disk has been
+					// idle for a whole period and nothing else owns the LED
+					if let Some(t) = &heartbeat_tfd {
+						t.ack(tbuf);
+					}
+					let idle = last_active.is_none_or(|t| t.elapsed() >= Duration::from_millis(cfg.heartbeat_ms));
+					let fading = fade.as_ref().is_some_and(|f| f.active());
+					if idle && !*led_on && !fading && !pattern.playing() && !*paused && led_override.is_none() {
+						pattern.start(&[HEARTBEAT_BLINK_MS], led.as_mut())?;
+						*pulsing = true;
+					}
+				}
+				
+				RECONCILE_TAG | HW_CHANGED_TAG => {
+					// Reconciliation timer fired, or firmware reported a
+					// brightness change - check the LED against our cache
+					if let Some(t) = &reconcile_tfd {
+						t.ack(tbuf);
+					}
+					if tag == HW_CHANGED_TAG && let Some(w) = hw_changed {
+						w.drain();
+					}
+					
+					// A failed read isn't fatal; we'll try again next tick
+					match led.reconcile() {
+						Ok(Some(seen)) => {
+							log!(Level::Warn,
+								fields: &[("LED_STATE", if seen { "on" } else { "off" }.to_string())],
+								"LED changed out-of-band (now {}, expected {}); next update will rewrite it",
+								if seen { "on" } else { "off" },
+								if *led_on { "on" } else { "off" }
+							);
+							// Treat the blink as over, so the next activity
+							// lights the LED again instead of assuming it's lit
+							*led_on = false;
+						}
+						Ok(None) => {}
+						Err(e) => {
+							log!(Level::Warn, "reconcile read of {} failed: {}", cfg.led_path, e);
+							stats.bump(|c| c.errors += 1);
+						}
+					}
+				}
+				
+				SUMMARY_TAG => {
+					// Summary timer fired - log the interval rollup and
+					// start a new interval (lifetime totals keep going)
+					if let Some(t) = &summary_tfd {
+						t.ack(tbuf);
+					}
+					let i = stats.take_interval();
+					let t = stats.total;
+					log!(Level::Info,
+						"summary {}: last {}ms polls={} blinks={} reads={} writes={} discards={} peak_delta={} missed={} errors={} | total blinks={} reads={} writes={} discards={} errors={}",
+						label, cfg.summary_ms, i.polls, i.blinks, i.reads, i.writes, i.discards,
+						i.peak_delta, i.missed_ticks, i.errors,
+						t.blinks, t.reads, t.writes, t.discards, t.errors);
+				}
+				
+				METRICS_HTTP_TAG => {
+					// A scraper connected; a client that misbehaves only
+					// costs it its answer
+					if let Some(m) = &metrics_http
+						&& let Err(e) = m.serve(|| render_metrics(label, &stats.total, throughput, *led_on)) {
+						log!(Level::Debug, "metrics request failed: {}", e);
+					}
+				}
+				
+				METRICS_TAG => {
+					// Metrics timer fired - rewrite the file with lifetime totals
+					if let Some(t) = &metrics_tfd {
+						t.ack(tbuf);
+					}
+					if let Some(path) = &cfg.metrics_file
+						&& let Err(e) = write_metrics(path, label, &stats.total, throughput, *led_on) {
+						log!(Level::Warn, "writing metrics to {} failed: {}", path, e);
+						stats.bump(|c| c.errors += 1);
+					}
+				}
+				
+				WATCHDOG_TAG => {
+					// Watchdog timer fired - tell systemd we're still alive
+					if let Some(t) = &watchdog_tfd {
+						t.ack(tbuf);
+					}
+					if let Err(e) = sd_notify("WATCHDOG=1") {
+						log!(Level::Warn, "sd_notify WATCHDOG failed: {}", e);
+						stats.bump(|c| c.errors += 1);
+					}
+				}
+				
+				CONTROL_TAG => {
+					// A client connected: answer each of its commands in turn
+					let Some((mut stream, lines)) = control.as_ref().and_then(ControlSocket::accept) else {
+						continue;
+					};
+					for line in lines {
+						log!(Level::Info, "control: {}", line);
+						let words: Vec<&str> = line.split_whitespace().collect();
+						
+						// Taking the LED over (or giving it back) first stops
+						// whatever the blink logic was doing with it
+						let hand = match words.as_slice() {
+							["led", "on"] => Some((Some(true), false)),
+							["led", "off"] => Some((Some(false), false)),
+							["led", "auto"] => Some((None, *paused)),
+							["pause"] => Some((*led_override, true)),
+							["resume"] => Some((*led_override, false)),
+							_ => None,
+						};
+						if let Some((hold, pause)) = hand {
+							off_tfd.arm_after_ms(0)?;
+							if let Some(p) = pwm {
+								p.stop()?;
+							}
+							if let Some(f) = fade
+								&& f.active() {
+								f.cancel()?;
+							}
+							let on = hold == Some(true) && !pause;
+							led.set(on)?;
+							if let Some(l) = rgb {
+								let v = if on { 255 } else { 0 };
+								l.set_color(v, v, v)?;
+							}
+							(*led_override, *paused, *led_on) = (hold, pause, false);
+						}
+						
+						let reply = match words.as_slice() {
+							["status"] => {
+								let t = &stats.total;
+								format!("state={} led={} override={} interval_ms={} blink_ms={} polls={} blinks={} reads={} writes={} discards={} errors={} {} {}",
+									if *paused { "paused" } else { "running" },
+									if led_override.unwrap_or(*led_on) && !*paused { "on" } else { "off" },
+									match *led_override { None => "auto", Some(true) => "on", Some(false) => "off" },
+									*cur_poll_ms, base_blink_ms(cfg), t.polls, t.blinks, t.reads, t.writes, t.discards, t.errors,
+									throughput.summary(), timer_status(poll_tfd, off_tfd, *lit_at, *off_at))
+							}
+							["set", key, value] if CONTROL_SET_KEYS.contains(key) => {
+								let valid = match *key {
+									"edge_only" => matches!(*value, "true" | "yes" | "1" | "false" | "no" | "0"),
+									// A 0ms blink would disarm the off-timer and leave the
+									// LED on; the CLI clamps these to 1ms
+									k if k.ends_with("blink_ms") => parse_ms(value).is_some_and(|ms| ms > 0),
+									_ => parse_ms(value).is_some(),
+								};
+								if valid {
+									apply_config_map(cfg, &HashMap::from([(key.to_string(), value.to_string())]));
+									format!("ok {}={}", key, value)
+								} else {
+									format!("error: invalid value for {}: {}", key, value)
+								}
+							}
+							["set", key, ..] => format!("error: {} can't be set at runtime (settable: {})", key, CONTROL_SET_KEYS.join(", ")),
+							_ if hand.is_some() => "ok".to_string(),
+							_ => format!("error: unknown command: {} (status, set KEY VALUE, led on|off|auto, pause, resume)", line),
+						};
+						if stream.write_all(format!("{}\n", reply).as_bytes()).is_err() {
+							break;
+						}
+					}
+				}
+				
+				HOTPLUG_TAG => {
+					let (Some(w), Some(pattern)) = (&uevents, hotplug_pattern(cfg)) else { continue };
+					let mut changed = false;
+					while let Some((added, dev)) = w.read(ubuf) {
+						if !glob_match(pattern, &dev) {
+							continue;
+						}
+						let present = nvmes.iter().position(|n| n.path == stat_path_of(&dev));
+						match (added, present) {
+							(true, None) if nvmes.len() >= cfg.max_devices => {
+								log!(Level::Warn, "hotplug: {} added, but max_devices ({}) are monitored already", dev, cfg.max_devices);
+							}
+							// Take the baseline now: a first read that fails
+							// would otherwise be fatal at the next poll
+							(true, None) => match open_monitor(cfg, &dev, None, None)
+								.and_then(|mut n| n.activity_dir(sbuf).map(|_| n)) {
+								Ok(n) => {
+									log!(Level::Info, "hotplug: {} added, monitoring it", dev);
+									nvmes.push(n);
+									changed = true;
+								}
+								Err(e) => log!(Level::Warn, "hotplug: {} added but can't be read: {}", dev, e),
+							},
+							(false, Some(i)) => {
+								log!(Level::Info, "hotplug: {} removed", dev);
+								nvmes.remove(i);
+								changed = true;
+							}
+							_ => {}
+						}
+					}
+					if !changed {
+						continue;
+					}
+					*label = monitors_label(nvmes);
+					if let Some(c) = csv {
+						c.rebase();
+					}
+					// The set of files to watch changed; timer polling covers
+					// the gap until one of them signals again
+					if stat_watchable(cfg) {
+						*stat_watch = StatWatch::open(nvmes, ep, STAT_NOTIFY_TAG);
+						if *notify_driven {
+							poll_tfd.set_periodic(cfg.poll_ms, cfg.poll_ms).map_err(DaemonError::TimerArm)?;
+							(*cur_poll_ms, *idle_polls, *notify_driven) = (cfg.poll_ms, 0, false);
+						}
+					}
+				}
+				
+				SIGNAL_TAG => {
+					// SIGTERM/SIGINT: leave the loop for the exit LED write
+					// SIGHUP: re-read config file and CLI, then apply what
+					// changed (see reload())
+					while let Some(sig) = sigfd.read() {
+						if sig == libc::SIGTERM || sig == libc::SIGINT {
+							log!(Level::Info, "received signal {}, shutting down", sig);
+							return Ok(false);
+						}
+						if sig == libc::SIGUSR1 {
+							let t = stats.total;
+							log!(Level::Info, "status {}: polls={} blinks={} bytes_read={} bytes_written={} {} interval_ms={} led={} {}",
+								label, t.polls, t.blinks, t.bytes_read, t.bytes_written, throughput.summary(), *cur_poll_ms,
+								if *led_on { "on" } else { "off" }, timer_status(poll_tfd, off_tfd, *lit_at, *off_at));
+							continue;
+						}
+						if sig == libc::SIGHUP {
+							reload = true;
+						}
+					}
+				}
+				
+				_ => {
+					// Unknown tag (shouldn't happen with our setup)
+					// We only registered two fds with specific tags
+					// If we get here, something is very wrong
+				}
+			}
+		}
+		if reload {
+			self.reload(load_config_layers())?;
+		}
+		Ok(true)
+	}
+
+	/// Apply a reloaded configuration (SIGHUP) over the running one
+	/// 
+	/// Anything that can't be applied keeps its old value rather than taking
+	/// the daemon down; a configuration that didn't load at all changes
+	/// nothing.
+	fn reload(&mut self, loaded: Result<Config, String>) -> Result<(), DaemonError> {
+		let Self {
+			cfg, ep, poll_tfd, hw_changed, led, nvmes, label, csv, stat_watch, led_on,
+			cur_poll_ms, idle_polls, notify_driven, ..
+		} = self;
+		
+		let mut new = match loaded {
+			Ok(c) => c,
+			Err(e) => {
+				log!(Level::Error, "reload: {}; keeping the current configuration", e);
+				return Ok(());
+			}
+		};
+		LOG_LEVEL.store(new.log_level as u8, Ordering::Relaxed);
+		if let Err(e) = set_log_target(new.log_target) {
+			log!(Level::Warn, "reload: can't log to {:?}: {}; using stdout", new.log_target, e);
+		}
+		
+		// The CSV file stays open as it was (needs a restart),
+		// and the monitors must keep capturing for it
+		(new.csv, new.csv_interval_ms) = (cfg.csv.clone(), cfg.csv_interval_ms);
+		// So does the clock the timers were created on
+		new.boottime_timers = cfg.boottime_timers;
+		
+		// An unset device is detected again (normally to the
+		// same one); failure keeps the device(s) we have
+		if new.source == Source::Stat && stat_list(&new).is_empty() && new.nvme_controller.is_none() && new.hotplug.is_none() && new.replay.is_none() {
+			match detect_nvme_path() {
+				Ok(path) => new.nvme_path = path,
+				Err(e) => {
+					log!(Level::Error, "reload: {}; keeping {}", e, cfg.nvme_path);
+					new.nvme_path = cfg.nvme_path.clone();
+				}
+			}
+		}
+		
+		// Likewise a missing default LED; failure keeps the
+		// LED we have
+		if new.led_slot.is_none() && new.led_path == DEFAULT_LED_PATH
+			&& !std::path::Path::new(DEFAULT_LED_PATH).exists() {
+			match detect_led_path() {
+				Ok(path) => new.led_path = path,
+				Err(e) => {
+					log!(Level::Error, "reload: {}; keeping {}", e, cfg.led_path);
+					new.led_path = cfg.led_path.clone();
+				}
+			}
+		}
+		
+		// Enclosure slots are re-resolved; failure keeps the
+		// LED we have
+		if let Some(spec) = &new.led_slot {
+			match resolve_enclosure_slot(std::path::Path::new(ENCLOSURE_ROOT), spec, &new.led_slot_attr) {
+				Ok(path) => new.led_path = path,
+				Err(e) => {
+					log!(Level::Error, "reload: {}; keeping {}", e, cfg.led_path);
+					new.led_path = cfg.led_path.clone();
+				}
+			}
+		}
+		
+		// Different LED (or polarity): the old one is left as at exit, and
+		// the new one starts off
+		let mut rewatch = false;
+		if new.led_path != cfg.led_path || new.active_high != cfg.active_high {
+			match open_led(&new, &new.led_path, new.active_high) {
+				Ok(l) => {
+					if let Err(e) = release_led(led.as_mut(), new.exit_led_state) {
+						log!(Level::Warn, "reload: releasing LED {}: {}", cfg.led_path, e);
+					}
+					*led = l;
+					let _ = led.off();
+					*led_on = false;
+					*hw_changed = HwChangedWatch::open(&new.led_path).filter(|_| !new.dry_run);
+					rewatch = true;
+				}
+				Err(e) => {
+					log!(Level::Error, "reload: opening LED {} failed: {}; keeping {}",
+						new.led_path, e, cfg.led_path);
+					new.led_path = cfg.led_path.clone();
+					new.active_high = cfg.active_high;
+				}
+			}
+		}
+		
+		if new.poll_ms != *cur_poll_ms {
+			poll_tfd.set_periodic(new.poll_ms, new.poll_ms).map_err(DaemonError::TimerArm)?;
+			*cur_poll_ms = new.poll_ms;
+			*idle_polls = 0;
+		}
+		
+		// A different device (or way of reading it) starts
+		// from a fresh baseline
+		if new.nvme_path != cfg.nvme_path || new.nvme_name != cfg.nvme_name
+			|| new.nvme_controller != cfg.nvme_controller || new.hotplug != cfg.hotplug
+			|| new.source != cfg.source || new.device != cfg.device
+			|| new.min_read != cfg.min_read || new.min_write != cfg.min_write
+			|| new.smoothing != cfg.smoothing || new.on_fields != cfg.on_fields
+			|| new.nvme_mode != cfg.nvme_mode || new.stat_format != cfg.stat_format
+			|| new.aggregate_members != cfg.aggregate_members || new.max_devices != cfg.max_devices {
+			match open_monitors(&new) {
+				Ok(n) => {
+					*nvmes = n;
+					if let Some(c) = csv {
+						c.rebase();
+					}
+					*label = monitors_label(nvmes);
+					*stat_watch = None;
+				}
+				Err(e) => {
+					log!(Level::Error, "reload: {}: {}; keeping {}", new.nvme_path, e, cfg.nvme_path);
+					new.nvme_path = cfg.nvme_path.clone();
+					new.nvme_name = cfg.nvme_name.clone();
+					new.nvme_controller = cfg.nvme_controller.clone();
+					new.hotplug = cfg.hotplug.clone();
+					new.source = cfg.source;
+					new.device = cfg.device.clone();
+					new.min_read = cfg.min_read;
+					new.min_write = cfg.min_write;
+					new.smoothing = cfg.smoothing;
+					new.on_fields = cfg.on_fields;
+					new.nvme_mode = cfg.nvme_mode;
+					new.stat_format = cfg.stat_format;
+					new.aggregate_members = cfg.aggregate_members;
+					new.max_devices = cfg.max_devices;
+				}
+			}
+		}
+		
+		// New files (or a new setting) have to signal again
+		// before polling stops following the timer
+		if stat_watch.is_some() != stat_watchable(&new) || *notify_driven {
+			*stat_watch = stat_watchable(&new).then(|| StatWatch::open(nvmes, ep, STAT_NOTIFY_TAG)).flatten();
+			if *notify_driven {
+				poll_tfd.set_periodic(new.poll_ms, new.poll_ms).map_err(DaemonError::TimerArm)?;
+				(*cur_poll_ms, *idle_polls, *notify_driven) = (new.poll_ms, 0, false);
+			}
+		}
+		
+		*cfg = new;
+		log!(Level::Info, "configuration reloaded: led={} nvme={} interval={}ms blink={}ms",
+			cfg.led_path, cfg.nvme_path, cfg.poll_ms, base_blink_ms(cfg));
+		
+		// The new LED's hw_changed watch, if it has one
+		let fd = self.hw_changed.as_ref().map(HwChangedWatch::fd).filter(|_| rewatch);
+		if let Some(fd) = fd
+			&& self.register(fd, HW_CHANGED_TAG, libc::EPOLLPRI as u32).is_err() {
+			self.hw_changed = None;
+		}
+		Ok(())
+	}
+
+	/// Step until told to stop, then write the exit LED state
+	fn run(&mut self) -> Result<(), DaemonError> {
+		while self.step(-1)? {}
+		self.shutdown()
+	}
+
+	/// Leave the LEDs in the configured exit state
+	fn shutdown(&mut self) -> Result<(), DaemonError> {
+		// Final LED write; each LED restores the brightness it was opened
+		// with (off if that couldn't be read)
+		for l in std::iter::once(&mut self.led).chain(self.dir_leds.values_mut().map(|b| &mut b.led)) {
+			release_led(l.as_mut(), self.cfg.exit_led_state)?;
+		}
+		if let Some(l) = &mut self.rgb {
+			l.set_color(0, 0, 0)?;
+		}
+		
+		// Rust's Drop implementations clean up the rest:
+		// - Epoll::drop() closes epoll fd
+		// - Tfd::drop() / SigFd::drop() close the timer and signal fds
+		// - File in Led is automatically closed
+		Ok(())
+	}
+}
+
+// ============================================================================
+// TESTS: Activity detection against scripted counters
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::VecDeque;
+	
+	/// In-memory activity source: hands out queued reads in order, then
+	/// repeats the last counters (an idle device)
+	struct FakeSource {
+		reads: VecDeque<io::Result<Option<(Counts, bool)>>>,
+		last: Option<(Counts, bool)>,
+	}
+	
+	impl FakeSource {
+		/// Source yielding each (reads, writes) pair in turn
+		fn counters(pairs: &[(u128, u128)]) -> Self {
+			Self {
+				reads: pairs.iter().map(|&(r, w)| Ok(Some(((r, w, 0, 0), false)))).collect(),
+				last: None,
+			}
+		}
+	}
+	
+	impl ActivitySource for FakeSource {
+		fn read(&mut self, _scratch: &mut [u8; STAT_BUF_LEN]) -> io::Result<Option<(Counts, bool)>> {
+			match self.reads.pop_front() {
+				Some(Ok(c)) => {
+					self.last = c.map(|(counts, _)| (counts, false));
+					Ok(c)
+				}
+				Some(Err(e)) => Err(e),
+				None => Ok(self.last),
+			}
+		}
+	}
+	
+	/// LED something else can change behind our back: the next
+	/// reconcile() reports the state put in `external`
+	struct TamperedLed {
+		led: RecordingLed,
+		external: std::rc::Rc<std::cell::Cell<Option<bool>>>,
+	}
+	
+	impl LedBackend for TamperedLed {
+		fn set(&mut self, on: bool) -> io::Result<()> { self.led.set(on) }
+		fn set_level(&mut self, level: u32) -> io::Result<()> { self.led.set_level(level) }
+		fn write_raw(&mut self, raw: &str) -> io::Result<()> { self.led.write_raw(raw) }
+		fn original(&self) -> Option<u32> { self.led.original() }
+		fn max_brightness(&self) -> u32 { self.led.max_brightness() }
+		fn level(&self) -> Option<u32> { self.led.level() }
+		
+		fn reconcile(&mut self) -> io::Result<Option<bool>> {
+			let seen = self.external.take();
+			if let Some(on) = seen {
+				self.led.on = Some(on);
+			}
+			Ok(seen)
+		}
+	}
+	
+	/// Silent LED recording every write
+	fn recording(max_brightness: u32) -> RecordingLed {
+		RecordingLed { label: None, started: Instant::now(), writes: Some(Vec::new()), max_brightness, on: None, level: None }
+	}
+	
+	fn writes(led: &RecordingLed) -> Vec<&str> {
+		led.writes.iter().flatten().map(String::as_str).collect()
+	}
+	
+	fn monitor(source: FakeSource) -> Nvme {
+		monitor_with(source)
+	}
+	
+	fn monitor_with(source: impl ActivitySource + 'static) -> Nvme {
+		Nvme::new("/fake/stat", Some("fake"), NvmeMode::Sectors, Box::new(source))
+	}
+	
+	/// Poll `nvme` once per entry, collecting the reported directions
+	fn polls(nvme: &mut Nvme, n: usize) -> Vec<Option<Dir>> {
+		let mut scratch = [0u8; STAT_BUF_LEN];
+		(0..n).map(|_| nvme.activity_dir(&mut scratch).unwrap()).collect()
+	}
+	
+	#[test]
+	fn detects_direction_of_counter_edges() {
+		let mut nvme = monitor(FakeSource::counters(&[
+			(100, 100),	// baseline
+			(100, 100),	// idle
+			(108, 100),	// read
+			(108, 116),	// write
+			(120, 130),	// both: reported as write
+			(120, 130),	// idle again
+		]));
+		assert_eq!(polls(&mut nvme, 6), [
+			Some(Dir::Write), None, Some(Dir::Read), Some(Dir::Write), Some(Dir::Write), None,
+		]);
+		assert_eq!(nvme.last_delta, 0);
+	}
+	
+	#[test]
+	fn first_read_has_no_delta() {
+		let mut nvme = monitor(FakeSource::counters(&[(5000, 7000), (5004, 7000)]));
+		polls(&mut nvme, 1);
+		assert_eq!(nvme.last_delta, 0);
+		assert_eq!(nvme.ema, 0.0);
+		polls(&mut nvme, 1);
+		assert_eq!(nvme.last_delta, 4);
+	}
+	
+	#[test]
+	fn small_deltas_below_threshold_are_ignored() {
+		let mut nvme = monitor(FakeSource::counters(&[
+			(0, 0),
+			(0, 0),
+			(7, 0),		// under min_read
+			(15, 0),	// exactly min_read
+			(24, 0),	// over min_read
+			(24, 64),	// exactly min_write
+			(24, 129),	// over min_write
+		]));
+		nvme.min_read = 8;
+		nvme.min_write = 64;
+		assert_eq!(polls(&mut nvme, 7), [None, None, None, None, Some(Dir::Read), None, Some(Dir::Write)]);
+	}
+	
+	#[test]
+	fn counters_going_backwards_rebaseline() {
+		let mut nvme = monitor(FakeSource::counters(&[
+			(1_000_000, 2_000_000),
+			(1_000_008, 2_000_000),
+			(3, 5),		// device re-added: counters restarted
+			(3, 5),
+			(3, 13),
+		]));
+		assert_eq!(polls(&mut nvme, 5), [Some(Dir::Write), Some(Dir::Read), None, None, Some(Dir::Write)]);
+		assert_eq!(nvme.last_delta, 8);
+	}
+	
+	#[test]
+	fn changed_member_set_rebaselines() {
+		let mut nvme = monitor(FakeSource {
+			reads: VecDeque::from([
+				Ok(Some(((10, 10, 0, 0), false))),
+				Ok(Some(((50, 90, 0, 0), true))),	// member joined: sums jump
+				Ok(Some(((50, 90, 0, 0), false))),
+			]),
+			last: None,
+		});
+		assert_eq!(polls(&mut nvme, 3), [Some(Dir::Write), None, None]);
+	}
+	
+	#[test]
+	fn unreadable_source_counts_as_idle_after_first_read() {
+		let mut scratch = [0u8; STAT_BUF_LEN];
+		
+		// A bad path fails hard up front
+		let mut nvme = monitor(FakeSource { reads: VecDeque::from([Err(io::Error::other("gone"))]), last: None });
+		assert!(nvme.activity_dir(&mut scratch).is_err());
+		
+		// Later failures back off quietly and resume without a phantom delta
+		let mut nvme = monitor(FakeSource {
+			reads: VecDeque::from([Ok(Some(((1, 1, 0, 0), false))), Err(io::Error::other("gone"))]),
+			last: None,
+		});
+		assert_eq!(polls(&mut nvme, 2), [Some(Dir::Write), None]);
+		assert!(nvme.retry_at.is_some());
+		nvme.retry_at = Some(Instant::now());
+		nvme.source = Box::new(FakeSource::counters(&[(900, 900)]));
+		assert_eq!(polls(&mut nvme, 2), [None, None]);
+		assert!(nvme.retry_at.is_none());
+		assert_eq!(nvme.last_reads, 900);
+	}
+	
+	#[test]
+	fn in_flight_mode_follows_queue_depth() {
+		let source = FakeSource {
+			reads: VecDeque::from([
+				Ok(Some(((0, 0, 0, 0), false))),
+				Ok(Some(((4, 0, 0, 2), false))),	// reads completing, 2 queued
+				Ok(Some(((4, 0, 0, 1), false))),	// nothing completed, still queued
+				Ok(Some(((4, 0, 0, 0), false))),
+			]),
+			last: None,
+		};
+		let mut nvme = Nvme::new("/fake/stat", Some("fake"), NvmeMode::InFlight, Box::new(source));
+		assert_eq!(polls(&mut nvme, 4), [None, Some(Dir::Read), Some(Dir::Read), None]);
+	}
+	
+	#[test]
+	fn parses_indexed_stat_line() {
+		let line = "  100 0 800 5 200 0 1600 9 3 40 50 7 0 56 1";
+		assert_eq!(parse_indexed_counters(line.split_whitespace(), NvmeMode::Sectors, true), Some((800, 1600, 56, 0)));
+		assert_eq!(parse_indexed_counters(line.split_whitespace(), NvmeMode::Io, false), Some((100, 200, 0, 0)));
+		assert_eq!(parse_indexed_counters(line.split_whitespace(), NvmeMode::Busy, false), Some((100, 200, 0, 40)));
+		assert_eq!(parse_indexed_counters("1 2 3".split_whitespace(), NvmeMode::Io, false), None);
+		
+		// Seven fields are enough for reads and writes, not for in-flight
+		assert_eq!(parse_indexed_counters("1 0 8 0 2 0 16".split_whitespace(), NvmeMode::Sectors, true), Some((8, 16, 0, 0)));
+		assert_eq!(parse_indexed_counters("1 0 8 0 2 0 16".split_whitespace(), NvmeMode::InFlight, false), None);
+		// A garbled field would shift the rest, so the line is unusable
+		assert_eq!(parse_indexed_counters("100 0 800 x 200 0 1600".split_whitespace(), NvmeMode::Sectors, false), None);
+		// Flush fields and anything after them don't disturb the offsets
+		let long = format!("{} 11 22 33", line);
+		assert_eq!(parse_indexed_counters(long.split_whitespace(), NvmeMode::Sectors, true), Some((800, 1600, 56, 0)));
+	}
+	
+	#[test]
+	fn stat_offsets_match_field_names() {
+		let at = |name: &str| STAT_FIELD_NAMES.iter().position(|n| *n == name);
+		assert_eq!(at("r_ios"), Some(STAT_R_IOS));
+		assert_eq!(at("r_sectors"), Some(STAT_R_SECTORS));
+		assert_eq!(at("w_ios"), Some(STAT_W_IOS));
+		assert_eq!(at("w_sectors"), Some(STAT_W_SECTORS));
+		assert_eq!(at("in_flight"), Some(STAT_IN_FLIGHT));
+		assert_eq!(at("io_ticks"), Some(STAT_IO_TICKS));
+		assert_eq!(at("d_ios"), Some(STAT_D_IOS));
+		assert_eq!(at("d_sectors"), Some(STAT_D_SECTORS));
+	}
+	
+	#[test]
+	fn recording_led_skips_redundant_writes() {
+		let mut led = recording(1);
+		led.on().unwrap();
+		led.on().unwrap();
+		led.off().unwrap();
+		led.set_level(5).unwrap();	// clamped to max_brightness, and already lit...
+		led.set(true).unwrap();		// ...so this is a repeat
+		led.write_raw(" 0\n").unwrap();
+		led.off().unwrap();
+		assert_eq!(writes(&led), ["on", "off", "level 1", "raw 0", "off"]);
+	}
+	
+	#[test]
+	fn led_restores_the_brightness_it_was_opened_with() {
+		let path = std::env::temp_dir().join(format!("nvme-led-restore-{}", process::id()));
+		std::fs::write(&path, "3\n").unwrap();
+		let mut led = Led::new(path.to_str().unwrap(), true).unwrap();
+		assert_eq!(led.original(), Some(3));
+		led.on().unwrap();
+		led.restore().unwrap();
+		// A plain file takes the writes one after another
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n3\n");
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn led_on_writes_max_brightness() {
+		// A fake sysfs LED directory: plain files take the writes one after another
+		let dir = std::env::temp_dir().join(format!("nvme-led-max-{}", process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("brightness");
+		std::fs::write(dir.join("max_brightness"), "255\n").unwrap();
+		
+		std::fs::write(&path, "").unwrap();
+		let mut led = Led::new(path.to_str().unwrap(), true).unwrap();
+		led.on().unwrap();
+		led.off().unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "255\n0\n");
+		
+		std::fs::write(&path, "").unwrap();
+		let mut led = Led::new(path.to_str().unwrap(), false).unwrap();
+		led.on().unwrap();
+		led.off().unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "0\n255\n");
+		
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn controller_namespaces_are_found_by_name() {
+		let dir = std::env::temp_dir().join(format!("nvme-led-ctrl-{}", process::id()));
+		for name in ["nvme0n1", "nvme0n2", "nvme0n1p1", "nvme0c0n1", "nvme10n1", "sda"] {
+			std::fs::create_dir_all(dir.join(name)).unwrap();
+		}
+		let found = discover_namespaces(&dir, "nvme0");
+		let names: Vec<String> = found.iter().map(|p| device_name_from_path(p)).collect();
+		assert_eq!(names, ["nvme0n1", "nvme0n2"]);
+		assert!(is_nvme_controller("nvme0") && !is_nvme_controller("nvme0n1") && !is_nvme_controller("nvme"));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn device_names_resolve_to_block_stat() {
+		assert_eq!(stat_path_of("sda"), "/sys/block/sda/stat");
+		assert_eq!(stat_path_of("mmcblk0"), "/sys/block/mmcblk0/stat");
+		assert_eq!(stat_path_of("/tmp/activity"), "/tmp/activity");
+		assert_eq!(device_name_from_path(&stat_path_of("dm-0")), "dm-0");
+	}
+	
+	#[test]
+	fn sparkline_scales_to_the_peak() {
+		assert_eq!(sparkline(&[0.0, 1.0, 4.0, 8.0]), " ▁▄█");
+		assert_eq!(sparkline(&[0.0, 0.0]), "  ");
+	}
+	
+	#[test]
+	fn csv_rows_sum_devices_and_take_the_busiest() {
+		// [r_sectors, w_sectors, r_ios, w_ios, io_ticks] for two devices
+		let prev = [[0, 0, 0, 0, 0], [100, 0, 10, 0, 50]];
+		let now = [[8, 16, 2, 4, 100], [108, 0, 14, 0, 550]];
+		assert_eq!(csv_row(&prev, &now, 500), ([16 * 512, 16 * 512, 20], 100));
+		// A device re-added with lower counters contributes nothing
+		assert_eq!(csv_row(&now, &prev, 500), ([0, 0, 0], 0));
+	}
+	
+	#[test]
+	fn throughput_buckets_by_second() {
+		let t0 = Instant::now();
+		let mut tp = Throughput::new(t0);
+		tp.add(t0, 100, 0);
+		tp.add(t0 + Duration::from_millis(500), 100, 10);
+		assert_eq!(tp.peak, [0, 0]);  // The first second isn't over yet
+		tp.add(t0 + Duration::from_millis(1500), 50, 0);
+		tp.add(t0 + Duration::from_millis(2100), 0, 0);
+		assert_eq!(tp.peak, [200, 10]);
+		assert_eq!(tp.mean(60), [125, 5]);
+		// A long gap ages everything out without growing the ring
+		tp.add(t0 + Duration::from_secs(10_000), 0, 0);
+		assert_eq!(tp.buckets.len(), THROUGHPUT_WINDOW_S + 1);
+		assert_eq!((tp.mean(300), tp.peak), ([0, 0], [200, 10]));
+	}
+	
+	#[test]
+	fn glob_matches_device_names() {
+		assert!(glob_match("nvme*", "nvme1n1"));
+		assert!(glob_match("nvme?n1", "nvme2n1"));
+		assert!(glob_match("sd*", "sd"));
+		assert!(glob_match("*n1", "nvme10n1"));
+		assert!(!glob_match("nvme*", "sda"));
+		assert!(!glob_match("nvme?n1", "nvme10n1"));
+	}
+	
+	#[test]
+	fn uevents_pick_out_disks() {
+		let msg = |fields: &[&str]| fields.join("\0").into_bytes();
+		let add = msg(&["add@/devices/pci0000:00/nvme/nvme1/nvme1n1", "ACTION=add", "SUBSYSTEM=block",
+			"DEVTYPE=disk", "DEVNAME=nvme1n1", "SEQNUM=4711"]);
+		assert_eq!(parse_uevent(&add), Some((true, "nvme1n1".to_string())));
+		let remove = msg(&["remove@/x", "ACTION=remove", "SUBSYSTEM=block", "DEVTYPE=disk", "DEVNAME=/dev/sdb"]);
+		assert_eq!(parse_uevent(&remove), Some((false, "sdb".to_string())));
+		let partition = msg(&["add@/x", "ACTION=add", "SUBSYSTEM=block", "DEVTYPE=partition", "DEVNAME=sdb1"]);
+		assert_eq!(parse_uevent(&partition), None);
+		let change = msg(&["change@/x", "ACTION=change", "SUBSYSTEM=block", "DEVTYPE=disk", "DEVNAME=sdb"]);
+		assert_eq!(parse_uevent(&change), None);
+	}
+	
+	#[test]
+	fn daemon_errors_name_what_failed() {
+		let e = DaemonError::led_open("/sys/class/leds/x/brightness")(io::Error::from_raw_os_error(libc::ENOENT));
+		assert!(e.to_string().starts_with("opening LED /sys/class/leds/x/brightness: "));
+		assert!(std::error::Error::source(&e).is_some());
+		let e: DaemonError = io::Error::other("epoll").into();
+		assert_eq!(e.to_string(), "epoll");
+		assert_eq!(e.exit_code(), EXIT_FAILURE);
+		assert_eq!(DaemonError::stat_read("/x")(io::Error::from_raw_os_error(libc::ENOENT)).exit_code(), EXIT_NO_DEVICE);
+		assert_eq!(DaemonError::led_open("/x")(io::Error::from_raw_os_error(libc::EACCES)).exit_code(), EXIT_PERMISSION);
+		assert_eq!(device_exit_code(&io::Error::from_raw_os_error(libc::ENOENT)), EXIT_NO_DEVICE);
+		
+		// --test-led on a missing LED is an LED failure, not a generic one
+		let cfg = Config { led_path: "/nonexistent/nvme-led/brightness".to_string(), ..Config::default() };
+		assert_eq!(test_led(&cfg).map_err(|e| e.exit_code()), Err(EXIT_LED));
+	}
+	
+	#[test]
+	fn sleep_is_boottime_not_seen_by_monotonic() {
+		assert_eq!(slept_ms((1000, 500), (1010, 510)), 0);
+		assert_eq!(slept_ms((1000, 500), (61_010, 510)), 60_000);
+		// A clock read slightly out of order never underflows
+		assert_eq!(slept_ms((1000, 500), (1009, 510)), 0);
+	}
+	
+	#[test]
+	fn pattern_alternates_and_ends_off() {
+		let mut led = recording(1);
+		let mut pattern = Pattern::new().unwrap();
+		let mut tbuf = [0u8; 8];
+		pattern.start(&[50, 50, 50], &mut led).unwrap();
+		assert!(pattern.playing());
+		for _ in 0..3 {
+			pattern.advance(&mut led, &mut tbuf).unwrap();
+		}
+		assert!(!pattern.playing());
+		assert_eq!(writes(&led), ["on", "off", "on", "off"]);
+	}
+	
+	#[test]
+	fn fade_steps_down_to_zero() {
+		let mut led = recording(255);
+		let mut fade = Fade::new(FadeCurve::Linear).unwrap();
+		let mut tbuf = [0u8; 8];
+		fade.start(100, FADE_STEP_MS * 4).unwrap();
+		while fade.active() {
+			fade.tick(&mut led, &mut tbuf).unwrap();
+		}
+		assert_eq!(writes(&led), ["level 75", "level 50", "level 25", "level 0"]);
+	}
+	
+	#[test]
+	fn startup_blink_leaves_led_off() {
+		let mut led = recording(1);
+		startup_blink(&mut led).unwrap();
+		assert_eq!(writes(&led).len(), STARTUP_BLINKS as usize * 2);
+		assert_eq!(led.on, Some(false));
+		
+		let mut led = recording(4);
+		startup_blink(&mut led).unwrap();
+		assert_eq!(writes(&led).first(), Some(&"level 0"));
+		assert_eq!(led.level, Some(0));
+	}
+	
+	#[test]
+	fn replay_feeds_captured_fields_through_the_parser() {
+		let samples = [(0, "1 0 10 0 1 0 10 0 0 0 0"), (300, "2 0 20 0 1 0 10 0 0 0 0"), (500, "2 0 20 0 5 0 90 0 0 0 0")];
+		let source = ReplaySource {
+			samples: samples.iter().map(|&(t, f)| (t, f.to_string())).collect(),
+			pos: 0,
+			started: None,
+			fast: true,
+			mode: NvmeMode::Sectors,
+			discards: false,
+		};
+		let mut nvme = monitor_with(source);
+		assert_eq!(polls(&mut nvme, 4), [Some(Dir::Write), Some(Dir::Read), Some(Dir::Write), None]);
+		assert!(nvme.source.finished());
+	}
+	
+	#[test]
+	fn named_fields_come_out_in_kernel_order() {
+		let fields: Vec<u64> = named_fields("# comment\nw_sectors r_ios in_flight\n70 3 1\n").collect();
+		assert_eq!(fields.len(), STAT_FIELD_NAMES.len());
+		assert_eq!((fields[0], fields[6], fields[8], fields[2]), (3, 70, 1, 0));
+	}
+	
+	#[test]
+	fn journal_fields_frame_multiline_values() {
+		let mut out = Vec::new();
+		journal_field(&mut out, "LED_STATE", "on");
+		journal_field(&mut out, "MESSAGE", "a\nb");
+		let mut want = b"LED_STATE=on\nMESSAGE\n".to_vec();
+		want.extend_from_slice(&3u64.to_le_bytes());
+		want.extend_from_slice(b"a\nb\n");
+		assert_eq!(out, want);
+	}
+	
+	#[test]
+	fn min_on_holds_only_young_blinks() {
+		let now = Instant::now();
+		let lit = now - Duration::from_millis(10);
+		assert_eq!(min_on_left(Some(lit), 40, now), Some(30));
+		assert_eq!(min_on_left(Some(lit), 10, now), None);
+		assert_eq!(min_on_left(Some(lit), 0, now), None);
+		assert_eq!(min_on_left(None, 40, now), None);
+	}
+	
+	#[test]
+	fn hysteresis_holds_small_level_changes() {
+		assert_eq!(with_hysteresis(Some(100), 104, 5), 100);
+		assert_eq!(with_hysteresis(Some(100), 94, 5), 94);
+		assert_eq!(with_hysteresis(Some(0), 3, 5), 3);
+		assert_eq!(with_hysteresis(None, 3, 5), 3);
+		assert_eq!(with_hysteresis(Some(100), 101, 0), 101);
+	}
+	
+	#[test]
+	fn epoll_wait_survives_a_signal() {
+		extern "C" fn ignore(_: libc::c_int) {}
+		unsafe {
+			let mut sa: libc::sigaction = std::mem::zeroed();
+			sa.sa_sigaction = ignore as *const () as usize;
+			libc::sigaction(libc::SIGUSR2, &sa, std::ptr::null_mut());
+		}
+		let ep = Epoll::new().unwrap();
+		let t = Tfd::oneshot().unwrap();
+		t.arm_after_ms(300).unwrap();
+		ep.add_fd(t.0, 1, libc::EPOLLIN as u32).unwrap();
+		
+		// Interrupt the wait from another thread, well before the timer
+		let me = unsafe { libc::pthread_self() };
+		let kicker = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(50));
+			unsafe { libc::pthread_kill(me, libc::SIGUSR2) };
+		});
+		let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+		assert_eq!(ep.wait(&mut events).unwrap(), 0);
+		kicker.join().unwrap();
+		
+		// The loop carries on and sees the timer as usual
+		assert_eq!(ep.wait(&mut events).unwrap(), 1);
+		assert_eq!({ events[0].u64 }, 1);
+	}
+	
+	#[test]
+	fn engine_steps_through_a_blink() {
+		let path = std::env::temp_dir().join(format!("nvme-led-engine-{}", process::id()));
+		let line = |sectors: u64| format!("1 0 {} 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n", sectors);
+		std::fs::write(&path, line(8)).unwrap();
+		let cfg = Config { nvme_path: path.to_string_lossy().into_owned(), dry_run: true, ..Config::default() };
+		let mut engine = Engine::new(cfg).unwrap();
+		let poll = |e: &mut Engine| {
+			let polls = e.stats.total.polls;
+			while e.stats.total.polls == polls {
+				assert!(e.step(1000).unwrap());
+			}
+		};
+		
+		// Settle after the first poll (counted against zero)
+		poll(&mut engine);
+		while engine.led_on {
+			assert!(engine.step(1000).unwrap());
+		}
+		let blinks = engine.stats.total.blinks;
+		
+		// New sectors light the LED; idle polls after them leave it to the
+		// off timer
+		std::fs::write(&path, line(16)).unwrap();
+		poll(&mut engine);
+		assert!(engine.led_on);
+		assert_eq!(engine.stats.total.blinks, blinks + 1);
+		while engine.led_on {
+			assert!(engine.step(1000).unwrap());
+		}
+		poll(&mut engine);
+		assert!(!engine.led_on);
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn long_stat_lines_are_read_whole() {
+		// Wide padding pushes the write sectors field across the buffer end
+		let pad = " ".repeat(83);
+		let fields = ["1", "0", "8", "0", "2", "0", "123456789012345", "0", "0", "0", "0", "0", "0", "0", "0", "0", "0"];
+		let line = fields.join(&pad) + "\n";
+		assert!(line.len() > STAT_BUF_LEN && line.find("123456789012345").is_some_and(|i| i < STAT_BUF_LEN && i + 15 > STAT_BUF_LEN));
+		let path = std::env::temp_dir().join(format!("nvme-led-long-stat-{}", process::id()));
+		std::fs::write(&path, line).unwrap();
+		let source = SysfsSource::new(path.to_str().unwrap(), "long", NvmeMode::Sectors);
+		let counts = source.read_counters(path.to_str().unwrap(), &mut [0u8; STAT_BUF_LEN]).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(counts.map(|(r, w, ..)| (r, w)), Some((8, 123456789012345)));
+	}
+	
+	#[test]
+	fn durations_and_sizes_take_units() {
+		assert_eq!(parse_ms("250"), Some(250));
+		assert_eq!(parse_ms("250ms"), Some(250));
+		assert_eq!(parse_ms("1.5s"), Some(1500));
+		assert_eq!(parse_ms(" 2m "), Some(120_000));
+		assert_eq!(parse_ms("5h"), None);
+		assert_eq!(parse_ms("ms"), None);
+		assert_eq!(parse_bytes("4096"), Some(4096));
+		assert_eq!(parse_bytes("4MB"), Some(4_000_000));
+		assert_eq!(parse_bytes("4 KiB"), Some(4096));
+		assert_eq!(parse_bytes("1gib"), Some(1 << 30));
+		assert_eq!(parse_bytes("4MBs"), None);
+		assert_eq!(parse_mb_s("500"), Some(500));
+		assert_eq!(parse_mb_s("500MB/s"), Some(500));
+		assert_eq!(parse_mb_s("1.2GB/s"), Some(1200));
+		assert_eq!(parse_mb_s("500MB"), None);
+	}
+	
+	#[test]
+	fn mixed_polls_follow_mixed_priority() {
+		let source = FakeSource {
+			reads: VecDeque::from([
+				Ok(Some(((0, 0, 0, 0), false))),
+				Ok(Some(((5, 5, 0, 0), false))),	// both moved
+				Ok(Some(((5, 9, 0, 0), false))),	// writes only
+			]),
+			last: None,
+		};
+		let mut nvmes = [Nvme::new("/fake/stat", Some("fake"), NvmeMode::Sectors, Box::new(source))];
+		let mut scratch = [0u8; STAT_BUF_LEN];
+		let dirs: Vec<_> = (0..3).map(|_| poll_monitors(&mut nvmes, &mut scratch, Dir::Read).unwrap().0).collect();
+		assert_eq!(dirs, [None, Some(Dir::Read), Some(Dir::Write)]);
+		
+		let mut cfg = Config { mixed_priority: MixedPriority::Longer, read_blink_ms: Some(50), ..Config::default() };
+		assert_eq!(mixed_dir(&cfg), Dir::Read);
+		cfg.mixed_priority = MixedPriority::Shorter;
+		assert_eq!(mixed_dir(&cfg), Dir::Write);
+		cfg.read_blink_ms = None;
+		assert_eq!(mixed_dir(&cfg), Dir::Write);
+	}
+	
+	#[test]
+	fn reload_with_bad_config_keeps_the_old_one() {
+		let dir = std::env::temp_dir().join(format!("nvme-led-reload-{}", process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let stat = dir.join("stat");
+		std::fs::write(&stat, "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		let conf = dir.join("conf");
+		let layers = |body: &str| {
+			std::fs::write(&conf, body).unwrap();
+			let args = ["--nvme", stat.to_str().unwrap(), "--dry-run", "--config", conf.to_str().unwrap()];
+			config_from_layers(args.iter().map(|a| a.to_string()), &HashMap::new())
+		};
+		let mut engine = Engine::new(layers("interval_ms=25\n").unwrap()).unwrap();
+		
+		// Each of these used to exit the daemon from inside the reload
+		let missing = config_from_layers(["--config", "/nonexistent/nvme-led.conf"].iter().map(|a| a.to_string()), &HashMap::new());
+		for bad in [missing, layers("interval_ms=50\nmax_devices=0\n"), layers("interval_ms=50\nred_path=/x\n")] {
+			assert!(bad.is_err());
+			engine.reload(bad).unwrap();
+			assert_eq!((engine.cfg.poll_ms, engine.cur_poll_ms), (25, 25));
+		}
+		
+		engine.reload(layers("interval_ms=50\n")).unwrap();
+		assert_eq!((engine.cfg.poll_ms, engine.cur_poll_ms), (50, 50));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn decide_follows_the_blink_policy() {
+		let quiet = BlinkState { in_grace: false, idle_for: None, pattern_playing: false, dark_for: None };
+		let cfg = Config { blink_ms: 10, read_blink_ms: Some(30), write_blink_ms: Some(40), discard_blink_ms: Some(50), ..Config::default() };
+		assert_eq!(decide(&cfg, None, quiet), Action::Leave);
+		
+		// on_fields x direction: the duration is the direction's own
+		let (r, w, d) = (Action::Blink(30), Action::Blink(40), Action::Blink(50));
+		let l = Action::Leave;
+		for (on_fields, want) in [
+			(FieldsSel::Reads, [r, l, l]),
+			(FieldsSel::Writes, [l, w, l]),
+			(FieldsSel::Both, [r, w, l]),
+			(FieldsSel::Discards, [l, l, d]),
+			(FieldsSel::All, [r, w, d]),
+		] {
+			let c = Config { on_fields, ..cfg.clone() };
+			let got = [Dir::Read, Dir::Write, Dir::Discard].map(|dir| decide(&c, Some(dir), quiet));
+			assert_eq!(got, want, "{:?}", on_fields);
+		}
+		
+		// Grace and a playing pattern hold every blink back
+		let cfg = Config { on_fields: FieldsSel::All, ..cfg };
+		assert_eq!(decide(&cfg, Some(Dir::Write), BlinkState { in_grace: true, ..quiet }), Action::Leave);
+		assert_eq!(decide(&cfg, Some(Dir::Write), BlinkState { pattern_playing: true, ..quiet }), Action::Leave);
+		
+		// Edge-only: blink after idle_ms of quiet (or the first time), not
+		// while activity continues; off, every poll blinks
+		let ms = Duration::from_millis;
+		let edge = Config { edge_only: true, idle_ms: 100, ..cfg.clone() };
+		for (c, idle_for, want) in [
+			(&edge, None, w),
+			(&edge, Some(ms(99)), l),
+			(&edge, Some(ms(100)), w),
+			(&cfg, Some(ms(1)), w),
+		] {
+			assert_eq!(decide(c, Some(Dir::Write), BlinkState { idle_for, ..quiet }), want, "{:?}", idle_for);
+		}
+		
+		// min_off_ms keeps a just-darkened LED dark
+		let anti = Config { min_off_ms: 50, ..cfg.clone() };
+		for (dark_for, want) in [(None, w), (Some(ms(49)), l), (Some(ms(50)), w)] {
+			assert_eq!(decide(&anti, Some(Dir::Write), BlinkState { dark_for, ..quiet }), want, "{:?}", dark_for);
+		}
+		
+		// Level modes hold the LED at least until the next poll
+		for (nvme_mode, poll_ms, want) in [
+			(NvmeMode::InFlight, 100, Action::Blink(100)),
+			(NvmeMode::Busy, 100, Action::Blink(100)),
+			(NvmeMode::Busy, 20, w),
+			(NvmeMode::Sectors, 100, w),
+		] {
+			let c = Config { nvme_mode, poll_ms, ..cfg.clone() };
+			assert_eq!(decide(&c, Some(Dir::Write), quiet), want, "{:?}", nvme_mode);
+		}
+	}
+	
+	#[test]
+	fn decide_off_waits_out_min_on() {
+		let now = Instant::now();
+		let cfg = Config { min_on_ms: 40, ..Config::default() };
+		assert_eq!(decide_off(&cfg, false, Some(now), now), Action::Leave);
+		assert_eq!(decide_off(&cfg, true, Some(now - Duration::from_millis(10)), now), Action::Blink(30));
+		assert_eq!(decide_off(&cfg, true, Some(now - Duration::from_millis(40)), now), Action::Off);
+		assert_eq!(decide_off(&Config::default(), true, Some(now), now), Action::Off);
+	}
+	
+	#[test]
+	fn out_of_band_led_change_is_rewritten() {
+		let stat = std::env::temp_dir().join(format!("nvme-led-reconcile-{}", process::id()));
+		let line = |sectors: u64| format!("1 0 {} 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n", sectors);
+		std::fs::write(&stat, line(8)).unwrap();
+		let cfg = Config {
+			nvme_path: stat.to_string_lossy().into_owned(),
+			dry_run: true,
+			reconcile_ms: 5,
+			blink_ms: 60_000,
+			..Config::default()
+		};
+		let mut engine = Engine::new(cfg).unwrap();
+		let external = std::rc::Rc::new(std::cell::Cell::new(None));
+		engine.led = Box::new(TamperedLed { led: recording(1), external: external.clone() });
+		let poll = |e: &mut Engine| {
+			let polls = e.stats.total.polls;
+			while e.stats.total.polls == polls {
+				assert!(e.step(1000).unwrap());
+			}
+		};
+		
+		// The first poll lights the LED for a long blink
+		poll(&mut engine);
+		assert!(engine.led_on);
+		
+		// Something else turns it off; the next reconcile notices
+		external.set(Some(false));
+		while external.get().is_some() {
+			assert!(engine.step(1000).unwrap());
+		}
+		assert!(!engine.led_on);
+		
+		// ...so new activity lights it again
+		let blinks = engine.stats.total.blinks;
+		std::fs::write(&stat, line(16)).unwrap();
+		poll(&mut engine);
+		assert!(engine.led_on);
+		assert_eq!(engine.stats.total.blinks, blinks + 1);
+		std::fs::remove_file(&stat).unwrap();
+	}
+	
+	#[test]
+	fn emptied_pidfile_is_stale() {
+		let path = std::env::temp_dir().join(format!("nvme-led-pidfile-{}", process::id()));
+		let path = path.to_str().unwrap();
+		let f = create_pidfile(path).unwrap();
+		assert_eq!(std::fs::read_to_string(path).unwrap(), format!("{}\n", process::id()));
+		assert!(create_pidfile(path).is_err());	// we're still running
+		
+		// What remove_pidfile() leaves when it can't unlink
+		f.set_len(0).unwrap();
+		let f = create_pidfile(path).unwrap();
+		remove_pidfile(path, &f);
+		assert!(!std::path::Path::new(path).exists());
+	}
+	
+	#[test]
+	fn environment_overrides_config_files_and_flags_override_both() {
+		let conf = std::env::temp_dir().join(format!("nvme-led-layers-{}", process::id()));
+		std::fs::write(&conf, "interval_ms=30\nblink_ms=30\nidle_ms=30\n").unwrap();
+		let env_map = HashMap::from([("interval_ms".to_string(), "40".to_string()), ("blink_ms".to_string(), "40".to_string())]);
+		// --config last still sits below the environment and the flag before it
+		let args = ["--blink-ms", "50", "--config", conf.to_str().unwrap()].map(String::from);
+		let cfg = config_from_layers(args.into_iter(), &env_map).unwrap();
+		std::fs::remove_file(&conf).unwrap();
+		assert_eq!((cfg.idle_ms, cfg.poll_ms, cfg.blink_ms), (30, 40, 50));
+		assert_eq!(cfg.config_path.as_deref(), conf.to_str());
+	}
+	
+	#[test]
+	fn configured_names_label_the_output() {
+		let dir = std::env::temp_dir().join(format!("nvme-led-names-{}", process::id()));
+		for dev in ["nvme0n1", "nvme1n1"] {
+			std::fs::create_dir_all(dir.join(dev)).unwrap();
+			std::fs::write(dir.join(dev).join("stat"), "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		}
+		// Only the first is named; the second falls back to its device node
+		let cfg = Config {
+			nvme_path: format!("{0}/nvme0n1/stat,{0}/nvme1n1/stat", dir.display()),
+			nvme_name: Some("boot".to_string()),
+			..Config::default()
+		};
+		let nvmes = open_monitors(&cfg).unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		let label = monitors_label(&nvmes);
+		assert_eq!(label, "boot+nvme1n1");
+		let metrics = render_metrics(&label, &Counters::default(), &Throughput::new(Instant::now()), false);
+		assert!(metrics.contains("nvme_led_polls_total{device=\"boot+nvme1n1\"} 0\n"));
+	}
+	
+	#[test]
+	fn led_off_writes_only_the_off_value() {
+		// A freshly opened LED has no cached state, so off() can't be skipped
+		let mut led = recording(1);
+		led.off().unwrap();
+		assert_eq!(writes(&led), ["off"]);
+		
+		// --led-off through run(): one write, in the LED's polarity
+		let path = std::env::temp_dir().join(format!("nvme-led-off-{}", process::id()));
+		for (active_high, want) in [(true, "0\n"), (false, "1\n")] {
+			std::fs::write(&path, "").unwrap();
+			let cfg = Config { led_path: path.to_string_lossy().into_owned(), active_high, led_off: true, ..Config::default() };
+			run(&cfg).unwrap();
+			assert_eq!(std::fs::read_to_string(&path).unwrap(), want);
+		}
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn grace_period_tracks_without_blinking() {
+		let path = std::env::temp_dir().join(format!("nvme-led-grace-{}", process::id()));
+		let line = |sectors: u64| format!("1 0 {} 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n", sectors);
+		std::fs::write(&path, line(8)).unwrap();
+		let cfg = Config { nvme_path: path.to_string_lossy().into_owned(), dry_run: true, startup_grace_ms: 60_000, ..Config::default() };
+		let mut engine = Engine::new(cfg).unwrap();
+		let poll = |e: &mut Engine| {
+			let polls = e.stats.total.polls;
+			while e.stats.total.polls == polls {
+				assert!(e.step(1000).unwrap());
+			}
+		};
+		
+		// Boot-time activity inside the window: counted, not shown
+		poll(&mut engine);
+		std::fs::write(&path, line(16)).unwrap();
+		poll(&mut engine);
+		assert_eq!((engine.stats.total.blinks, engine.led_on), (0, false));
+		assert_eq!(engine.nvmes[0].last_reads, 16);
+		
+		// Once it's over, that activity isn't replayed; new activity blinks
+		engine.grace_until = Instant::now();
+		poll(&mut engine);
+		assert_eq!(engine.stats.total.blinks, 0);
+		std::fs::write(&path, line(24)).unwrap();
+		poll(&mut engine);
+		assert_eq!(engine.stats.total.blinks, 1);
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn md_members_are_summed() {
+		let md = std::env::temp_dir().join(format!("nvme-led-md-{}", process::id())).join("md0");
+		let line = |r: u64, w: u64| format!("1 0 {} 0 1 0 {} 0 0 0 0 0 0 0 0 0 0\n", r, w);
+		std::fs::create_dir_all(&md).unwrap();
+		std::fs::write(md.join("stat"), line(1000, 1000)).unwrap();
+		for (dev, r, w) in [("sda", 8, 16), ("sdb", 24, 32)] {
+			std::fs::create_dir_all(md.join("slaves").join(dev)).unwrap();
+			std::fs::write(md.join("slaves").join(dev).join("stat"), line(r, w)).unwrap();
+		}
+		let mut src = SysfsSource::new(md.join("stat").to_str().unwrap(), "md0", NvmeMode::Sectors);
+		assert_eq!(src.aggregate_members(DEFAULT_MAX_DEVICES).unwrap(), 2);
+		let mut sbuf = [0u8; STAT_BUF_LEN];
+		assert_eq!(src.read(&mut sbuf).unwrap(), Some(((32, 48, 0, 0), false)));
+		
+		// A spare joining changes the sums' basis
+		std::fs::create_dir_all(md.join("slaves/sdc")).unwrap();
+		std::fs::write(md.join("slaves/sdc/stat"), line(1, 1)).unwrap();
+		src.members_scanned = None;
+		assert_eq!(src.read(&mut sbuf).unwrap(), Some(((33, 49, 0, 0), true)));
+		std::fs::remove_dir_all(md.parent().unwrap()).unwrap();
+	}
+	
+	#[test]
+	fn last_of_quiet_and_verbose_wins() {
+		let level = |args: &[&str]| config_from_layers(args.iter().map(|a| a.to_string()), &HashMap::new()).unwrap().log_level;
+		assert_eq!(level(&[]), Level::Info);
+		assert_eq!(level(&["--quiet"]), Level::Error);
+		assert_eq!(level(&["--verbose"]), Level::Debug);
+		assert_eq!(level(&["--quiet", "--verbose"]), Level::Debug);
+		assert_eq!(level(&["--verbose", "--quiet"]), Level::Error);
+		
+		// quiet=true from the file or environment layers is overridden too
+		let env_map = HashMap::from([("quiet".to_string(), "true".to_string())]);
+		assert_eq!(config_from_layers(std::iter::empty(), &env_map).unwrap().log_level, Level::Error);
+		assert_eq!(config_from_layers(std::iter::once("-v".to_string()), &env_map).unwrap().log_level, Level::Debug);
+	}
+	
+	#[test]
+	fn aligned_polls_expire_on_interval_boundaries() {
+		assert_eq!(next_aligned_ns(1_234, 1_000), 2_000);
+		assert_eq!(next_aligned_ns(2_000, 1_000), 3_000);	// strictly after
+		
+		// The armed timer's absolute expiration: now + what's left, bracketed
+		// by a clock read on either side of timerfd_gettime
+		let interval_ns = 250_000_000u64;
+		let now_ns = || {
+			let mut t = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+			unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut t) };
+			t.tv_sec as u64 * 1_000_000_000 + t.tv_nsec as u64
+		};
+		let tfd = Tfd::periodic_on(libc::CLOCK_MONOTONIC, interval_ns / 1_000_000, true).unwrap();
+		let mut cur: libc::itimerspec = unsafe { std::mem::zeroed() };
+		let before = now_ns();
+		assert_eq!(unsafe { libc::timerfd_gettime(tfd.0, &mut cur) }, 0);
+		let after = now_ns();
+		let left = cur.it_value.tv_sec as u64 * 1_000_000_000 + cur.it_value.tv_nsec as u64;
+		let (lo, hi) = (before + left, after + left);
+		assert!(lo.div_ceil(interval_ns) * interval_ns <= hi, "expires in [{}, {}]", lo, hi);
+	}
+	
+	#[test]
+	fn member_discovery_is_capped_at_max_devices() {
+		let mut src = SysfsSource::new("/sys/block/md0/stat", "md0", NvmeMode::Sectors);
+		src.max_members = 2;
+		let found = |n: usize| (0..n).map(|i| format!("/sys/block/sd{}/stat", i)).collect::<Vec<_>>();
+		
+		// Over the cap: truncated, and `capped` marks the warning as given so
+		// later scans over the cap don't repeat it
+		assert_eq!(src.capped_members(found(3)), found(2));
+		assert!(src.capped);
+		assert_eq!(src.capped_members(found(4)), found(2));
+		assert!(src.capped);
+		
+		// Back under it, the next time over warns again
+		assert_eq!(src.capped_members(found(2)), found(2));
+		assert!(!src.capped);
+	}
+	
+	#[test]
+	fn mount_changes_play_the_pattern_throttled() {
+		use std::os::unix::io::AsRawFd;
+		let path = std::env::temp_dir().join(format!("nvme-led-mounts-{}", process::id()));
+		std::fs::write(&path, "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		let cfg = Config {
+			nvme_path: path.to_string_lossy().into_owned(),
+			dry_run: true,
+			mount_blink: true,
+			mount_pattern: vec![20, 20],
+			mount_throttle_ms: 60_000,
+			..Config::default()
+		};
+		let mut engine = Engine::new(cfg).unwrap();
+		
+		// Stand-in for the mounts file signalling: a socket that stays
+		// readable, so every step sees another "change"
+		let (tx, rx) = std::os::unix::net::UnixStream::pair().unwrap();
+		engine.register(rx.as_raw_fd(), MOUNTS_TAG, libc::EPOLLIN as u32).unwrap();
+		(&tx).write_all(b"x").unwrap();
+		
+		// The first change starts the pattern...
+		assert!(engine.step(1000).unwrap());
+		assert!(engine.pattern.playing());
+		
+		// ...which then runs out while the changes keep coming, without a
+		// restart inside mount_throttle_ms
+		let until = Instant::now() + Duration::from_secs(5);
+		while engine.pattern.playing() && Instant::now() < until {
+			assert!(engine.step(1000).unwrap());
+		}
+		assert!(!engine.pattern.playing());
+		for _ in 0..10 {
+			assert!(engine.step(1000).unwrap());
+			assert!(!engine.pattern.playing());
+		}
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn enclosure_slots_resolve_to_their_led_file() {
+		// Two enclosures; component directories carry a `slot` number
+		let root = std::env::temp_dir().join(format!("nvme-led-enclosure-{}", process::id()));
+		for (enc, comp, slot) in [("0:0:1:0", "Slot 00", 0), ("0:0:1:0", "Slot 01", 1), ("0:0:2:0", "Disk7", 7)] {
+			let dir = root.join(enc).join(comp);
+			std::fs::create_dir_all(&dir).unwrap();
+			std::fs::write(dir.join("slot"), format!("{}\n", slot)).unwrap();
+			std::fs::write(dir.join("locate"), "0\n").unwrap();
+		}
+		std::fs::write(root.join("0:0:1:0").join("Slot 01").join("fault"), "0\n").unwrap();
+		let resolve = |spec: &str, attr: &str| resolve_enclosure_slot(&root, spec, attr);
+		let led = |enc: &str, comp: &str, attr: &str| root.join(enc).join(comp).join(attr).to_string_lossy().into_owned();
+		
+		assert_eq!(resolve("1", "fault").unwrap(), led("0:0:1:0", "Slot 01", "fault"));
+		assert_eq!(resolve("7", "locate").unwrap(), led("0:0:2:0", "Disk7", "locate"));
+		assert_eq!(resolve("0:0:1:0/Slot 00", "locate").unwrap(), led("0:0:1:0", "Slot 00", "locate"));
+		assert_eq!(resolve("Disk7", "locate").unwrap(), led("0:0:2:0", "Disk7", "locate"));
+		
+		// Wrong enclosure, unknown slot, missing attribute
+		for (spec, attr) in [("0:0:2:0/1", "locate"), ("9", "locate"), ("0", "fault")] {
+			assert_eq!(resolve(spec, attr).unwrap_err().kind(), io::ErrorKind::NotFound, "{}", spec);
+		}
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+	
+	#[test]
+	fn edge_only_blinks_once_per_burst() {
+		let cfg = Config { edge_only: true, idle_ms: 100, ..Config::default() };
+		let quiet = BlinkState { in_grace: false, idle_for: None, pattern_playing: false, dark_for: None };
+		
+		// Activity every 10ms for half a second, a 150ms gap, then more:
+		// idle_for is measured from the previous active poll, as in the loop
+		let mut last: Option<u64> = None;
+		let mut blinks_at = Vec::new();
+		for t in (0..=500).step_by(10).chain((650..=700).step_by(10)) {
+			let idle_for = last.map(|l| Duration::from_millis(t - l));
+			if let Action::Blink(_) = decide(&cfg, Some(Dir::Write), BlinkState { idle_for, ..quiet }) {
+				blinks_at.push(t);
+			}
+			last = Some(t);
+		}
+		assert_eq!(blinks_at, [0, 650]);
+	}
+	
+	#[test]
+	fn poll_and_off_in_one_batch_keep_the_led_on() {
+		let path = std::env::temp_dir().join(format!("nvme-led-batch-{}", process::id()));
+		let line = |sectors: u64| format!("1 0 {} 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n", sectors);
+		std::fs::write(&path, line(8)).unwrap();
+		let cfg = Config { nvme_path: path.to_string_lossy().into_owned(), dry_run: true, poll_ms: 50, blink_ms: 20, ..Config::default() };
+		let mut engine = Engine::new(cfg).unwrap();
+		
+		// The first poll lights the LED for 20ms
+		while engine.stats.total.polls == 0 {
+			assert!(engine.step(1000).unwrap());
+		}
+		assert!(engine.led_on);
+		let blinks = engine.stats.total.blinks;
+		
+		// More activity, and by the time we look both the off-timer and the
+		// next poll are due: the poll extends the blink, the off is stale
+		std::fs::write(&path, line(16)).unwrap();
+		std::thread::sleep(Duration::from_millis(70));
+		assert!(engine.step(0).unwrap());
+		assert_eq!(engine.stats.total.polls, 2);
+		assert!(engine.led_on);
+		assert_eq!(engine.stats.total.blinks, blinks);
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn summary_intervals_reset_while_totals_keep_counting() {
+		let mut stats = Stats::default();
+		let poll = |stats: &mut Stats, bytes: u128, peak: u128| stats.bump(|c| {
+			c.polls += 1;
+			c.bytes_read += bytes;
+			c.peak_delta = c.peak_delta.max(peak);
+		});
+		poll(&mut stats, 512, 1);
+		poll(&mut stats, 1024, 2);
+		let first = stats.take_interval();
+		assert_eq!((first.polls, first.bytes_read, first.peak_delta), (2, 1536, 2));
+		
+		// The next interval starts from zero, peak included
+		poll(&mut stats, 4096, 1);
+		let second = stats.take_interval();
+		assert_eq!((second.polls, second.bytes_read, second.peak_delta), (1, 4096, 1));
+		assert_eq!(stats.take_interval().polls, 0);
+		let t = stats.total;
+		assert_eq!((t.polls, t.bytes_read, t.peak_delta), (3, 5632, 2));
+	}
+	
+	#[test]
+	fn hw_changed_notification_reconciles_the_led() {
+		use std::os::unix::io::AsRawFd;
+		let path = std::env::temp_dir().join(format!("nvme-led-hw-changed-{}", process::id()));
+		std::fs::write(&path, "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		// No periodic reconcile: only the notification can trigger one
+		let cfg = Config { nvme_path: path.to_string_lossy().into_owned(), dry_run: true, blink_ms: 60_000, ..Config::default() };
+		let mut engine = Engine::new(cfg).unwrap();
+		assert!(engine.reconcile_tfd.is_none());
+		let external = std::rc::Rc::new(std::cell::Cell::new(None));
+		engine.led = Box::new(TamperedLed { led: recording(1), external: external.clone() });
+		while engine.stats.total.polls == 0 {
+			assert!(engine.step(1000).unwrap());
+		}
+		assert!(engine.led_on);
+		
+		// Firmware turns the LED off and brightness_hw_changed signals
+		// (a socket stands in for it, registered the same way)
+		let (tx, rx) = std::os::unix::net::UnixStream::pair().unwrap();
+		engine.register(rx.as_raw_fd(), HW_CHANGED_TAG, libc::EPOLLIN as u32).unwrap();
+		external.set(Some(false));
+		(&tx).write_all(b"x").unwrap();
+		assert!(engine.step(1000).unwrap());
+		assert_eq!(external.get(), None);
+		assert!(!engine.led_on);
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn parses_header_named_counters() {
+		// Columns in any order, with a comment and blank line before them
+		let src = "# wrapped activity file\n\nw_ios d_sectors in_flight r_sectors w_sectors r_ios io_ticks\n5 9 2 100 200 3 40\n";
+		assert_eq!(parse_named_counters(src, NvmeMode::Sectors, false), Some((100, 200, 0, 0)));
+		assert_eq!(parse_named_counters(src, NvmeMode::Sectors, true), Some((100, 200, 9, 0)));
+		assert_eq!(parse_named_counters(src, NvmeMode::Io, true), Some((3, 5, 0, 0)));	// no d_ios column
+		assert_eq!(parse_named_counters(src, NvmeMode::InFlight, false), Some((3, 5, 0, 2)));
+		assert_eq!(parse_named_counters(src, NvmeMode::Busy, false), Some((3, 5, 0, 40)));
+		
+		// A missing or non-numeric read/write column, or no values line
+		assert_eq!(parse_named_counters("r_sectors\n100\n", NvmeMode::Sectors, false), None);
+		assert_eq!(parse_named_counters("r_sectors w_sectors\n100 x\n", NvmeMode::Sectors, false), None);
+		assert_eq!(parse_named_counters("r_sectors w_sectors\n", NvmeMode::Sectors, false), None);
+		assert_eq!(parse_named_counters("r_ios w_ios\n1 2\n", NvmeMode::InFlight, false), None);
+	}
+	
+	#[test]
+	fn metrics_file_parses_as_openmetrics() {
+		let path = std::env::temp_dir().join(format!("nvme-led-metrics-{}.prom", process::id()));
+		let path = path.to_str().unwrap();
+		let c = Counters { polls: 7, blinks: 3, reads: 2, writes: 4, bytes_read: 4096, ..Counters::default() };
+		write_metrics(path, "nvme0n1", &c, &Throughput::new(Instant::now()), true).unwrap();
+		let text = std::fs::read_to_string(path).unwrap();
+		std::fs::remove_file(path).unwrap();
+		assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+		
+		// Every line is TYPE, HELP, a sample of a declared family, or the
+		// final EOF
+		let mut types = HashMap::new();
+		let mut samples = HashMap::new();
+		let lines: Vec<&str> = text.lines().collect();
+		assert_eq!(lines.last(), Some(&"# EOF"));
+		for line in &lines[..lines.len() - 1] {
+			match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+				["#", "TYPE", name, kind] => { types.insert(name.to_string(), kind.to_string()); }
+				["#", "HELP", _, ..] => {}
+				[sample, value] => {
+					let (name, labels) = sample.split_once('{').unwrap();
+					assert_eq!(labels, "device=\"nvme0n1\"}");
+					let family = name.strip_suffix("_total").filter(|f| types.get(*f).is_some_and(|k| k == "counter")).unwrap_or(name);
+					assert!(types.contains_key(family), "{} has no TYPE", name);
+					samples.insert(name.to_string(), value.parse::<u128>().unwrap());
+				}
+				_ => panic!("unexpected line {:?}", line),
+			}
+		}
+		for (name, value) in [
+			("nvme_led_polls_total", 7), ("nvme_led_blinks_total", 3), ("nvme_led_read_polls_total", 2),
+			("nvme_led_write_polls_total", 4), ("nvme_led_bytes_read_total", 4096), ("nvme_led_errors_total", 0),
+			("nvme_led_led_on", 1), ("nvme_led_read_bytes_per_second_1m", 0),
+		] {
+			assert_eq!(samples.get(name), Some(&value), "{}", name);
+		}
+	}
+	
+	#[test]
+	fn panic_led_strobes_through_trigger_or_falls_back_to_on() {
+		let dir = std::env::temp_dir().join(format!("nvme-led-panic-{}", process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let brightness = dir.join("brightness");
+		let trigger = dir.join("trigger");
+		let read = |p: &std::path::Path| std::fs::read_to_string(p).unwrap().trim().to_string();
+		let reset = |with_trigger: bool| {
+			std::fs::write(&brightness, "").unwrap();
+			let _ = std::fs::remove_file(&trigger);
+			if with_trigger {
+				std::fs::write(&trigger, "none").unwrap();
+			}
+		};
+		let cfg = |panic_led| Config { led_path: brightness.to_str().unwrap().to_string(), active_high: true, panic_led, ..Config::default() };
+		
+		// Strobe hands the LED to the kernel timer trigger
+		reset(true);
+		apply_panic_led(&cfg(PanicLed::Strobe)).unwrap();
+		assert_eq!(read(&trigger), "timer");
+		assert_eq!(read(&dir.join("delay_on")), PANIC_STROBE_MS.to_string());
+		assert_eq!(read(&dir.join("delay_off")), PANIC_STROBE_MS.to_string());
+		assert_eq!(read(&brightness), "");
+		
+		// On leaves the trigger alone and lights the LED solid
+		reset(true);
+		apply_panic_led(&cfg(PanicLed::On)).unwrap();
+		assert_eq!(read(&trigger), "none");
+		assert_eq!(read(&brightness), "1");
+		
+		// Strobe without a trigger file falls back to solid on
+		reset(false);
+		apply_panic_led(&cfg(PanicLed::Strobe)).unwrap();
+		assert!(!trigger.exists());
+		assert_eq!(read(&brightness), "1");
+		
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn blink_pct_tracks_poll_ms_unless_blink_ms_is_set() {
+		let load = |args: &[&str]| config_from_layers(args.iter().map(|a| a.to_string()), &HashMap::new()).unwrap();
+		
+		let mut cfg = load(&["--interval-ms", "200", "--blink-pct", "25"]);
+		assert_eq!(base_blink_ms(&cfg), 50);
+		assert_eq!(blink_duration(&cfg, Dir::Write), 50);
+		cfg.poll_ms = 400;
+		assert_eq!(base_blink_ms(&cfg), 100);
+		cfg.poll_ms = 2;
+		assert_eq!(base_blink_ms(&cfg), 1);
+		
+		// An explicit blink_ms wins whichever side of blink_pct it comes
+		for args in [
+			["--interval-ms", "200", "--blink-pct", "25", "--blink-ms", "30"],
+			["--interval-ms", "200", "--blink-ms", "30", "--blink-pct", "25"],
+		] {
+			let mut cfg = load(&args);
+			assert_eq!(base_blink_ms(&cfg), 30);
+			cfg.poll_ms = 400;
+			assert_eq!(base_blink_ms(&cfg), 30);
+		}
+	}
+	
+	#[test]
+	fn probe_io_checks_the_stat_file_follows_reads() {
+		// A fake sysfs node named after a real readable device: the probe
+		// reads /dev/zero, but the static stat file can't follow
+		let root = std::env::temp_dir().join(format!("nvme-led-probe-{}", process::id()));
+		let stat = root.join("block/zero/stat");
+		std::fs::create_dir_all(stat.parent().unwrap()).unwrap();
+		std::fs::write(&stat, "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		let cfg = |path: &std::path::Path| Config { nvme_path: path.to_str().unwrap().to_string(), ..Config::default() };
+		assert!(!probe_io(&cfg(&stat)).unwrap());
+		
+		// No /dev node behind the stat file's name
+		let missing = root.join("block/nvme-led-no-such-dev/stat");
+		std::fs::create_dir_all(missing.parent().unwrap()).unwrap();
+		std::fs::copy(&stat, &missing).unwrap();
+		let e = probe_io(&cfg(&missing)).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::NotFound);
+		assert!(e.to_string().contains("/dev/nvme-led-no-such-dev"), "{}", e);
+		
+		// No stat file at all fails before any device is opened
+		assert!(probe_io(&cfg(&root.join("block/absent/stat"))).is_err());
+		
+		// Reads that reach the device move its read counters: detected.
+		// Reads that don't (or only writes move) aren't
+		for (line, want) in [("1 0 16 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n", true), ("1 0 8 0 1 0 64 0 0 0 0 0 0 0 0 0 0\n", false)] {
+			let got = probe_io_with(&cfg(&stat), |nvme| {
+				assert_eq!(nvme.path, stat.to_str().unwrap());
+				std::fs::write(&stat, line)?;
+				Ok(("fake".to_string(), PROBE_READS as usize))
+			}).unwrap();
+			assert_eq!(got, want, "{}", line);
+			std::fs::write(&stat, "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		}
+		
+		// A failed read is the probe's error
+		let e = probe_io_with(&cfg(&stat), |_| Err(io::Error::from(io::ErrorKind::PermissionDenied))).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+		
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+	
+	#[test]
+	fn min_off_ms_holds_back_activity_right_after_a_blink() {
+		let path = std::env::temp_dir().join(format!("nvme-led-min-off-{}", process::id()));
+		let line = |sectors: u64| format!("1 0 {} 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n", sectors);
+		std::fs::write(&path, line(8)).unwrap();
+		let cfg = Config { nvme_path: path.to_string_lossy().into_owned(), dry_run: true, poll_ms: 20, blink_ms: 10, min_off_ms: 60_000, ..Config::default() };
+		let mut engine = Engine::new(cfg).unwrap();
+		let poll = |e: &mut Engine| {
+			let polls = e.stats.total.polls;
+			while e.stats.total.polls == polls {
+				assert!(e.step(1000).unwrap());
+			}
+		};
+		
+		// Blink, then let the off-timer end it
+		poll(&mut engine);
+		assert_eq!((engine.stats.total.blinks, engine.led_on), (1, true));
+		while engine.led_on {
+			assert!(engine.step(1000).unwrap());
+		}
+		let dark_at = engine.off_at.unwrap();
+		
+		// Activity while freshly dark: decide sees a short dark_for and the
+		// LED stays off
+		let state = BlinkState { in_grace: false, idle_for: None, pattern_playing: false, dark_for: Some(dark_at.elapsed()) };
+		assert_eq!(decide(&engine.cfg, Some(Dir::Read), state), Action::Leave);
+		std::fs::write(&path, line(16)).unwrap();
+		poll(&mut engine);
+		assert_eq!((engine.stats.total.blinks, engine.led_on), (1, false));
+		
+		// Once min_off_ms has passed the next activity blinks again
+		engine.off_at = dark_at.checked_sub(Duration::from_millis(60_000));
+		std::fs::write(&path, line(24)).unwrap();
+		poll(&mut engine);
+		assert_eq!((engine.stats.total.blinks, engine.led_on), (2, true));
+		std::fs::remove_file(&path).unwrap();
+	}
+	
+	#[test]
+	fn control_socket_round_trip() {
+		use std::io::{Read, Write};
+		let dir = std::env::temp_dir().join(format!("nvme-led-control-{}", process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("stat"), "1 0 8 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+		let sock = dir.join("sock").to_string_lossy().into_owned();
+		let cfg = Config {
+			nvme_path: dir.join("stat").to_string_lossy().into_owned(),
+			dry_run: true,
+			control_socket: Some(sock.clone()),
+			..Config::default()
+		};
+		let mut engine = Engine::new(cfg).unwrap();
+		
+		// One connection, every command on its own line; the engine
+		// answers them all when the connection is accepted
+		let mut client = std::os::unix::net::UnixStream::connect(&sock).unwrap();
+		client.write_all(b"led on\nstatus\nled off\nstatus\nled auto\nset blink_ms 0\nset read_blink_ms 0ms\nset min_off_ms 0\nset blink_ms 1.5s\nset min_off_ms fast\nset edge_only maybe\nset interval_ms 5\n").unwrap();
+		client.shutdown(std::net::Shutdown::Write).unwrap();
+		while engine.cfg.blink_ms != 1500 {
+			assert!(engine.step(1000).unwrap());
+		}
+		let mut replies = String::new();
+		client.read_to_string(&mut replies).unwrap();
+		let replies: Vec<&str> = replies.lines().collect();
+		assert_eq!(replies.len(), 12, "{:?}", replies);
+		assert_eq!(replies[0], "ok");
+		assert!(replies[1].starts_with("state=running led=on override=on "), "{}", replies[1]);
+		assert_eq!(replies[2], "ok");
+		assert!(replies[3].starts_with("state=running led=off override=off "), "{}", replies[3]);
+		assert_eq!(replies[4], "ok");
+		assert_eq!(replies[5], "error: invalid value for blink_ms: 0");
+		assert_eq!(replies[6], "error: invalid value for read_blink_ms: 0ms");
+		assert_eq!(replies[7], "ok min_off_ms=0");
+		assert_eq!(replies[8], "ok blink_ms=1.5s");
+		assert_eq!(replies[9], "error: invalid value for min_off_ms: fast");
+		assert_eq!(replies[10], "error: invalid value for edge_only: maybe");
+		assert!(replies[11].starts_with("error: interval_ms can't be set at runtime"), "{}", replies[11]);
+		assert_eq!((engine.led_override, engine.cfg.read_blink_ms, engine.cfg.min_off_ms, engine.cfg.edge_only), (None, None, 0, false));
+		
+		drop(engine);
+		assert!(!std::path::Path::new(&sock).exists());
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn pin_to_cpu_rejects_unavailable_cpus() {
+		for cpu in [100_000, libc::CPU_SETSIZE as usize] {
+			let e = pin_to_cpu(cpu).unwrap_err();
+			assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+			assert!(e.to_string().starts_with(&format!("--cpu {}: not one of the CPUs available (", cpu)), "{}", e);
+		}
+	}
+	
+	#[test]
+	fn time_remaining_counts_down_an_armed_one_shot() {
+		let t = Tfd::oneshot().unwrap();
+		assert_eq!(t.time_remaining().unwrap(), None);
+		t.arm_after_ms(10_000).unwrap();
+		let first = t.time_remaining().unwrap().unwrap();
+		assert!(first <= Duration::from_millis(10_000) && first > Duration::from_millis(9_000), "{:?}", first);
+		std::thread::sleep(Duration::from_millis(20));
+		let second = t.time_remaining().unwrap().unwrap();
+		assert!(second < first, "{:?} !< {:?}", second, first);
+		t.arm_after_ms(0).unwrap();
+		assert_eq!(t.time_remaining().unwrap(), None);
+		
+		// The status line reports both timers and the last LED change
+		let poll = Tfd::periodic(1000, false).unwrap();
+		let off = Tfd::oneshot().unwrap();
+		let status = timer_status(&poll, &off, None, None);
+		assert!(status.starts_with("poll_timer=armed poll_left_ms="), "{}", status);
+		assert!(status.ends_with(" off_timer=disarmed off_left_ms=0 led_changed_at=never"), "{}", status);
+		let at: u128 = timer_status(&poll, &off, Some(Instant::now()), None).rsplit('=').next().unwrap().parse().unwrap();
+		let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
+		assert!(now - at < 1000, "{} vs {}", at, now);
+	}
+	
+	#[test]
+	fn zero_metrics_interval_is_clamped_from_every_layer() {
+		let map = HashMap::from([("metrics_interval_ms".to_string(), "0".to_string())]);
+		let mut cfg = Config::default();
+		apply_config_map(&mut cfg, &map);
+		assert_eq!(cfg.metrics_interval_ms, 1);
+		assert_eq!(config_from_layers(std::iter::empty(), &map).unwrap().metrics_interval_ms, 1);
+		let cli = ["--metrics-interval-ms", "0"].map(String::from);
+		assert_eq!(config_from_layers(cli.into_iter(), &HashMap::new()).unwrap().metrics_interval_ms, 1);
+	}
+	
+	#[test]
+	fn byte_thresholds_are_exact_and_sectors_mode_only() {
+		let load = |args: &[&str]| config_from_layers(args.iter().map(|a| a.to_string()), &HashMap::new());
+		
+		// A delta exceeds N bytes once it exceeds N / 512 whole sectors
+		for (bytes, sectors) in [(0, 0), (511, 0), (512, 1), (4000, 7), (4096, 8)] {
+			let cfg = load(&["--min-bytes", &bytes.to_string()]).unwrap();
+			assert_eq!((cfg.min_read, cfg.min_write), (sectors, sectors), "{}", bytes);
+			assert!((sectors + 1) * SECTOR_BYTES > bytes && sectors * SECTOR_BYTES <= bytes, "{}", bytes);
+		}
+		
+		// Bytes mean nothing to the other modes; min_sectors does
+		let Err(e) = load(&["--min-read-bytes", "4KiB", "--nvme-mode", "io"]) else { panic!("bytes accepted in io mode") };
+		assert!(e.contains("nvme_mode=sectors"), "{}", e);
+		let cfg = load(&["--min-bytes", "4KiB", "--min-sectors", "3", "--nvme-mode", "io"]).unwrap();
+		assert_eq!((cfg.min_read, cfg.min_write, cfg.min_in_bytes), (3, 3, false));
+	}
 }